@@ -0,0 +1,24 @@
+#!/bin/sh
+# Replays session.jsonl against `confoosion lsp` and compares the replies with expected.jsonl.
+#
+# Both files hold one JSON-RPC message per line, with ROOT standing for the absolute path of
+# the notes directory next to this script. Build the core first, or point CONFOOSION at the
+# binary. Pass --update to write the replies to expected.jsonl instead of comparing them.
+set -eu
+here=$(cd "$(dirname "$0")" && pwd)
+binary=${CONFOOSION:-$here/../../target/debug/confoosion}
+root=$here/notes
+
+replies=$(
+    sed "s|ROOT|$root|g" "$here/session.jsonl" | while IFS= read -r line; do
+        printf 'Content-Length: %d\r\n\r\n%s' "$(printf '%s' "$line" | wc -c)" "$line"
+    done | "$binary" lsp | tr -d '\r' |
+        awk '{ gsub(/Content-Length: [0-9]+/, "\n"); print }' | grep -v '^$' |
+        sed "s|$root|ROOT|g"
+)
+
+if [ "${1:-}" = "--update" ]; then
+    printf '%s\n' "$replies" > "$here/expected.jsonl"
+else
+    printf '%s\n' "$replies" | diff -u "$here/expected.jsonl" - && echo "All replies match"
+fi
@@ -0,0 +1,22 @@
+#!/bin/sh
+# Replays session.jsonl against `confoosion rpc` and compares the replies with expected.jsonl.
+#
+# Both files hold one JSON-RPC message per line. The session renames, splits and merges notes,
+# so it runs on a copy of the notes directory next to this script, and ROOT stands for the
+# absolute path of that copy. Build the core first, or point CONFOOSION at the binary. Pass
+# --update to write the replies to expected.jsonl instead of comparing them.
+set -eu
+here=$(cd "$(dirname "$0")" && pwd)
+binary=${CONFOOSION:-$here/../../target/debug/confoosion}
+copy=$(mktemp -d)
+trap 'rm -rf "$copy"' EXIT
+cp -R "$here/notes/." "$copy"
+root=$(cd "$copy" && pwd -P)
+
+replies=$("$binary" rpc "$root" < "$here/session.jsonl" | sed "s|$root|ROOT|g")
+
+if [ "${1:-}" = "--update" ]; then
+    printf '%s\n' "$replies" > "$here/expected.jsonl"
+else
+    printf '%s\n' "$replies" | diff -u "$here/expected.jsonl" - && echo "All replies match"
+fi
@@ -0,0 +1,187 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Tells apart the temporary files of writes that happen at the same time in one process.
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Replaces the contents of `path` without ever leaving a half-written file behind.
+///
+/// The data is written to a temporary sibling, flushed to disk, and renamed over the
+/// original, whose permissions it keeps. The parent directory is synced afterwards so the
+/// rename itself survives a crash.
+pub fn write<P, C>(path: P, contents: C) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    C: AsRef<[u8]>,
+{
+    let path = path.as_ref();
+    let temporary = temporary_sibling(path)?;
+    let written = write_synced(&temporary, contents.as_ref())
+        .and_then(|()| keep_permissions(path, &temporary));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+    if let Err(e) = fs::rename(&temporary, path) {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+    sync_parent(path)
+}
+
+/// Creates `path` with `contents` unless it exists already, without ever leaving a
+/// half-written file behind. Returns whether it was created.
+///
+/// The data is written to a temporary sibling and flushed like in [`write`], but then hard
+/// linked to `path`, which fails rather than replacing a file that was created meanwhile.
+pub fn create<P, C>(path: P, contents: C) -> io::Result<bool>
+where
+    P: AsRef<Path>,
+    C: AsRef<[u8]>,
+{
+    let path = path.as_ref();
+    let temporary = temporary_sibling(path)?;
+    let linked =
+        write_synced(&temporary, contents.as_ref()).and_then(|()| fs::hard_link(&temporary, path));
+    let _ = fs::remove_file(&temporary);
+    match linked {
+        Ok(()) => sync_parent(path).map(|()| true),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes `contents` to a new file at `path` and flushes it to disk before returning.
+pub fn write_synced<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Gives `replacement` the permissions of `original`, if that exists, before it is renamed over
+/// it.
+pub fn keep_permissions(original: &Path, replacement: &Path) -> io::Result<()> {
+    match fs::metadata(original) {
+        Ok(metadata) => fs::set_permissions(replacement, metadata.permissions()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<()> {
+    fs::rename(&from, &to)?;
+    sync_parent(&from)?;
+    sync_parent(&to)
+}
+
+pub fn remove<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    fs::remove_file(&path)?;
+    sync_parent(&path)
+}
+
+/// Whether `a` and `b` name the same file, like two spellings of a name on a filesystem that
+/// ignores case. False when either does not exist.
+#[cfg(unix)]
+pub fn same_file<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_file<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+pub fn sync_parent<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    match path.as_ref().parent() {
+        Some(parent) if parent.as_os_str().is_empty() => sync_directory("."),
+        Some(parent) => sync_directory(parent),
+        None => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+pub fn sync_directory<P: AsRef<Path>>(directory: P) -> io::Result<()> {
+    File::open(directory)?.sync_all()
+}
+
+// Directories cannot be opened as files on every platform; there the rename is as durable as
+// the filesystem makes it.
+#[cfg(not(unix))]
+pub fn sync_directory<P: AsRef<Path>>(_directory: P) -> io::Result<()> {
+    Ok(())
+}
+
+fn temporary_sibling(path: &Path) -> io::Result<PathBuf> {
+    let name = match path.file_name() {
+        Some(x) => x,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} does not name a file", path.display()),
+            ))
+        }
+    };
+    let mut temporary = OsString::from(".");
+    temporary.push(name);
+    temporary.push(format!(
+        ".{}.{}.tmp",
+        process::id(),
+        TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    Ok(path.with_file_name(temporary))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn writes_from_several_threads_do_not_collide() {
+        let directory = std::env::temp_dir().join(format!("confoosion-atomic-{}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let note = directory.join("a.md");
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let note = note.clone();
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        write(&note, format!("writer {writer}")).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert!(fs::read_to_string(&note).unwrap().starts_with("writer "));
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn create_never_replaces_a_file() {
+        let directory = std::env::temp_dir().join(format!("confoosion-create-{}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let note = directory.join("a.md");
+        assert!(create(&note, "first").unwrap());
+        assert!(!create(&note, "second").unwrap());
+        assert_eq!(fs::read_to_string(&note).unwrap(), "first");
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+    }
+}
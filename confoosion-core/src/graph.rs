@@ -0,0 +1,387 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use confoosion_markdown_parser::escape_html;
+
+use crate::{json::Json, site::strip_tags, vault::Vault};
+
+/// Which part of the note graph [`Graph::from_vault`] takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphOptions {
+    /// Only follow `parent` relations, not wiki-links.
+    pub parents_only: bool,
+    /// Only take the notes at most this many edges away from this note, in either direction.
+    pub around: Option<(PathBuf, usize)>,
+    /// Keep the notes without any edges.
+    pub orphans: bool,
+}
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        Self {
+            parents_only: false,
+            around: None,
+            orphans: true,
+        }
+    }
+}
+
+/// The notes of a vault and the links between them, ready to be written out for other tools.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    pub note: PathBuf,
+    /// The file name without `.md`, which identifies the node in every format.
+    pub name: String,
+    /// The title as plain text, or the name when the note has none.
+    pub title: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GraphEdge {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    /// `from` has a wiki-link to `to`, written in it or produced by one of its templates.
+    Link,
+    /// `to` is named as a parent in the front matter of `from`.
+    Parent,
+}
+
+impl EdgeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Link => "link",
+            EdgeKind::Parent => "parent",
+        }
+    }
+}
+
+impl Graph {
+    /// Takes the graph of the notes in `vault`, ordered by file name. Notes never link to
+    /// themselves in it. Links are those of the rendered notes, see [`NoteEntry::links`].
+    ///
+    /// [`NoteEntry::links`]: crate::vault::NoteEntry::links
+    pub fn from_vault(vault: &Vault, options: &GraphOptions) -> Self {
+        let mut nodes: BTreeSet<PathBuf> = BTreeSet::new();
+        let mut edges: BTreeSet<GraphEdge> = BTreeSet::new();
+        for (note, entry) in vault.entries() {
+            nodes.insert(note.to_path_buf());
+            let links: &[PathBuf] = match options.parents_only {
+                true => &[],
+                false => &entry.links,
+            };
+            let targets = links
+                .iter()
+                .map(|to| (to, EdgeKind::Link))
+                .chain(entry.parents.iter().map(|to| (to, EdgeKind::Parent)));
+            for (to, kind) in targets {
+                if to != note {
+                    edges.insert(GraphEdge {
+                        from: note.to_path_buf(),
+                        to: to.clone(),
+                        kind,
+                    });
+                }
+            }
+        }
+
+        if let Some((center, hops)) = &options.around {
+            nodes = neighbourhood(&edges, center, *hops);
+            edges.retain(|edge| nodes.contains(&edge.from) && nodes.contains(&edge.to));
+        }
+        if !options.orphans {
+            let connected: BTreeSet<&PathBuf> = edges
+                .iter()
+                .flat_map(|edge| [&edge.from, &edge.to])
+                .collect();
+            nodes.retain(|note| connected.contains(note));
+        }
+
+        let nodes = nodes
+            .into_iter()
+            .map(|note| {
+                let entry = vault.entry(&note);
+                let name = note_name(&note);
+                let title = entry
+                    .and_then(|entry| entry.title.as_deref())
+                    .map(|title| strip_tags(title).trim().to_string())
+                    .unwrap_or_else(|| name.clone());
+                GraphNode {
+                    tags: entry.map(|entry| entry.tags.clone()).unwrap_or_default(),
+                    note,
+                    name,
+                    title,
+                }
+            })
+            .collect();
+        Self {
+            nodes,
+            edges: edges.into_iter().collect(),
+        }
+    }
+
+    /// Writes the graph in the Graphviz DOT language, with parent edges drawn bold.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph notes {\n");
+        for node in &self.nodes {
+            let _ = writeln!(
+                out,
+                "  {} [label={}];",
+                dot_string(&node.name),
+                dot_string(&node.title)
+            );
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Link => "",
+                EdgeKind::Parent => " [style=bold]",
+            };
+            let _ = writeln!(
+                out,
+                "  {} -> {}{style};",
+                dot_string(&note_name(&edge.from)),
+                dot_string(&note_name(&edge.to))
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Writes the graph as GraphML, with the title and tags of each note and the kind of each
+    /// edge as data.
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"title\" for=\"node\" attr.name=\"title\" attr.type=\"string\"/>\n",
+            "  <key id=\"tags\" for=\"node\" attr.name=\"tags\" attr.type=\"string\"/>\n",
+            "  <key id=\"kind\" for=\"edge\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+            "  <graph id=\"notes\" edgedefault=\"directed\">\n",
+        ));
+        for node in &self.nodes {
+            let _ = writeln!(out, "    <node id=\"{}\">", escape_html(&node.name));
+            let _ = writeln!(
+                out,
+                "      <data key=\"title\">{}</data>",
+                escape_html(&node.title)
+            );
+            if !node.tags.is_empty() {
+                let _ = writeln!(
+                    out,
+                    "      <data key=\"tags\">{}</data>",
+                    escape_html(&node.tags.join(" "))
+                );
+            }
+            out.push_str("    </node>\n");
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                out,
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"kind\">{}</data></edge>",
+                escape_html(&note_name(&edge.from)),
+                escape_html(&note_name(&edge.to)),
+                edge.kind.name()
+            );
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    /// Writes the graph as `{"nodes": [{"id", "title", "tags"}], "edges": [{"source",
+    /// "target", "kind"}]}`.
+    pub fn to_json(&self) -> Json {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                Json::object([
+                    ("id", Json::string(node.name.as_str())),
+                    ("title", Json::string(node.title.as_str())),
+                    ("tags", node.tags.clone().into()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let edges = self
+            .edges
+            .iter()
+            .map(|edge| {
+                Json::object([
+                    ("source", Json::string(note_name(&edge.from))),
+                    ("target", Json::string(note_name(&edge.to))),
+                    ("kind", Json::string(edge.kind.name())),
+                ])
+            })
+            .collect::<Vec<_>>();
+        Json::object([("nodes", Json::from(nodes)), ("edges", Json::from(edges))])
+    }
+}
+
+/// The notes at most `hops` edges away from `center`, following edges both ways.
+fn neighbourhood(edges: &BTreeSet<GraphEdge>, center: &Path, hops: usize) -> BTreeSet<PathBuf> {
+    let mut neighbours: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+    for edge in edges {
+        neighbours.entry(&edge.from).or_default().push(&edge.to);
+        neighbours.entry(&edge.to).or_default().push(&edge.from);
+    }
+    let mut found: BTreeSet<PathBuf> = BTreeSet::from([center.to_path_buf()]);
+    let mut frontier = vec![center];
+    for _ in 0..hops {
+        let mut next = Vec::new();
+        for note in frontier {
+            for &neighbour in neighbours.get(note).into_iter().flatten() {
+                if found.insert(neighbour.to_path_buf()) {
+                    next.push(neighbour);
+                }
+            }
+        }
+        frontier = next;
+    }
+    found
+}
+
+fn note_name(note: &Path) -> String {
+    note.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn dot_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(character);
+            }
+            '\n' => out.push_str("\\n"),
+            _ => out.push(character),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use confoosion_markdown_parser::template::TemplateMap;
+
+    use super::*;
+
+    /// Takes the graph of a vault of its own for each test, with edges written as
+    /// `from -> to kind`.
+    fn graph_of(name: &str, options: &GraphOptions) -> (Graph, Vec<String>) {
+        let directory =
+            std::env::temp_dir().join(format!("confoosion-graph-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for (note, source) in [
+            ("a", "# Alpha\n\nSee [[b]] and [[a]]."),
+            ("b", "---\nparent: c\n---\n# Bravo"),
+            ("c", "# Charlie"),
+            ("d", "# Delta\n\n{{see|a}}"),
+            ("e", "# Tom & \"Jerry\"\n\nAlone."),
+            ("Template:see", "See [[{{{1}}}]]."),
+        ] {
+            fs::write(directory.join(format!("{note}.md")), source).unwrap();
+        }
+        let vault = Vault::scan(&directory, &TemplateMap::with_builtins()).unwrap();
+        let mut options = options.clone();
+        if let Some((center, _)) = &mut options.around {
+            *center = directory.join(&center);
+        }
+        let graph = Graph::from_vault(&vault, &options);
+        let edges = graph
+            .edges
+            .iter()
+            .map(|edge| {
+                let (from, to) = (note_name(&edge.from), note_name(&edge.to));
+                format!("{from} -> {to} {}", edge.kind.name())
+            })
+            .collect();
+        (graph, edges)
+    }
+
+    fn names(graph: &Graph) -> Vec<&str> {
+        graph.nodes.iter().map(|node| node.name.as_str()).collect()
+    }
+
+    #[test]
+    fn links_written_by_templates_are_edges() {
+        let (graph, edges) = graph_of("links", &GraphOptions::default());
+        assert_eq!(names(&graph), ["Template:see", "a", "b", "c", "d", "e"]);
+        assert_eq!(edges, ["a -> b link", "b -> c parent", "d -> a link"]);
+    }
+
+    #[test]
+    fn around_takes_the_notes_some_hops_away() {
+        let options = GraphOptions {
+            around: Some((PathBuf::from("a.md"), 1)),
+            ..GraphOptions::default()
+        };
+        let (graph, edges) = graph_of("around", &options);
+        assert_eq!(names(&graph), ["a", "b", "d"]);
+        assert_eq!(edges, ["a -> b link", "d -> a link"]);
+
+        let options = GraphOptions {
+            around: Some((PathBuf::from("d.md"), 2)),
+            ..GraphOptions::default()
+        };
+        assert_eq!(names(&graph_of("around-two", &options).0), ["a", "b", "d"]);
+
+        let options = GraphOptions {
+            around: Some((PathBuf::from("e.md"), 3)),
+            ..GraphOptions::default()
+        };
+        assert_eq!(names(&graph_of("around-alone", &options).0), ["e"]);
+    }
+
+    #[test]
+    fn orphans_can_be_left_out() {
+        let options = GraphOptions {
+            orphans: false,
+            ..GraphOptions::default()
+        };
+        let (graph, _) = graph_of("orphans", &options);
+        assert_eq!(names(&graph), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn parents_only_leaves_out_the_links() {
+        let options = GraphOptions {
+            parents_only: true,
+            orphans: false,
+            ..GraphOptions::default()
+        };
+        let (graph, edges) = graph_of("parents", &options);
+        assert_eq!(names(&graph), ["b", "c"]);
+        assert_eq!(edges, ["b -> c parent"]);
+    }
+
+    #[test]
+    fn quotes_and_ampersands_are_escaped() {
+        let (graph, _) = graph_of("escape", &GraphOptions::default());
+        let dot = graph.to_dot();
+        assert!(dot.contains("  \"e\" [label=\"Tom & \\\"Jerry\\\"\"];\n"));
+        assert!(dot.contains("  \"b\" -> \"c\" [style=bold];\n"));
+        let graphml = graph.to_graphml();
+        assert!(graphml.contains("<data key=\"title\">Tom &amp; &quot;Jerry&quot;</data>"));
+        assert!(graphml.contains("<edge source=\"a\" target=\"b\">"));
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use confoosion_markdown_parser::date::{Date, DAILY_NOTE_PATTERN};
+
+use crate::{atomic, refactor};
+
+/// How daily notes are named and what a new one starts with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalOptions {
+    /// The file name of a day's note, for [`Date::format`].
+    pub pattern: String,
+    /// The text of a new note, in which `$date$` (`YYYY-MM-DD`), `$name$`, `$title$` (like
+    /// `Sunday, 18 October 2026`) and `$pattern$` are filled in. `None` starts with the title
+    /// and links to the days before and after.
+    pub template: Option<String>,
+}
+
+impl Default for JournalOptions {
+    fn default() -> Self {
+        Self {
+            pattern: DAILY_NOTE_PATTERN.to_string(),
+            template: None,
+        }
+    }
+}
+
+/// The daily note for `date`, which is created from the template if it does not exist yet.
+/// Returns the note and whether it was created.
+pub fn open(directory: &Path, date: Date, options: &JournalOptions) -> io::Result<(PathBuf, bool)> {
+    let note = note_path(directory, date, &options.pattern)?;
+    let template = match &options.template {
+        Some(x) => x.clone(),
+        None => default_template(&options.pattern),
+    };
+    let text = template
+        .replace("$date$", &date.to_string())
+        .replace("$name$", &date.format(&options.pattern))
+        .replace("$title$", &date.format("%A, %e %B %Y"))
+        .replace("$pattern$", &options.pattern);
+    // A note made meanwhile is kept rather than replaced.
+    let created = atomic::create(&note, text)?;
+    Ok((note, created))
+}
+
+/// Where the daily note for `date` is, whether it exists or not.
+pub fn note_path(directory: &Path, date: Date, pattern: &str) -> io::Result<PathBuf> {
+    let name = date.format(pattern);
+    let name = refactor::note_name(&name)?;
+    if Date::parse_with(name, pattern) != Some(date) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{pattern:?} needs a year, a month and a day to name daily notes"),
+        ));
+    }
+    Ok(directory.join(format!("{name}.md")))
+}
+
+fn default_template(pattern: &str) -> String {
+    let navigation = match pattern == DAILY_NOTE_PATTERN {
+        true => "{{journal_nav}}".to_string(),
+        false => format!("{{{{journal_nav|pattern={pattern}}}}}"),
+    };
+    format!("# $title$\n\n{navigation}\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn an_existing_note_is_kept() {
+        let directory =
+            std::env::temp_dir().join(format!("confoosion-journal-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let date = Date::new(2024, 1, 31).unwrap();
+        let options = JournalOptions {
+            template: Some("# $title$\n".to_string()),
+            ..JournalOptions::default()
+        };
+
+        let (note, created) = open(&directory, date, &options).unwrap();
+        assert!(created);
+        assert_eq!(note, directory.join("2024-01-31.md"));
+        assert_eq!(
+            fs::read_to_string(&note).unwrap(),
+            "# Wednesday, 31 January 2024\n"
+        );
+
+        fs::write(&note, "written meanwhile\n").unwrap();
+        assert_eq!(
+            open(&directory, date, &options).unwrap(),
+            (note.clone(), false)
+        );
+        assert_eq!(fs::read_to_string(&note).unwrap(), "written meanwhile\n");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+use std::fmt::{self, Display, Write};
+
+/// A JSON value, for the protocols the core speaks. Objects keep their keys in the order they
+/// were written, so output is stable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from `key, value` pairs.
+    pub fn object<K: Into<String>, I: IntoIterator<Item = (K, Json)>>(entries: I) -> Self {
+        Json::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.into(), value))
+                .collect(),
+        )
+    }
+
+    pub fn string<S: Into<String>>(text: S) -> Self {
+        Json::String(text.into())
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, index: 0 };
+        parser.whitespace();
+        let value = parser.value(0)?;
+        parser.whitespace();
+        match parser.index == text.len() {
+            true => Ok(value),
+            false => Err(parser.error("Unexpected text after the value")),
+        }
+    }
+
+    /// The value under `key`, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(x, _)| x == key).map(|(_, x)| x),
+            _ => None,
+        }
+    }
+
+    /// Follows a path of object keys, like `["textDocument", "uri"]`.
+    pub fn at(&self, path: &[&str]) -> Option<&Json> {
+        path.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    /// Sets `key` on an object, replacing an earlier value. Does nothing on other values.
+    pub fn insert<K: Into<String>>(&mut self, key: K, value: Json) {
+        if let Json::Object(entries) = self {
+            let key = key.into();
+            match entries.iter_mut().find(|(x, _)| *x == key) {
+                Some(entry) => entry.1 = value,
+                None => entries.push((key, value)),
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /// The number as an unsigned integer, if it is one.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(x) if *x >= 0.0 && x.fract() == 0.0 && *x <= u64::MAX as f64 => {
+                Some(*x as u64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Json::Null)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(x) => x.into(),
+            None => Json::Null,
+        }
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(value: Vec<T>) -> Self {
+        Json::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Writes the value on one line, without spaces, which is what line-delimited protocols need.
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(x) => write!(f, "{x}"),
+            Json::Number(x) if !x.is_finite() => f.write_str("null"),
+            Json::Number(x) if x.fract() == 0.0 && x.abs() < 1e15 => write!(f, "{}", *x as i64),
+            Json::Number(x) => write!(f, "{x}"),
+            Json::String(x) => write_string(f, x),
+            Json::Array(values) => {
+                f.write_char('[')?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(entries) => {
+                f.write_char('{')?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for character in text.chars() {
+        match character {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{2028}' => f.write_str("\\u2028")?,
+            '\u{2029}' => f.write_str("\\u2029")?,
+            x if (x as u32) < 0x20 => write!(f, "\\u{:04x}", x as u32)?,
+            x => f.write_char(x)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Deeper nesting than this is refused rather than risking the stack.
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    text: &'a str,
+    index: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> String {
+        format!("{message} at byte {}", self.index)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.index).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.index += 1;
+        }
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        match self.text[self.index..].starts_with(word) {
+            true => {
+                self.index += word.len();
+                Ok(())
+            }
+            false => Err(self.error(&format!("Expected {word}"))),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("Nested too deeply"));
+        }
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.index += 1;
+                let mut values = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.index += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    self.whitespace();
+                    values.push(self.value(depth + 1)?);
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.index += 1,
+                        Some(b']') => {
+                            self.index += 1;
+                            return Ok(Json::Array(values));
+                        }
+                        _ => return Err(self.error("Expected , or ]")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.index += 1;
+                let mut entries = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.index += 1;
+                    return Ok(Json::Object(entries));
+                }
+                loop {
+                    self.whitespace();
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("Expected a key"));
+                    }
+                    let key = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    self.whitespace();
+                    entries.push((key, self.value(depth + 1)?));
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.index += 1,
+                        Some(b'}') => {
+                            self.index += 1;
+                            return Ok(Json::Object(entries));
+                        }
+                        _ => return Err(self.error("Expected , or }")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.index;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.index += 1;
+        }
+        match self.text[start..self.index].parse() {
+            Ok(x) => Ok(Json::Number(x)),
+            Err(_) => Err(self.error("Malformed number")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.index += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.text[self.index..];
+            let character = match rest.chars().next() {
+                Some(x) => x,
+                None => return Err(self.error("Unterminated string")),
+            };
+            self.index += character.len_utf8();
+            match character {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("Unterminated string"))?;
+                    self.index += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => out.push(self.unicode_escape()?),
+                        _ => return Err(self.error("Unknown escape")),
+                    }
+                }
+                x if (x as u32) < 0x20 => return Err(self.error("Control character in string")),
+                x => out.push(x),
+            }
+        }
+    }
+
+    /// Reads the hex digits of a `\u` escape, and the second half of a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let first = self.hex()?;
+        if !(0xD800..0xDC00).contains(&first) {
+            return Ok(char::from_u32(first).unwrap_or('\u{FFFD}'));
+        }
+        if !self.text[self.index..].starts_with("\\u") {
+            return Ok('\u{FFFD}');
+        }
+        self.index += 2;
+        let second = self.hex()?;
+        if !(0xDC00..0xE000).contains(&second) {
+            return Ok('\u{FFFD}');
+        }
+        let code = 0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00);
+        Ok(char::from_u32(code).unwrap_or('\u{FFFD}'))
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.index..self.index + 4)
+            .ok_or_else(|| self.error("Short \\u escape"))?;
+        let code =
+            u32::from_str_radix(digits, 16).map_err(|_| self.error("Malformed \\u escape"))?;
+        self.index += 4;
+        Ok(code)
+    }
+}
@@ -0,0 +1,13 @@
+pub mod atomic;
+pub mod graph;
+pub mod journal;
+pub mod json;
+pub mod lsp;
+pub mod refactor;
+pub mod rpc;
+pub mod search;
+pub mod serve;
+pub mod site;
+pub mod transaction;
+pub mod vault;
+pub mod watch;
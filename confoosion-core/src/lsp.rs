@@ -0,0 +1,908 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, BufRead, Write},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use confoosion_markdown_parser::{
+    markdown_to_html,
+    resolver::{notes_in, Resolution},
+    scan::WikiLinkOccurrence,
+    template::TemplateMap,
+    vault::VaultQuery,
+};
+
+use crate::{
+    atomic,
+    json::Json,
+    refactor::{links_in, note_name, plain_parents, target_range},
+    site::percent_decode,
+    vault::{SharedVault, Vault},
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+const REQUEST_FAILED: i64 = -32803;
+
+const SEVERITY_ERROR: usize = 1;
+const SEVERITY_WARNING: usize = 2;
+
+const KIND_FUNCTION: usize = 3;
+const KIND_FILE: usize = 17;
+const KIND_REFERENCE: usize = 18;
+
+/// The largest message [`read_message`] accepts, so that a bad Content-Length cannot make the
+/// server allocate without bound.
+const MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+
+/// A Language Server Protocol server for notes, so that general-purpose editors can check
+/// notes, complete and follow wiki-links, list backlinks, and rename notes.
+///
+/// The server works on the notes in one directory: the directory of the note that was opened
+/// last, or the workspace root until a note is opened. Messages go in and out of
+/// [`LanguageServer::handle`], which does no IO of its own, and [`run`] connects it to a
+/// Content-Length framed stream such as standard input and output.
+pub struct LanguageServer {
+    templates: TemplateMap,
+    vault: SharedVault,
+    directory: Option<PathBuf>,
+    /// The open notes, with the text the editor has, which may not be saved yet.
+    documents: BTreeMap<PathBuf, String>,
+    initialized: bool,
+    shutdown: bool,
+    exit: bool,
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        Self {
+            templates: TemplateMap::with_builtins(),
+            vault: SharedVault::default(),
+            directory: None,
+            documents: BTreeMap::new(),
+            initialized: false,
+            shutdown: false,
+            exit: false,
+        }
+    }
+
+    /// Whether the client asked the server to exit.
+    pub fn exited(&self) -> bool {
+        self.exit
+    }
+
+    /// Whether the client asked the server to shut down, which makes an exit a clean one.
+    pub fn is_shut_down(&self) -> bool {
+        self.shutdown
+    }
+
+    /// Answers one message from the client, and returns the messages to send back: a response
+    /// to a request, and any notifications such as diagnostics.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str);
+        let id = message.get("id").cloned();
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let (method, id) = match (method, id) {
+            (Some(method), id) => (method, id),
+            // Responses to requests the server never sends.
+            (None, Some(_)) => return Vec::new(),
+            (None, None) => {
+                return vec![error_response(
+                    Json::Null,
+                    INVALID_REQUEST,
+                    "A message needs a method",
+                )]
+            }
+        };
+        let mut out = Vec::new();
+        match id {
+            Some(id) => {
+                let response = match self.request(method, &params) {
+                    Ok(result) => response(id, result),
+                    Err((code, message)) => error_response(id, code, &message),
+                };
+                out.push(response);
+            }
+            None => self.notification(method, &params, &mut out),
+        }
+        out
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i64, String)> {
+        if method == "initialize" {
+            return Ok(self.initialize(params));
+        }
+        if !self.initialized {
+            return Err((SERVER_NOT_INITIALIZED, "Not initialized yet".to_string()));
+        }
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "The server is shutting down".to_string()));
+        }
+        match method {
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/completion" => self.completion(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/rename" => self.rename(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {method}"))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json, out: &mut Vec<Json>) {
+        match method {
+            "exit" => self.exit = true,
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                let uri = document.and_then(|x| x.get("uri")).and_then(Json::as_str);
+                let text = document.and_then(|x| x.get("text")).and_then(Json::as_str);
+                if let (Some(note), Some(text)) = (uri.and_then(uri_to_path), text) {
+                    self.open_directory_of(&note);
+                    self.documents.insert(note.clone(), text.to_string());
+                    out.push(self.diagnostics(&note));
+                }
+            }
+            "textDocument/didChange" => {
+                let note = document_path(params);
+                // Only full synchronisation is offered, so the last change holds the whole text.
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|x| x.last())
+                    .and_then(|x| x.get("text"))
+                    .and_then(Json::as_str);
+                if let (Some(note), Some(text)) = (note, text) {
+                    self.documents.insert(note.clone(), text.to_string());
+                    out.push(self.diagnostics(&note));
+                }
+            }
+            "textDocument/didSave" => {
+                if let Some(note) = document_path(params) {
+                    self.reindex(&note);
+                    self.publish_all(out);
+                }
+            }
+            "textDocument/didClose" => {
+                if let Some(note) = document_path(params) {
+                    self.documents.remove(&note);
+                    out.push(notification(
+                        "textDocument/publishDiagnostics",
+                        Json::object([
+                            ("uri", Json::from(path_to_uri(&note))),
+                            ("diagnostics", Json::Array(Vec::new())),
+                        ]),
+                    ));
+                }
+            }
+            "workspace/didChangeWatchedFiles" => {
+                let changes = params
+                    .get("changes")
+                    .and_then(Json::as_array)
+                    .unwrap_or(&[]);
+                for change in changes {
+                    if let Some(note) = change
+                        .get("uri")
+                        .and_then(Json::as_str)
+                        .and_then(uri_to_path)
+                    {
+                        self.reindex(&note);
+                    }
+                }
+                self.publish_all(out);
+            }
+            // Everything else, like `initialized` or `$/cancelRequest`, needs no answer.
+            _ => (),
+        }
+    }
+
+    fn initialize(&mut self, params: &Json) -> Json {
+        self.initialized = true;
+        let root = params
+            .get("rootUri")
+            .and_then(Json::as_str)
+            .or_else(|| {
+                params
+                    .get("workspaceFolders")
+                    .and_then(Json::as_array)
+                    .and_then(|x| x.first())
+                    .and_then(|x| x.get("uri"))
+                    .and_then(Json::as_str)
+            })
+            .and_then(uri_to_path);
+        if let Some(root) = root {
+            self.open_directory(&root);
+        }
+        Json::object([
+            (
+                "capabilities",
+                Json::object([
+                    ("textDocumentSync", Json::from(1usize)),
+                    (
+                        "completionProvider",
+                        Json::object([("triggerCharacters", Json::from(vec!["[", "{"]))]),
+                    ),
+                    ("definitionProvider", Json::from(true)),
+                    ("referencesProvider", Json::from(true)),
+                    ("hoverProvider", Json::from(true)),
+                    ("renameProvider", Json::from(true)),
+                ]),
+            ),
+            (
+                "serverInfo",
+                Json::object([
+                    ("name", Json::from("confoosion")),
+                    ("version", Json::from(env!("CARGO_PKG_VERSION"))),
+                ]),
+            ),
+        ])
+    }
+
+    /// Indexes `directory` unless it is the one already indexed.
+    fn open_directory(&mut self, directory: &Path) {
+        if self.directory.as_deref() == Some(directory) {
+            return;
+        }
+        self.directory = Some(directory.to_path_buf());
+        self.reset_templates();
+    }
+
+    fn open_directory_of(&mut self, note: &Path) {
+        if let Some(directory) = note.parent() {
+            self.open_directory(directory);
+        }
+    }
+
+    /// Starts over with fresh templates, which is also how a render that panicked is undone.
+    fn reset_templates(&mut self) {
+        let mut templates = TemplateMap::with_builtins();
+        let vault = match &self.directory {
+            Some(directory) => Vault::scan(directory, &templates).unwrap_or_default(),
+            None => Vault::default(),
+        };
+        self.vault = SharedVault::new(vault);
+        templates.set_vault(Rc::new(self.vault.clone()));
+        self.templates = templates;
+    }
+
+    fn reindex(&mut self, note: &Path) {
+        if note.parent() != self.directory.as_deref() {
+            return;
+        }
+        match note.is_file() {
+            true => {
+                let _ = self.vault.update(note, &self.templates);
+            }
+            false => {
+                self.vault.borrow_mut().remove(note, &self.templates);
+            }
+        }
+    }
+
+    /// The text of a note: what the editor has if it is open, and what is on disk otherwise.
+    fn source(&self, note: &Path) -> Option<String> {
+        match self.documents.get(note) {
+            Some(text) => Some(text.clone()),
+            None => std::fs::read_to_string(note).ok(),
+        }
+    }
+
+    fn resolve(&self, target: &str, note: &Path) -> Resolution {
+        let directory = note.parent().unwrap_or(Path::new("."));
+        self.templates
+            .resolver()
+            .resolve(target, directory, self.templates.title_cache())
+    }
+
+    fn publish_all(&mut self, out: &mut Vec<Json>) {
+        let notes: Vec<PathBuf> = self.documents.keys().cloned().collect();
+        for note in notes {
+            out.push(self.diagnostics(&note));
+        }
+    }
+
+    /// Renders an open note and reports its error, its warnings and its links to notes that do
+    /// not exist.
+    fn diagnostics(&mut self, note: &Path) -> Json {
+        let text = self.documents.get(note).cloned().unwrap_or_default();
+        let mut diagnostics = Vec::new();
+        let rendered = catch_unwind(AssertUnwindSafe(|| {
+            markdown_to_html(&text, note, &self.templates)
+        }));
+        match rendered {
+            Ok(Ok(parsed)) => {
+                for warning in &parsed.diagnostics {
+                    diagnostics.push(diagnostic(
+                        point_range(&text, warning.line(), warning.column()),
+                        SEVERITY_WARNING,
+                        &warning.comment,
+                    ));
+                }
+            }
+            Ok(Err(e)) => diagnostics.push(diagnostic(
+                point_range(&text, e.line(), e.column()),
+                SEVERITY_ERROR,
+                &e.comment,
+            )),
+            Err(_) => {
+                self.reset_templates();
+                diagnostics.push(diagnostic(
+                    range(&text, 0, 0),
+                    SEVERITY_ERROR,
+                    "The parser crashed on this note",
+                ));
+            }
+        }
+        for link in links_in(&text) {
+            if let Resolution::Missing(_) = self.resolve(&link.target, note) {
+                diagnostics.push(diagnostic(
+                    range(&text, link.start, link.end),
+                    SEVERITY_WARNING,
+                    &format!("There is no note {}", link.target),
+                ));
+            }
+        }
+        notification(
+            "textDocument/publishDiagnostics",
+            Json::object([
+                ("uri", Json::from(path_to_uri(note))),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        )
+    }
+
+    /// Note names after `[[`, and template names after `{{`.
+    fn completion(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (note, text, offset) = self.position(params)?;
+        let line_start = text[..offset].rfind('\n').map_or(0, |x| x + 1);
+        let before = &text[line_start..offset];
+        let opened = |open: &str, close: &str| match (before.rfind(open), before.rfind(close)) {
+            (Some(open_at), Some(close_at)) if close_at > open_at => None,
+            (Some(open_at), _) => {
+                Some(open_at + open.len()).filter(|x| !before[*x..].contains('|'))
+            }
+            (None, _) => None,
+        };
+        let mut items = Vec::new();
+        if let Some(start) = opened("[[", "]]") {
+            let replace = range(&text, line_start + start, offset);
+            let prefix = self.templates.template_prefix();
+            for other in self.vault.notes() {
+                let stem = stem(&other);
+                if stem.starts_with(prefix) {
+                    continue;
+                }
+                let header = self.templates.title_cache().header(&other);
+                let title = header.as_ref().and_then(|x| x.raw_title.clone());
+                items.push(completion_item(
+                    &stem,
+                    KIND_FILE,
+                    title.as_deref().map(str::trim),
+                    &replace,
+                ));
+                for alias in header.map(|x| x.front_matter.aliases()).unwrap_or_default() {
+                    items.push(completion_item(
+                        &alias,
+                        KIND_REFERENCE,
+                        Some(&format!("Alias of {stem}")),
+                        &replace,
+                    ));
+                }
+            }
+        } else if let Some(start) = opened("{{", "}}") {
+            let replace = range(&text, line_start + start, offset);
+            let mut names: Vec<&String> = self.templates.map.keys().collect();
+            names.sort();
+            for name in names {
+                items.push(completion_item(
+                    name,
+                    KIND_FUNCTION,
+                    Some("Built-in template"),
+                    &replace,
+                ));
+            }
+            let prefix = self.templates.template_prefix();
+            let directory = note.parent().unwrap_or(Path::new("."));
+            for template in notes_in(directory) {
+                if let Some(name) = stem(&template).strip_prefix(prefix) {
+                    items.push(completion_item(
+                        name,
+                        KIND_FUNCTION,
+                        Some("Template note"),
+                        &replace,
+                    ));
+                }
+            }
+        }
+        Ok(Json::Array(items))
+    }
+
+    fn definition(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (note, text, offset) = self.position(params)?;
+        let link = match link_at(&text, offset) {
+            Some(x) => x,
+            None => return Ok(Json::Null),
+        };
+        let targets = match self.resolve(&link.target, &note) {
+            Resolution::Found(target) => vec![target],
+            Resolution::Ambiguous(candidates) => candidates,
+            Resolution::Missing(_) => return Ok(Json::Null),
+        };
+        Ok(Json::Array(
+            targets
+                .iter()
+                .map(|target| location(target, Json::object(start_of_file())))
+                .collect(),
+        ))
+    }
+
+    /// The links to the note the cursor's link points at, or to the current note when the
+    /// cursor is not on a link.
+    fn references(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (note, text, offset) = self.position(params)?;
+        let target = match link_at(&text, offset) {
+            Some(link) => match self.resolve(&link.target, &note) {
+                Resolution::Missing(_) => return Ok(Json::Array(Vec::new())),
+                resolution => resolution.path().to_path_buf(),
+            },
+            None => note,
+        };
+        let locations = self
+            .links_to(&target)
+            .into_iter()
+            .map(|(other, source, link)| location(&other, range(&source, link.start, link.end)))
+            .collect();
+        Ok(Json::Array(locations))
+    }
+
+    /// Every link that resolves to `target`, with the note it is in and that note's text.
+    fn links_to(&self, target: &Path) -> Vec<(PathBuf, String, WikiLinkOccurrence)> {
+        let mut notes: BTreeSet<PathBuf> = self.vault.backlinks(target).into_iter().collect();
+        notes.extend(self.documents.keys().cloned());
+        notes.insert(target.to_path_buf());
+        let mut out = Vec::new();
+        for note in notes {
+            let source = match self.source(&note) {
+                Some(x) => x,
+                None => continue,
+            };
+            for link in links_in(&source) {
+                let resolution = self.resolve(&link.target, &note);
+                if resolution.exists() && resolution.path() == target {
+                    out.push((note.clone(), source.clone(), link));
+                }
+            }
+        }
+        out
+    }
+
+    fn hover(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (note, text, offset) = self.position(params)?;
+        let link = match link_at(&text, offset) {
+            Some(x) => x,
+            None => return Ok(Json::Null),
+        };
+        let contents = match self.resolve(&link.target, &note) {
+            Resolution::Found(target) => {
+                let title = self
+                    .templates
+                    .title_cache()
+                    .header(&target)
+                    .and_then(|x| x.raw_title.clone());
+                match title {
+                    Some(title) => format!("**{}**\n\n`{}`", title.trim(), file_name(&target)),
+                    None => format!("`{}` has no title", file_name(&target)),
+                }
+            }
+            Resolution::Ambiguous(candidates) => {
+                let mut out = format!("`{}` could mean several notes:\n", link.target);
+                for candidate in &candidates {
+                    out.push_str(&format!("\n- `{}`", file_name(candidate)));
+                }
+                out
+            }
+            Resolution::Missing(target) => format!(
+                "There is no note `{}` yet, it would be `{}`",
+                link.target,
+                file_name(&target)
+            ),
+        };
+        Ok(Json::object([
+            (
+                "contents",
+                Json::object([
+                    ("kind", Json::from("markdown")),
+                    ("value", Json::from(contents)),
+                ]),
+            ),
+            ("range", range(&text, link.start, link.end)),
+        ]))
+    }
+
+    /// Renames the note the cursor's link points at, or the current note, and rewrites the
+    /// links and front-matter parents that name it by its file name. Links by title or alias
+    /// keep working as they are.
+    fn rename(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (note, text, offset) = self.position(params)?;
+        let new_name = params
+            .get("newName")
+            .and_then(Json::as_str)
+            .map(str::trim)
+            .ok_or((INVALID_PARAMS, "newName is missing".to_string()))?;
+        let new_name = note_name(new_name).map_err(|e| (REQUEST_FAILED, e.to_string()))?;
+        let target = match link_at(&text, offset) {
+            Some(link) => match self.resolve(&link.target, &note) {
+                Resolution::Found(x) => x,
+                _ => {
+                    return Err((
+                        REQUEST_FAILED,
+                        format!("{} is not a single note", link.target),
+                    ))
+                }
+            },
+            None => note,
+        };
+        let renamed = target.with_file_name(format!("{new_name}.md"));
+        if renamed.exists() && !atomic::same_file(&renamed, &target) {
+            return Err((
+                REQUEST_FAILED,
+                format!("{} already exists", file_name(&renamed)),
+            ));
+        }
+        let old_name = stem(&target).to_lowercase();
+        // The text of every note to change, with the byte ranges of the names in it.
+        let mut ranges: BTreeMap<PathBuf, (String, Vec<(usize, usize)>)> = BTreeMap::new();
+        for (other, source, link) in self.links_to(&target) {
+            if link.target.to_lowercase() != old_name {
+                continue;
+            }
+            let range = target_range(&source, &link);
+            let entry = ranges.entry(other).or_insert_with(|| (source, Vec::new()));
+            entry.1.push(range);
+        }
+        // Parents named without brackets are not wiki-links, but point at the note all the same.
+        let mut notes: BTreeSet<PathBuf> = match target.parent() {
+            Some(directory) => notes_in(directory).into_iter().collect(),
+            None => BTreeSet::new(),
+        };
+        notes.extend(self.documents.keys().cloned());
+        for other in notes {
+            let source = match self.source(&other) {
+                Some(x) => x,
+                None => continue,
+            };
+            let parents: Vec<(usize, usize)> = plain_parents(&source)
+                .into_iter()
+                .filter(|&(start, end)| {
+                    let parent = &source[start..end];
+                    parent.to_lowercase() == old_name
+                        && matches!(self.resolve(parent, &other), Resolution::Found(x) if x == target)
+                })
+                .collect();
+            if !parents.is_empty() {
+                let entry = ranges.entry(other).or_insert_with(|| (source, Vec::new()));
+                entry.1.extend(parents);
+            }
+        }
+        let mut changes: Vec<Json> = ranges
+            .into_iter()
+            .map(|(other, (source, mut ranges))| {
+                ranges.sort();
+                let edits = ranges
+                    .into_iter()
+                    .map(|(start, end)| {
+                        Json::object([
+                            ("range", range(&source, start, end)),
+                            ("newText", Json::from(new_name)),
+                        ])
+                    })
+                    .collect();
+                Json::object([
+                    (
+                        "textDocument",
+                        Json::object([
+                            ("uri", Json::from(path_to_uri(&other))),
+                            ("version", Json::Null),
+                        ]),
+                    ),
+                    ("edits", Json::Array(edits)),
+                ])
+            })
+            .collect();
+        if renamed != target {
+            changes.push(Json::object([
+                ("kind", Json::from("rename")),
+                ("oldUri", Json::from(path_to_uri(&target))),
+                ("newUri", Json::from(path_to_uri(&renamed))),
+            ]));
+        }
+        Ok(Json::object([("documentChanges", Json::Array(changes))]))
+    }
+
+    /// The note, its text and the byte offset a `textDocument/position` request is about.
+    fn position(&self, params: &Json) -> Result<(PathBuf, String, usize), (i64, String)> {
+        let note = document_path(params)
+            .ok_or((INVALID_PARAMS, "textDocument.uri is missing".to_string()))?;
+        let line = params.at(&["position", "line"]).and_then(Json::as_u64);
+        let character = params.at(&["position", "character"]).and_then(Json::as_u64);
+        let (line, character) = match (line, character) {
+            (Some(line), Some(character)) => (line as usize, character as usize),
+            _ => return Err((INVALID_PARAMS, "position is missing".to_string())),
+        };
+        let text = self
+            .source(&note)
+            .ok_or((REQUEST_FAILED, format!("Could not read {}", note.display())))?;
+        let offset = offset_of(&text, line, character);
+        Ok((note, text, offset))
+    }
+}
+
+/// Reads messages from `input` and writes the answers to `output` until the client exits.
+/// Returns whether the client shut the server down first, as the protocol asks.
+pub fn run<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> io::Result<bool> {
+    let mut server = LanguageServer::new();
+    while !server.exited() {
+        let body = match read_message(input)? {
+            Some(x) => x,
+            None => break,
+        };
+        let replies = match Json::parse(&body) {
+            Ok(message) => server.handle(&message),
+            Err(e) => vec![error_response(Json::Null, PARSE_ERROR, &e)],
+        };
+        for reply in replies {
+            write_message(output, &reply)?;
+        }
+    }
+    Ok(server.is_shut_down())
+}
+
+/// Reads one Content-Length framed message. Returns `None` at the end of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "A message has no Content-Length",
+        )
+    })?;
+    if length > MAX_MESSAGE_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("A message is longer than {MAX_MESSAGE_BYTES} bytes"),
+        ));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "A message is not UTF-8"))
+}
+
+pub fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+fn response(id: Json, result: Json) -> Json {
+    Json::object([
+        ("jsonrpc", Json::from("2.0")),
+        ("id", id),
+        ("result", result),
+    ])
+}
+
+fn error_response(id: Json, code: i64, message: &str) -> Json {
+    Json::object([
+        ("jsonrpc", Json::from("2.0")),
+        ("id", id),
+        (
+            "error",
+            Json::object([("code", Json::from(code)), ("message", Json::from(message))]),
+        ),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object([
+        ("jsonrpc", Json::from("2.0")),
+        ("method", Json::from(method)),
+        ("params", params),
+    ])
+}
+
+fn diagnostic(range: Json, severity: usize, message: &str) -> Json {
+    Json::object([
+        ("range", range),
+        ("severity", Json::from(severity)),
+        ("source", Json::from("confoosion")),
+        ("message", Json::from(message)),
+    ])
+}
+
+fn completion_item(label: &str, kind: usize, detail: Option<&str>, replace: &Json) -> Json {
+    let mut item = Json::object([("label", Json::from(label)), ("kind", Json::from(kind))]);
+    if let Some(detail) = detail {
+        item.insert("detail", Json::from(detail));
+    }
+    item.insert(
+        "textEdit",
+        Json::object([("range", replace.clone()), ("newText", Json::from(label))]),
+    );
+    item
+}
+
+fn location(note: &Path, range: Json) -> Json {
+    Json::object([("uri", Json::from(path_to_uri(note))), ("range", range)])
+}
+
+fn start_of_file() -> [(&'static str, Json); 2] {
+    let start = || {
+        Json::object([
+            ("line", Json::from(0usize)),
+            ("character", Json::from(0usize)),
+        ])
+    };
+    [("start", start()), ("end", start())]
+}
+
+fn document_path(params: &Json) -> Option<PathBuf> {
+    params
+        .at(&["textDocument", "uri"])
+        .and_then(Json::as_str)
+        .and_then(uri_to_path)
+}
+
+fn link_at(text: &str, offset: usize) -> Option<WikiLinkOccurrence> {
+    links_in(text)
+        .into_iter()
+        .find(|link| link.start <= offset && offset <= link.end)
+}
+
+/// A range over the bytes `start..end` of `text`, in the UTF-16 positions of the protocol.
+fn range(text: &str, start: usize, end: usize) -> Json {
+    Json::object([
+        ("start", position_of(text, start)),
+        ("end", position_of(text, end)),
+    ])
+}
+
+/// A range over one character, for errors that only know their 1-based line and column.
+fn point_range(text: &str, line: usize, column: usize) -> Json {
+    if line == 0 {
+        return range(text, 0, 0);
+    }
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(str::len)
+        .sum::<usize>()
+        .min(text.len());
+    let line_text = text[line_start..].split('\n').next().unwrap_or_default();
+    let start = line_start
+        + line_text
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(line_text.len(), |(x, _)| x);
+    let end = text[start..]
+        .chars()
+        .next()
+        .filter(|x| *x != '\n')
+        .map_or(start, |x| start + x.len_utf8());
+    range(text, start, end)
+}
+
+fn position_of(text: &str, offset: usize) -> Json {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |x| x + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    Json::object([
+        ("line", Json::from(line)),
+        ("character", Json::from(character)),
+    ])
+}
+
+/// The byte offset of a protocol position, clamped to the line and to the text.
+fn offset_of(text: &str, line: usize, character: usize) -> usize {
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(x) => line_start += x + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (index, x) in text[line_start..].char_indices() {
+        if units >= character || x == '\n' {
+            return line_start + index;
+        }
+        units += x.len_utf16();
+    }
+    text.len()
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // `file://host/path` is not a local file; `file:///path` has an empty host.
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    if !path.starts_with('/') {
+        return None;
+    }
+    Some(PathBuf::from(percent_decode(path)))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut out = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'/' | b'-' | b'_' | b'.' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+fn stem(note: &Path) -> String {
+    note.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn file_name(note: &Path) -> String {
+    note.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_read_by_their_length() {
+        let mut input = "Content-Length: 2\r\n\r\n{}Content-Length: 3\r\n\r\n[1]".as_bytes();
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("{}"));
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("[1]"));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        let mut input = "Content-Length: 18446744073709551615\r\n\r\n{}".as_bytes();
+        let e = read_message(&mut input).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let mut input = "Content-Type: text/plain\r\n\r\n{}".as_bytes();
+        assert!(read_message(&mut input).is_err());
+    }
+}
@@ -0,0 +1,468 @@
+use std::io::IsTerminal;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use confoosion_core::graph::{Graph, GraphOptions};
+use confoosion_core::journal::{self, JournalOptions};
+use confoosion_core::search::SearchIndex;
+use confoosion_core::serve::Server;
+use confoosion_core::site::{BuildReport, Site, SiteOptions};
+use confoosion_core::transaction::{self, Recovery};
+use confoosion_core::vault::Vault;
+use confoosion_core::watch::Watcher;
+use confoosion_core::{atomic, lsp, rpc};
+use confoosion_markdown_parser::date::Date;
+use confoosion_markdown_parser::template::TemplateMap;
+
+const USAGE: &str = "\
+Usage: confoosion <command> [arguments]
+
+Commands:
+  build <directory> [-o <output>] [--skeleton <file>]
+      Publish every note in a directory as a static HTML site, in `out` by default
+  watch <directory> [-o <output>] [--skeleton <file>] [--interval <milliseconds>]
+      Build the site, then render the notes that change and the pages that depend on them
+  serve <directory> [--bind <address>] [--skeleton <file>]
+      Preview the notes in a browser, at 127.0.0.1:8080 by default, reloading on changes
+  search <directory> <query> [--limit <count>]
+      List the notes that contain every word of a query, best first; quote phrases with \" and
+      end words with * to match prefixes
+  graph <directory> [--format dot|graphml|json] [--parents-only] [--around <note>]
+        [--hops <count>] [--no-orphans] [-o <file>]
+      Export the links and parent relations between the notes, as DOT by default; --around
+      only takes the notes up to --hops links (1 by default) away from a note
+  journal <directory> [--date <YYYY-MM-DD>] [--pattern <pattern>] [--template <file>]
+      Print the path of today's daily note, named like 2024-01-31 by default, and create it
+      from a template first if it does not exist yet
+  lsp
+      Run a Language Server Protocol server on standard input and output, for editors
+  rpc <directory> [--interval <milliseconds>]
+      Answer JSON-RPC requests from a frontend on standard input and output, see PROTOCOL.md
+
+Exit codes: 0 on success, 1 when a note has errors, 2 on wrong usage, 3 when a file cannot be
+read or written.";
+
+const EXIT_PROBLEMS: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_IO: u8 = 3;
+
+/// Why a command failed, which decides the exit code.
+enum Failure {
+    Problems,
+    Usage(String),
+    Io(String),
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("build") => build(&args[1..]),
+        Some("watch") => watch(&args[1..]),
+        Some("serve") => serve(&args[1..]),
+        Some("search") => search(&args[1..]),
+        Some("graph") => graph(&args[1..]),
+        Some("journal") => daily_note(&args[1..]),
+        Some("lsp") => language_server(&args[1..]),
+        Some("rpc") => remote(&args[1..]),
+        Some("-h" | "--help" | "help") => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(other) => Err(Failure::Usage(format!("Unknown command {other}"))),
+        None => Err(Failure::Usage("No command given".to_string())),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Problems) => ExitCode::from(EXIT_PROBLEMS),
+        Err(Failure::Usage(message)) => {
+            eprintln!("{message}\n\n{USAGE}");
+            ExitCode::from(EXIT_USAGE)
+        }
+        Err(Failure::Io(message)) => {
+            eprintln!("{message}");
+            ExitCode::from(EXIT_IO)
+        }
+    }
+}
+
+fn build(args: &[String]) -> Result<(), Failure> {
+    let arguments = site_arguments(args, "build", &["--output", "--skeleton"])?;
+    recover(&arguments.directory)?;
+    let mut site = open_site(&arguments.directory, arguments.options)?;
+    let report = site.build().map_err(|e| write_failure(&site, e))?;
+    print_report(&site, &report);
+    match report.errors.is_empty() {
+        true => Ok(()),
+        false => Err(Failure::Problems),
+    }
+}
+
+fn watch(args: &[String]) -> Result<(), Failure> {
+    let arguments = site_arguments(args, "watch", &["--output", "--skeleton", "--interval"])?;
+    let directory = arguments.directory;
+    recover(&directory)?;
+    let mut watcher = Watcher::new(&directory)
+        .map_err(|e| Failure::Io(format!("Could not read {}: {e}", directory.display())))?;
+    let mut site = open_site(&directory, arguments.options)?;
+    let report = site.build().map_err(|e| write_failure(&site, e))?;
+    print_report(&site, &report);
+    eprintln!("Watching {} for changes", directory.display());
+    loop {
+        std::thread::sleep(arguments.interval);
+        let changes = watcher
+            .poll()
+            .map_err(|e| Failure::Io(format!("Could not read {}: {e}", directory.display())))?;
+        if changes.is_empty() {
+            continue;
+        }
+        // Notes pass through broken states while they are edited, so keep watching regardless.
+        match site.update(&changes) {
+            Ok(report) => print_report(&site, &report),
+            Err(e) => eprintln!("Could not update {}: {e}", site.options().output.display()),
+        }
+    }
+}
+
+fn serve(args: &[String]) -> Result<(), Failure> {
+    let arguments = site_arguments(args, "serve", &["--skeleton", "--bind"])?;
+    recover(&arguments.directory)?;
+    let site = open_site(&arguments.directory, arguments.options)?;
+    let mut server = Server::new(site).map_err(|e| {
+        Failure::Io(format!(
+            "Could not read {}: {e}",
+            arguments.directory.display()
+        ))
+    })?;
+    let listener = TcpListener::bind(&arguments.bind)
+        .map_err(|e| Failure::Io(format!("Could not listen on {}: {e}", arguments.bind)))?;
+    eprintln!(
+        "Serving {} at http://{}/",
+        arguments.directory.display(),
+        arguments.bind
+    );
+    server
+        .run(&listener)
+        .map_err(|e| Failure::Io(format!("Could not accept a connection: {e}")))
+}
+
+fn search(args: &[String]) -> Result<(), Failure> {
+    let mut directory = None;
+    let mut query = Vec::new();
+    let mut limit = 10;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--limit" => match args.next().map(|x| x.parse()) {
+                Some(Ok(x)) => limit = x,
+                _ => return Err(Failure::Usage("--limit needs a number".to_string())),
+            },
+            _ if directory.is_none() => directory = Some(PathBuf::from(arg)),
+            _ => query.push(arg.as_str()),
+        }
+    }
+    let directory =
+        directory.ok_or_else(|| Failure::Usage("search needs a directory".to_string()))?;
+    if query.is_empty() {
+        return Err(Failure::Usage("search needs a query".to_string()));
+    }
+    recover(&directory)?;
+    if !directory.is_dir() {
+        return Err(Failure::Io(format!(
+            "{} is not a directory",
+            directory.display()
+        )));
+    }
+    let index = SearchIndex::open(&directory, &TemplateMap::with_builtins());
+    for (note, e) in index.problems() {
+        eprintln!(
+            "{}:{}:{}: warning: could not be rendered, so its source is searched instead: {}",
+            note.display(),
+            e.line(),
+            e.column(),
+            e.comment
+        );
+    }
+    if let Err(e) = index.save() {
+        eprintln!("Could not save the search index: {e}");
+    }
+    let hits = index.search(&query.join(" "), limit);
+    if hits.is_empty() {
+        eprintln!("No notes match");
+    }
+    let terminal = std::io::stdout().is_terminal();
+    for hit in hits {
+        let name = hit.note.file_stem().unwrap_or_default().to_string_lossy();
+        // Matches are shown in bold on a terminal, and left unmarked otherwise.
+        let snippet = match terminal {
+            true => hit.plain_snippet_with("\x1b[1m", "\x1b[0m"),
+            false => hit.plain_snippet(),
+        };
+        println!("{name} ({:.2})\n    {snippet}", hit.score);
+    }
+    Ok(())
+}
+
+fn graph(args: &[String]) -> Result<(), Failure> {
+    let mut directory = None;
+    let mut format = "dot".to_string();
+    let mut around = None;
+    let mut hops = 1;
+    let mut output = None;
+    let mut options = GraphOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--parents-only" => options.parents_only = true,
+            "--no-orphans" => options.orphans = false,
+            "--hops" => match args.next().map(|x| x.parse()) {
+                Some(Ok(x)) => hops = x,
+                _ => return Err(Failure::Usage("--hops needs a number".to_string())),
+            },
+            "--format" | "--around" | "-o" | "--output" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Failure::Usage(format!("{arg} needs a value")))?;
+                match arg.as_str() {
+                    "--format" => format = value.clone(),
+                    "--around" => around = Some(value.clone()),
+                    _ => output = Some(PathBuf::from(value)),
+                }
+            }
+            _ if !arg.starts_with('-') && directory.is_none() => {
+                directory = Some(PathBuf::from(arg))
+            }
+            _ => return Err(Failure::Usage(format!("Unexpected argument {arg}"))),
+        }
+    }
+    let directory =
+        directory.ok_or_else(|| Failure::Usage("graph needs a directory".to_string()))?;
+    if !["dot", "graphml", "json"].contains(&format.as_str()) {
+        return Err(Failure::Usage(format!("Unknown graph format {format}")));
+    }
+    recover(&directory)?;
+    let templates = TemplateMap::with_builtins();
+    let vault = Vault::scan(&directory, &templates)
+        .map_err(|e| Failure::Io(format!("Could not read {}: {e}", directory.display())))?;
+    if let Some(name) = around {
+        let resolution = templates
+            .resolver()
+            .resolve(&name, &directory, templates.title_cache());
+        if !resolution.exists() {
+            return Err(Failure::Usage(format!("There is no note {name}")));
+        }
+        options.around = Some((resolution.path().to_path_buf(), hops));
+    }
+    let graph = Graph::from_vault(&vault, &options);
+    let text = match format.as_str() {
+        "graphml" => graph.to_graphml(),
+        "json" => format!("{}\n", graph.to_json()),
+        _ => graph.to_dot(),
+    };
+    match output {
+        Some(path) => atomic::write(&path, text)
+            .map_err(|e| Failure::Io(format!("Could not write {}: {e}", path.display()))),
+        None => {
+            print!("{text}");
+            Ok(())
+        }
+    }
+}
+
+fn daily_note(args: &[String]) -> Result<(), Failure> {
+    let mut directory = None;
+    let mut date = Date::today();
+    let mut options = JournalOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') && directory.is_none() {
+            directory = Some(PathBuf::from(arg));
+            continue;
+        }
+        let value = match arg.as_str() {
+            "--date" | "--pattern" | "--template" => args
+                .next()
+                .ok_or_else(|| Failure::Usage(format!("{arg} needs a value")))?,
+            _ => return Err(Failure::Usage(format!("Unexpected argument {arg}"))),
+        };
+        match arg.as_str() {
+            "--date" => {
+                date = Date::parse(value).ok_or_else(|| {
+                    Failure::Usage(format!("{value} is not a date like 2024-01-31"))
+                })?
+            }
+            "--pattern" => options.pattern = value.clone(),
+            _ => {
+                options.template = Some(
+                    std::fs::read_to_string(value)
+                        .map_err(|e| Failure::Io(format!("Could not read {value}: {e}")))?,
+                )
+            }
+        }
+    }
+    let directory =
+        directory.ok_or_else(|| Failure::Usage("journal needs a directory".to_string()))?;
+    recover(&directory)?;
+    if !directory.is_dir() {
+        return Err(Failure::Io(format!(
+            "{} is not a directory",
+            directory.display()
+        )));
+    }
+    let (note, created) =
+        journal::open(&directory, date, &options).map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidInput => Failure::Usage(e.to_string()),
+            _ => Failure::Io(format!("Could not write the note for {date}: {e}")),
+        })?;
+    if created {
+        eprintln!("Created {}", note.display());
+    }
+    println!("{}", note.display());
+    Ok(())
+}
+
+fn language_server(args: &[String]) -> Result<(), Failure> {
+    // Editors commonly pass `--stdio`, which is the only transport there is.
+    if let Some(arg) = args.iter().find(|x| *x != "--stdio") {
+        return Err(Failure::Usage(format!("Unexpected argument {arg}")));
+    }
+    let shut_down = lsp::run(&mut std::io::stdin().lock(), &mut std::io::stdout().lock())
+        .map_err(|e| Failure::Io(format!("Could not talk to the editor: {e}")))?;
+    match shut_down {
+        true => Ok(()),
+        // The protocol asks for an exit code of 1 when the editor exits without a shutdown.
+        false => Err(Failure::Problems),
+    }
+}
+
+fn remote(args: &[String]) -> Result<(), Failure> {
+    let arguments = site_arguments(args, "rpc", &["--interval"])?;
+    recover(&arguments.directory)?;
+    let input = std::io::BufReader::new(std::io::stdin());
+    rpc::run(
+        &arguments.directory,
+        input,
+        &mut std::io::stdout().lock(),
+        arguments.interval,
+    )
+    .map_err(|e| {
+        Failure::Io(format!(
+            "Could not serve {}: {e}",
+            arguments.directory.display()
+        ))
+    })
+}
+
+/// The arguments shared by the commands that work on a whole site.
+struct SiteArguments {
+    directory: PathBuf,
+    options: SiteOptions,
+    interval: Duration,
+    bind: String,
+}
+
+/// Reads `<directory>` followed by any of the `flags` a command accepts.
+fn site_arguments(
+    args: &[String],
+    command: &str,
+    flags: &[&str],
+) -> Result<SiteArguments, Failure> {
+    let mut directory = None;
+    let mut arguments = SiteArguments {
+        directory: PathBuf::new(),
+        options: SiteOptions::default(),
+        interval: Duration::from_millis(500),
+        bind: "127.0.0.1:8080".to_string(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let flag = match arg.as_str() {
+            "-o" => "--output",
+            other => other,
+        };
+        if !flag.starts_with('-') && directory.is_none() {
+            directory = Some(PathBuf::from(arg));
+            continue;
+        }
+        if !flags.contains(&flag) {
+            return Err(Failure::Usage(format!("Unexpected argument {arg}")));
+        }
+        let value = match args.next() {
+            Some(x) => x,
+            None => return Err(Failure::Usage(format!("{arg} needs a value"))),
+        };
+        match flag {
+            "--output" => arguments.options.output = PathBuf::from(value),
+            "--skeleton" => {
+                arguments.options.skeleton = std::fs::read_to_string(value)
+                    .map_err(|e| Failure::Io(format!("Could not read {value}: {e}")))?
+            }
+            "--interval" => match value.parse() {
+                Ok(x) => arguments.interval = Duration::from_millis(x),
+                Err(_) => return Err(Failure::Usage(format!("{arg} needs milliseconds"))),
+            },
+            _ => arguments.bind = value.clone(),
+        }
+    }
+    match directory {
+        Some(directory) => Ok(SiteArguments {
+            directory,
+            ..arguments
+        }),
+        None => Err(Failure::Usage(format!("{command} needs a directory"))),
+    }
+}
+
+fn open_site(directory: &Path, options: SiteOptions) -> Result<Site, Failure> {
+    Site::new(directory, TemplateMap::with_builtins(), options)
+        .map_err(|e| Failure::Io(format!("Could not read {}: {e}", directory.display())))
+}
+
+fn write_failure(site: &Site, e: std::io::Error) -> Failure {
+    Failure::Io(format!(
+        "Could not write {}: {e}",
+        site.options().output.display()
+    ))
+}
+
+fn print_report(site: &Site, report: &BuildReport) {
+    for message in report.errors.iter().chain(&report.warnings) {
+        eprintln!("{message}");
+    }
+    eprintln!(
+        "Wrote {} pages and {} images to {}",
+        report.pages,
+        report.images,
+        site.options().output.display()
+    );
+}
+
+/// Finishes or undoes a batch of writes that was interrupted, so no half-applied change is read.
+fn recover(directory: &Path) -> Result<(), Failure> {
+    match transaction::recover(directory) {
+        Ok(Recovery::Clean) => Ok(()),
+        Ok(Recovery::RolledForward(count)) => {
+            eprintln!("Finished an interrupted batch of {count} changes");
+            Ok(())
+        }
+        Ok(Recovery::RolledForwardExcept(count, kept)) => {
+            for from in kept {
+                eprintln!(
+                    "{}: warning: left in place because the name it was renamed to was taken",
+                    directory.join(from).display()
+                );
+            }
+            eprintln!("Finished an interrupted batch of {count} changes");
+            Ok(())
+        }
+        Ok(Recovery::RolledBack(count)) => {
+            eprintln!("Undid an interrupted batch of {count} changes");
+            Ok(())
+        }
+        Err(e) => Err(Failure::Io(format!(
+            "Could not recover {}: {e}",
+            directory.display()
+        ))),
+    }
+}
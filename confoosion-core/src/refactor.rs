@@ -0,0 +1,328 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use confoosion_markdown_parser::{
+    frontmatter::FrontMatter,
+    resolver::notes_in,
+    scan::{self, WikiLinkOccurrence},
+    template::TemplateMap,
+};
+
+use crate::{atomic, transaction::Transaction};
+
+/// Renames `note` to `new_name` and rewrites the links that name it by its file name. Links by
+/// title or alias keep working as they are. Returns the notes whose links were rewritten.
+///
+/// Everything happens in one [`Transaction`], so a crash leaves either the old or the new vault.
+pub fn rename(
+    directory: &Path,
+    templates: &TemplateMap,
+    note: &Path,
+    new_name: &str,
+) -> io::Result<Vec<PathBuf>> {
+    let new_name = note_name(new_name)?;
+    let renamed = note.with_file_name(format!("{new_name}.md"));
+    // Changing only the case of a name finds the note itself on filesystems that ignore case.
+    if renamed.exists() && !atomic::same_file(note, &renamed) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", renamed.display()),
+        ));
+    }
+    let old_name = stem(note).to_lowercase();
+    let mut transaction = Transaction::begin(directory)?;
+    let mut changed = Vec::new();
+    for other in notes_in(directory) {
+        let source = std::fs::read_to_string(&other)?;
+        let retargeted = retarget(&source, new_name, |target| {
+            target.to_lowercase() == old_name && resolves_to(templates, target, &other, note)
+        });
+        if let Some(retargeted) = retargeted {
+            transaction.write(&other, retargeted)?;
+            changed.push(other);
+        }
+    }
+    if renamed != note {
+        transaction.rename(note, &renamed)?;
+    }
+    transaction.commit()?;
+    Ok(changed)
+}
+
+/// Moves the section under `heading` out of `note` into a note of its own, and leaves a link to
+/// it in its place. The new note is named `new_name`, or after the heading, and is titled with
+/// the heading. Returns the new note.
+pub fn split(
+    directory: &Path,
+    note: &Path,
+    heading: &str,
+    new_name: Option<&str>,
+) -> io::Result<PathBuf> {
+    let source = std::fs::read_to_string(note)?;
+    let (_, body, _) = FrontMatter::split(&source);
+    let shift = source.len() - body.len();
+    let headings = scan::headings(body);
+    let index = headings
+        .iter()
+        .position(|x| x.text.trim().eq_ignore_ascii_case(heading.trim()))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no heading {heading}", note.display()),
+            )
+        })?;
+    let found = &headings[index];
+    if found.start == 0 && found.level == 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The title cannot be split off, merge the note instead",
+        ));
+    }
+    let end = headings[index + 1..]
+        .iter()
+        .find(|x| x.level <= found.level)
+        .map_or(body.len(), |x| x.start);
+    let title = found.text.trim();
+    let name = note_name(new_name.unwrap_or(title))?;
+    let new_note = directory.join(format!("{name}.md"));
+    if new_note.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", new_note.display()),
+        ));
+    }
+    let section = &body[found.start..end];
+    let content = section.split_once('\n').map_or("", |(_, x)| x);
+    let mut new_source = format!("# {title}\n{}", content.trim_start_matches('\n'));
+    if !new_source.ends_with('\n') {
+        new_source.push('\n');
+    }
+    let mut remaining = source[..shift + found.start].to_string();
+    remaining.push_str(&format!("[[{name}]]\n"));
+    if end < body.len() {
+        remaining.push('\n');
+    }
+    remaining.push_str(&body[end..]);
+
+    let mut transaction = Transaction::begin(directory)?;
+    transaction.write(&new_note, new_source)?;
+    transaction.write(note, remaining)?;
+    transaction.commit()?;
+    Ok(new_note)
+}
+
+/// Appends `note` to the end of `into`, removes it, and points every link to it at `into`.
+///
+/// The title of `note` becomes a `##` heading, and its front matter is dropped. Links by its
+/// title or alias are rewritten as well, since those would not resolve any more. Returns the
+/// notes whose links were rewritten.
+pub fn merge(
+    directory: &Path,
+    templates: &TemplateMap,
+    note: &Path,
+    into: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    if note == into {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "A note cannot be merged into itself",
+        ));
+    }
+    let source = std::fs::read_to_string(note)?;
+    let (_, body, _) = FrontMatter::split(&source);
+    let body = body.trim_start_matches('\n');
+    let section = match body.strip_prefix("# ") {
+        Some(rest) => format!("## {rest}"),
+        None => format!("## {}\n{body}", stem(note)),
+    };
+    let into_name = stem(into);
+    let mut transaction = Transaction::begin(directory)?;
+    let mut changed = Vec::new();
+    for other in notes_in(directory) {
+        if other == note {
+            continue;
+        }
+        let mut other_source = std::fs::read_to_string(&other)?;
+        let mut modified = false;
+        if other == into {
+            other_source = format!("{}\n\n{section}", other_source.trim_end());
+            if !other_source.ends_with('\n') {
+                other_source.push('\n');
+            }
+            modified = true;
+        }
+        // Links resolve from the note they are in, which is `into` for the merged section too.
+        let retargeted = retarget(&other_source, &into_name, |target| {
+            resolves_to(templates, target, &other, note)
+        });
+        if let Some(retargeted) = retargeted {
+            other_source = retargeted;
+            changed.push(other.clone());
+            modified = true;
+        }
+        if modified {
+            transaction.write(&other, other_source)?;
+        }
+    }
+    transaction.remove(note)?;
+    transaction.commit()?;
+    Ok(changed)
+}
+
+/// The wiki-links in a note, in its front matter as well as its body, with offsets into the
+/// whole text.
+pub fn links_in(source: &str) -> Vec<WikiLinkOccurrence> {
+    let (_, body, _) = FrontMatter::split(source);
+    let shift = source.len() - body.len();
+    let mut out = scan::wiki_links(&source[..shift]);
+    out.extend(
+        scan::wiki_links(body)
+            .into_iter()
+            .map(|link| WikiLinkOccurrence {
+                start: link.start + shift,
+                end: link.end + shift,
+                ..link
+            }),
+    );
+    out
+}
+
+/// The byte range of the target in a link, as written, without surrounding spaces.
+pub fn target_range(source: &str, link: &WikiLinkOccurrence) -> (usize, usize) {
+    let inner = &source[link.start + 2..link.end - 2];
+    let written = inner.split('|').next().unwrap_or_default();
+    let start = link.start + 2 + (written.len() - written.trim_start().len());
+    (start, start + written.trim().len())
+}
+
+/// The byte ranges of the notes named by `parent` or `parents` in the front matter without
+/// brackets, as in `parents: [Projects, "Home"]`. Those written as wiki-links are in
+/// [`links_in`] instead.
+pub(crate) fn plain_parents(source: &str) -> Vec<(usize, usize)> {
+    let (_, body, _) = FrontMatter::split(source);
+    let front_matter = &source[..source.len() - body.len()];
+    let mut out = Vec::new();
+    // Every part below is a slice of `source`, so this is where it starts in there.
+    let at = |part: &str| part.as_ptr() as usize - source.as_ptr() as usize;
+    let mut in_parents = false;
+    for line in front_matter.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            if in_parents {
+                out.extend(plain_parent(item, at(item)));
+            }
+            continue;
+        }
+        let (key, value) = match trimmed.split_once(':') {
+            Some(x) => x,
+            None => continue,
+        };
+        in_parents = matches!(key.trim(), "parent" | "parents");
+        let value = value.trim();
+        if !in_parents || value.is_empty() {
+            continue;
+        }
+        match value
+            .strip_prefix('[')
+            .and_then(|inner| inner.strip_suffix(']'))
+            .filter(|inner| !inner.starts_with('['))
+        {
+            Some(inner) => {
+                for item in inner.split(',') {
+                    out.extend(plain_parent(item, at(item)));
+                }
+            }
+            None => out.extend(plain_parent(value, at(value))),
+        }
+        // Only a key without a value goes on in `- item` lines.
+        in_parents = false;
+    }
+    out
+}
+
+/// The range of the name in one front matter value starting at `start`, without spaces and
+/// quotes, unless it is empty or a wiki-link.
+fn plain_parent(value: &str, start: usize) -> Option<(usize, usize)> {
+    let trimmed = value.trim();
+    let mut start = start + (value.len() - value.trim_start().len());
+    let mut name = trimmed;
+    for quote in ['"', '\''] {
+        if let Some(inner) = trimmed
+            .strip_prefix(quote)
+            .and_then(|inner| inner.strip_suffix(quote))
+        {
+            start += 1;
+            name = inner;
+        }
+    }
+    let leading = name.len() - name.trim_start().len();
+    let name = name.trim();
+    if name.is_empty() || name.starts_with("[[") {
+        return None;
+    }
+    Some((start + leading, start + leading + name.len()))
+}
+
+/// Replaces the target of every link for which `matches` holds with `new_target`, keeping
+/// labels. Parents named in the front matter without brackets count as links. Returns `None`
+/// when no link matched.
+fn retarget<F: Fn(&str) -> bool>(source: &str, new_target: &str, matches: F) -> Option<String> {
+    let mut targets: Vec<(usize, usize)> = links_in(source)
+        .iter()
+        .filter(|link| matches(&link.target))
+        .map(|link| target_range(source, link))
+        .chain(
+            plain_parents(source)
+                .into_iter()
+                .filter(|&(start, end)| matches(&source[start..end])),
+        )
+        .collect();
+    if targets.is_empty() {
+        return None;
+    }
+    targets.sort();
+    let mut out = String::new();
+    let mut copied = 0;
+    for (start, end) in targets {
+        out.push_str(&source[copied..start]);
+        out.push_str(new_target);
+        copied = end;
+    }
+    out.push_str(&source[copied..]);
+    Some(out)
+}
+
+fn resolves_to(templates: &TemplateMap, target: &str, from: &Path, note: &Path) -> bool {
+    let directory = from.parent().unwrap_or(Path::new("."));
+    let resolution = templates
+        .resolver()
+        .resolve(target, directory, templates.title_cache());
+    resolution.exists() && resolution.path() == note
+}
+
+/// Checks that `name` can be used as a note's file name and in links.
+pub fn note_name(name: &str) -> io::Result<&str> {
+    let name = name.trim();
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\', '[', ']', '|', '#', '{', '}', '\n'])
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name:?} cannot be a note name"),
+        ));
+    }
+    Ok(name)
+}
+
+fn stem(note: &Path) -> String {
+    note.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
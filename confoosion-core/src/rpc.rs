@@ -0,0 +1,494 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use confoosion_markdown_parser::{
+    date::Date, links::UriLinks, template::TemplateMap, vault::VaultQuery,
+};
+
+use crate::{
+    atomic,
+    journal::{self, JournalOptions},
+    json::Json,
+    refactor,
+    search::SearchIndex,
+    site::render_file,
+    vault::{SharedVault, Vault},
+    watch::{Changes, Watcher},
+};
+
+/// The version of the protocol described in `PROTOCOL.md`. It changes whenever a change to the
+/// protocol could break a client.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const UNSUPPORTED_PROTOCOL: i64 = -32001;
+const NO_SUCH_NOTE: i64 = -32002;
+const RENDER_FAILED: i64 = -32003;
+const IO_FAILED: i64 = -32004;
+
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+type RpcResult = Result<Json, (i64, String, Json)>;
+
+/// The core's side of the protocol a frontend talks to it with: line-delimited JSON-RPC 2.0,
+/// as described in `PROTOCOL.md`.
+///
+/// Notes are named by their file name without `.md`, and live in one directory.
+pub struct RpcServer {
+    directory: PathBuf,
+    templates: TemplateMap,
+    vault: SharedVault,
+    search: SearchIndex,
+    watcher: Watcher,
+}
+
+impl RpcServer {
+    pub fn new<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        let directory = std::fs::canonicalize(directory)?;
+        let mut templates = TemplateMap::with_builtins();
+        templates.set_link_generator(Box::new(UriLinks::default()));
+        let vault = SharedVault::new(Vault::scan(&directory, &templates)?);
+        templates.set_vault(Rc::new(vault.clone()));
+        let watcher = Watcher::new(&directory)?;
+        let search = SearchIndex::open(&directory, &templates);
+        // The saved index only saves time, so failing to write it is no reason to stop.
+        let _ = search.save();
+        Ok(Self {
+            directory,
+            templates,
+            vault,
+            search,
+            watcher,
+        })
+    }
+
+    /// Answers one line from the client. Notifications get no answer.
+    pub fn handle_line(&mut self, line: &str) -> Option<Json> {
+        match Json::parse(line) {
+            Ok(message) => self.handle(&message),
+            Err(e) => Some(error_response(Json::Null, PARSE_ERROR, &e, Json::Null)),
+        }
+    }
+
+    pub fn handle(&mut self, message: &Json) -> Option<Json> {
+        let id = message.get("id").cloned();
+        let method = match message.get("method").and_then(Json::as_str) {
+            Some(x) => x,
+            None => {
+                return Some(error_response(
+                    id.unwrap_or(Json::Null),
+                    INVALID_REQUEST,
+                    "A message needs a method",
+                    Json::Null,
+                ))
+            }
+        };
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let result = self.request(method, &params);
+        // Notifications are not answered, not even with an error.
+        let id = id?;
+        Some(match result {
+            Ok(result) => Json::object([
+                ("jsonrpc", Json::from("2.0")),
+                ("id", id),
+                ("result", result),
+            ]),
+            Err((code, message, data)) => error_response(id, code, &message, data),
+        })
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> RpcResult {
+        match method {
+            "initialize" => self.initialize(params),
+            "notes/list" => Ok(self.list()),
+            "notes/search" => self.search(params),
+            "note/read" => self.read(params),
+            "note/render" => self.render(params),
+            "note/backlinks" => self.backlinks(params),
+            "note/save" => self.save(params),
+            "note/rename" => self.rename(params),
+            "note/split" => self.split(params),
+            "note/merge" => self.merge(params),
+            "journal/open" => self.journal(params),
+            _ => Err(failure(
+                METHOD_NOT_FOUND,
+                format!("Unknown method {method}"),
+            )),
+        }
+    }
+
+    fn initialize(&self, params: &Json) -> RpcResult {
+        let protocol = params.get("protocol").and_then(Json::as_u64);
+        if protocol.is_some_and(|x| x != PROTOCOL_VERSION) {
+            return Err((
+                UNSUPPORTED_PROTOCOL,
+                format!(
+                    "Protocol version {} is not supported",
+                    protocol.unwrap_or_default()
+                ),
+                Json::object([("protocol", Json::from(PROTOCOL_VERSION))]),
+            ));
+        }
+        Ok(Json::object([
+            ("protocol", Json::from(PROTOCOL_VERSION)),
+            (
+                "server",
+                Json::object([
+                    ("name", Json::from("confoosion")),
+                    ("version", Json::from(env!("CARGO_PKG_VERSION"))),
+                ]),
+            ),
+            (
+                "directory",
+                Json::from(self.directory.to_string_lossy().to_string()),
+            ),
+        ]))
+    }
+
+    fn list(&self) -> Json {
+        let vault = self.vault.borrow();
+        let notes = vault
+            .notes()
+            .iter()
+            .map(|note| {
+                let entry = vault.entry(note).cloned().unwrap_or_default();
+                let raw_title = self
+                    .templates
+                    .title_cache()
+                    .header(note)
+                    .and_then(|x| x.raw_title.clone())
+                    .map(|x| x.trim().to_string());
+                Json::object([
+                    ("note", Json::from(name(note))),
+                    ("title", Json::from(raw_title)),
+                    ("aliases", Json::from(entry.front_matter.aliases())),
+                    ("tags", Json::from(entry.tags)),
+                    (
+                        "template",
+                        Json::from(name(note).starts_with(self.templates.template_prefix())),
+                    ),
+                ])
+            })
+            .collect();
+        Json::Array(notes)
+    }
+
+    /// The notes that match the query, best first, with a snippet around the best match.
+    fn search(&self, params: &Json) -> RpcResult {
+        let query = string_param(params, "query")?;
+        let limit = params
+            .get("limit")
+            .and_then(Json::as_u64)
+            .map_or(DEFAULT_SEARCH_LIMIT, |x| x as usize);
+        let hits = self
+            .search
+            .search(query, limit)
+            .into_iter()
+            .map(|hit| {
+                Json::object([
+                    ("note", Json::from(name(&hit.note))),
+                    // Rounded, so that the same index gives the same output everywhere.
+                    ("score", Json::from((hit.score * 1000.0).round() / 1000.0)),
+                    ("snippet", Json::from(hit.snippet)),
+                ])
+            })
+            .collect();
+        Ok(Json::Array(hits))
+    }
+
+    fn read(&self, params: &Json) -> RpcResult {
+        let note = self.existing_note(params, "note")?;
+        let text = std::fs::read_to_string(&note).map_err(io_failure)?;
+        Ok(Json::object([
+            ("note", Json::from(name(&note))),
+            ("text", Json::from(text)),
+        ]))
+    }
+
+    fn render(&self, params: &Json) -> RpcResult {
+        let note = self.existing_note(params, "note")?;
+        let parsed = render_file(&note, &self.templates).map_err(|e| {
+            (
+                RENDER_FAILED,
+                e.comment.clone(),
+                Json::object([
+                    ("line", Json::from(e.line())),
+                    ("column", Json::from(e.column())),
+                ]),
+            )
+        })?;
+        let mut links = Vec::new();
+        let mut missing = Vec::new();
+        for link in &parsed.dependencies.links {
+            match link.exists() {
+                true => links.push(name(link)),
+                false => missing.push(name(link)),
+            }
+        }
+        let diagnostics: Vec<Json> = parsed
+            .diagnostics
+            .iter()
+            .map(|x| {
+                Json::object([
+                    ("line", Json::from(x.line())),
+                    ("column", Json::from(x.column())),
+                    ("message", Json::from(x.comment.as_str())),
+                ])
+            })
+            .collect();
+        Ok(Json::object([
+            ("note", Json::from(name(&note))),
+            ("title", Json::from(self.vault.title(&note))),
+            ("html", Json::from(parsed.html)),
+            ("links", Json::from(links)),
+            ("missing", Json::from(missing)),
+            ("tags", Json::from(parsed.tags)),
+            ("diagnostics", Json::Array(diagnostics)),
+        ]))
+    }
+
+    fn backlinks(&self, params: &Json) -> RpcResult {
+        let note = self.existing_note(params, "note")?;
+        let backlinks: Vec<String> = self
+            .vault
+            .backlinks(&note)
+            .iter()
+            .map(|x| name(x))
+            .collect();
+        Ok(Json::from(backlinks))
+    }
+
+    /// Replaces the text of a note, creating it if need be.
+    fn save(&self, params: &Json) -> RpcResult {
+        let note = self.note_param(params, "note")?;
+        let text = string_param(params, "text")?;
+        let created = !note.exists();
+        atomic::write(&note, text).map_err(io_failure)?;
+        Ok(Json::object([
+            ("note", Json::from(name(&note))),
+            ("created", Json::from(created)),
+        ]))
+    }
+
+    fn rename(&self, params: &Json) -> RpcResult {
+        let note = self.existing_note(params, "note")?;
+        let to = string_param(params, "to")?;
+        let changed =
+            refactor::rename(&self.directory, &self.templates, &note, to).map_err(io_failure)?;
+        Ok(Json::object([
+            ("note", Json::from(to.trim())),
+            ("changed", names(&changed)),
+        ]))
+    }
+
+    fn split(&self, params: &Json) -> RpcResult {
+        let note = self.existing_note(params, "note")?;
+        let heading = string_param(params, "heading")?;
+        let to = params.get("to").and_then(Json::as_str);
+        let created = refactor::split(&self.directory, &note, heading, to).map_err(io_failure)?;
+        Ok(Json::object([("note", Json::from(name(&created)))]))
+    }
+
+    fn merge(&self, params: &Json) -> RpcResult {
+        let note = self.existing_note(params, "note")?;
+        let into = self.existing_note(params, "into")?;
+        let changed =
+            refactor::merge(&self.directory, &self.templates, &note, &into).map_err(io_failure)?;
+        Ok(Json::object([
+            ("note", Json::from(name(&into))),
+            ("changed", names(&changed)),
+        ]))
+    }
+
+    /// Finds the daily note for a day, today by default, creating it if need be.
+    fn journal(&self, params: &Json) -> RpcResult {
+        let date = match params.get("date").and_then(Json::as_str) {
+            Some(date) => Date::parse(date).ok_or_else(|| {
+                failure(
+                    INVALID_PARAMS,
+                    format!("{date} is not a date like 2024-01-31"),
+                )
+            })?,
+            None => Date::today(),
+        };
+        let mut options = JournalOptions::default();
+        if let Some(pattern) = params.get("pattern").and_then(Json::as_str) {
+            options.pattern = pattern.to_string();
+        }
+        options.template = params
+            .get("template")
+            .and_then(Json::as_str)
+            .map(str::to_string);
+        let (note, created) = journal::open(&self.directory, date, &options).map_err(io_failure)?;
+        Ok(Json::object([
+            ("note", Json::from(name(&note))),
+            ("created", Json::from(created)),
+        ]))
+    }
+
+    /// Looks for notes that changed on disk, by this server or anything else, and returns the
+    /// `notes/changed` notification to send if there are any.
+    pub fn poll(&mut self) -> Option<Json> {
+        let changes = match self.watcher.poll() {
+            Ok(x) => x,
+            Err(_) => return None,
+        };
+        if changes.is_empty() {
+            return None;
+        }
+        self.reindex(&changes);
+        Some(Json::object([
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from("notes/changed")),
+            (
+                "params",
+                Json::object([
+                    ("changed", names(&changes.changed)),
+                    ("removed", names(&changes.removed)),
+                ]),
+            ),
+        ]))
+    }
+
+    /// Brings the indexes up to date. Notes that appear, disappear or change their names can
+    /// change where links in every other note lead, so those changes index everything again.
+    fn reindex(&mut self, changes: &Changes) {
+        // Reading a note renders it, which looks at the vault, so it is not borrowed meanwhile.
+        let mut everything = !changes.removed.is_empty();
+        for note in &changes.changed {
+            let old = self.vault.borrow().entry(note).cloned();
+            let _ = self.vault.update(note, &self.templates);
+            let vault = self.vault.borrow();
+            everything |= match (&old, vault.entry(note)) {
+                (Some(old), Some(new)) => {
+                    old.title != new.title || old.front_matter != new.front_matter
+                }
+                _ => true,
+            };
+        }
+        if everything {
+            if let Ok(fresh) = Vault::scan(&self.directory, &self.templates) {
+                *self.vault.borrow_mut() = fresh;
+            }
+        }
+        // Rendering the notes for the search index looks at the vault, so it comes after.
+        self.search.apply(changes, &self.templates);
+        let _ = self.search.save();
+    }
+
+    /// The note named by `key`, which does not have to exist.
+    fn note_param(&self, params: &Json, key: &str) -> Result<PathBuf, (i64, String, Json)> {
+        let note = string_param(params, key)?;
+        let note = refactor::note_name(note).map_err(|e| failure(INVALID_PARAMS, e.to_string()))?;
+        Ok(self.directory.join(format!("{note}.md")))
+    }
+
+    fn existing_note(&self, params: &Json, key: &str) -> Result<PathBuf, (i64, String, Json)> {
+        let note = self.note_param(params, key)?;
+        match note.is_file() {
+            true => Ok(note),
+            false => Err((
+                NO_SUCH_NOTE,
+                format!("There is no note {}", name(&note)),
+                Json::object([("note", Json::from(name(&note)))]),
+            )),
+        }
+    }
+}
+
+/// Talks to a client over `input` and `output` until `input` ends, and sends `notes/changed`
+/// notifications when notes change, looking every `interval`.
+pub fn run<R, W>(directory: &Path, input: R, output: &mut W, interval: Duration) -> io::Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write,
+{
+    let mut server = RpcServer::new(directory)?;
+    let (sender, receiver) = mpsc::channel();
+    // Reading blocks, so it gets a thread of its own while this one keeps an eye on the notes.
+    std::thread::spawn(move || {
+        for line in input.lines() {
+            let line = match line {
+                Ok(x) => x,
+                Err(_) => break,
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    loop {
+        match receiver.recv_timeout(interval) {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => {
+                if let Some(notification) = server.poll() {
+                    write_line(output, &notification)?;
+                }
+                if let Some(reply) = server.handle_line(&line) {
+                    write_line(output, &reply)?;
+                }
+                if let Some(notification) = server.poll() {
+                    write_line(output, &notification)?;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(notification) = server.poll() {
+                    write_line(output, &notification)?;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+fn write_line<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    writeln!(output, "{message}")?;
+    output.flush()
+}
+
+fn error_response(id: Json, code: i64, message: &str, data: Json) -> Json {
+    let mut error = Json::object([("code", Json::from(code)), ("message", Json::from(message))]);
+    if !data.is_null() {
+        error.insert("data", data);
+    }
+    Json::object([("jsonrpc", Json::from("2.0")), ("id", id), ("error", error)])
+}
+
+fn failure(code: i64, message: String) -> (i64, String, Json) {
+    (code, message, Json::Null)
+}
+
+fn io_failure(e: io::Error) -> (i64, String, Json) {
+    let code = match e.kind() {
+        io::ErrorKind::InvalidInput => INVALID_PARAMS,
+        io::ErrorKind::NotFound => NO_SUCH_NOTE,
+        _ => IO_FAILED,
+    };
+    failure(code, e.to_string())
+}
+
+fn string_param<'a>(params: &'a Json, key: &str) -> Result<&'a str, (i64, String, Json)> {
+    params
+        .get(key)
+        .and_then(Json::as_str)
+        .ok_or_else(|| failure(INVALID_PARAMS, format!("{key} is missing")))
+}
+
+fn name(note: &Path) -> String {
+    note.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn names(notes: &[PathBuf]) -> Json {
+    Json::Array(notes.iter().map(|x| Json::from(name(x))).collect())
+}
@@ -0,0 +1,919 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use confoosion_markdown_parser::{
+    error::ParseError, escape_html, frontmatter::FrontMatter, markdown_to_html, resolver::notes_in,
+    template::TemplateMap,
+};
+
+use crate::{
+    atomic,
+    site::{render_guarded, strip_tags},
+    watch::Changes,
+};
+
+/// Where [`SearchIndex::save`] keeps the index, relative to the vault.
+pub const INDEX_FILE: &str = ".confoosion/search-index";
+
+const HEADER: &[u8] = b"confoosion-search 2\n";
+
+// The usual BM25 parameters: how quickly repeated words stop counting, and how much long notes
+// are held back.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// How many words a snippet shows, and how many of them come before the first match.
+const SNIPPET_WORDS: usize = 24;
+const SNIPPET_LEAD: usize = 6;
+
+/// An inverted index over the text of the notes in a directory, for full-text search.
+///
+/// Notes are indexed by their rendered text with the markup stripped, so included templates
+/// are searched as well and link targets are not. A note is indexed again when a note or
+/// template it was rendered from changes. Queries are words that all have to match,
+/// with `"quoted phrases"` and `prefix*` words, and results are ranked with BM25.
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    directory: PathBuf,
+    ids: BTreeMap<PathBuf, u32>,
+    documents: BTreeMap<u32, Document>,
+    /// Every word, with the notes it is in and its positions in them, counted in words.
+    postings: BTreeMap<String, BTreeMap<u32, Vec<u32>>>,
+    next_id: u32,
+    total_words: u64,
+    /// The notes that could not be rendered, with why.
+    problems: BTreeMap<PathBuf, ParseError>,
+}
+
+#[derive(Debug, Clone)]
+struct Document {
+    note: PathBuf,
+    stamp: Stamp,
+    text: String,
+    words: u32,
+    /// The other files the text was rendered from, like included notes and templates.
+    sources: BTreeSet<PathBuf>,
+    /// Whether the text depends on the vault as a whole, like a list of backlinks, or has links
+    /// that any new note could make resolve.
+    vault: bool,
+    /// Whether the note could be rendered, or its source text was indexed instead.
+    rendered: bool,
+}
+
+/// What a note looked like on disk when it was indexed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Stamp {
+    modified: Duration,
+    length: u64,
+}
+
+/// A note that matches a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub note: PathBuf,
+    pub score: f64,
+    /// Some text around the best match, as HTML with the matching words in `<mark>`.
+    pub snippet: String,
+}
+
+impl SearchHit {
+    /// The snippet as plain text, without the marks.
+    pub fn plain_snippet(&self) -> String {
+        self.plain_snippet_with("", "")
+    }
+
+    /// The snippet as plain text, with the matches between `before` and `after`.
+    pub fn plain_snippet_with(&self, before: &str, after: &str) -> String {
+        let marked = self
+            .snippet
+            .replace("<mark>", "\u{1}")
+            .replace("</mark>", "\u{2}");
+        strip_tags(&marked)
+            .replace('\u{1}', before)
+            .replace('\u{2}', after)
+    }
+}
+
+/// One part of a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    Word(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+impl SearchIndex {
+    /// Indexes every note in `directory`.
+    pub fn build<P: AsRef<Path>>(directory: P, templates: &TemplateMap) -> Self {
+        let mut index = Self {
+            directory: directory.as_ref().to_path_buf(),
+            ..Self::default()
+        };
+        for note in notes_in(&index.directory) {
+            index.update(&note, templates);
+        }
+        index
+    }
+
+    /// Reads the index saved in `directory`, and brings it up to date with the notes that were
+    /// changed since, like [`SearchIndex::apply`]. Builds a new one if there is none, or it
+    /// cannot be read.
+    pub fn open<P: AsRef<Path>>(directory: P, templates: &TemplateMap) -> Self {
+        let directory = directory.as_ref();
+        let mut index = match fs::read(directory.join(INDEX_FILE)) {
+            Ok(bytes) => match Self::decode(directory, &bytes) {
+                Ok(x) => x,
+                Err(_) => return Self::build(directory, templates),
+            },
+            Err(_) => return Self::build(directory, templates),
+        };
+        let notes = notes_in(directory);
+        let removed = index
+            .ids
+            .keys()
+            .filter(|x| notes.binary_search(x).is_err())
+            .cloned()
+            .collect();
+        let mut unrendered = Vec::new();
+        let mut changed = Vec::new();
+        for note in notes {
+            match index.ids.get(&note).map(|x| &index.documents[x]) {
+                Some(document) if Some(document.stamp) == stamp(&note) => {
+                    if !document.rendered {
+                        unrendered.push(note);
+                    }
+                }
+                _ => changed.push(note),
+            }
+        }
+        index.apply(&Changes { changed, removed }, templates);
+        // Notes that could not be rendered are tried again, so that their problems are
+        // reported until they are fixed.
+        for note in unrendered {
+            index.update(&note, templates);
+        }
+        index
+    }
+
+    /// Writes the index to [`INDEX_FILE`] in its directory.
+    pub fn save(&self) -> io::Result<()> {
+        let path = self.directory.join(INDEX_FILE);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        atomic::write(path, self.encode())
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// The number of notes in the index.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Indexes `note` again, or drops it if it cannot be read any more.
+    pub fn update(&mut self, note: &Path, templates: &TemplateMap) {
+        self.remove(note);
+        let (source, stamp) = match (fs::read_to_string(note), stamp(note)) {
+            (Ok(source), Some(stamp)) => (source, stamp),
+            _ => return,
+        };
+        let mut document = Document {
+            note: note.to_path_buf(),
+            stamp,
+            text: String::new(),
+            words: 0,
+            sources: BTreeSet::new(),
+            vault: false,
+            rendered: true,
+        };
+        // A note that cannot be rendered is still found by the words in it.
+        match render_guarded(templates, || markdown_to_html(&source, note, templates)) {
+            Ok(parsed) => {
+                document.text = strip_tags(&parsed.html);
+                document.sources = parsed
+                    .dependencies
+                    .files()
+                    .into_iter()
+                    .filter(|x| *x != note)
+                    .map(Path::to_path_buf)
+                    .collect();
+                document.vault = parsed.dependencies.vault || !parsed.broken_links.is_empty();
+            }
+            Err(e) => {
+                self.problems.insert(note.to_path_buf(), e);
+                document.text = FrontMatter::split(&source).1.to_string();
+                document.rendered = false;
+            }
+        }
+        self.insert(document);
+    }
+
+    /// Drops `note` from the index.
+    pub fn remove(&mut self, note: &Path) {
+        self.problems.remove(note);
+        let id = match self.ids.remove(note) {
+            Some(x) => x,
+            None => return,
+        };
+        let document = match self.documents.remove(&id) {
+            Some(x) => x,
+            None => return,
+        };
+        self.total_words -= u64::from(document.words);
+        let words: BTreeSet<String> = words(&document.text).map(|(_, word)| word).collect();
+        for word in words {
+            if let Some(notes) = self.postings.get_mut(&word) {
+                notes.remove(&id);
+                if notes.is_empty() {
+                    self.postings.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// The notes that could not be rendered when they were indexed in this process, and are
+    /// searched by their source text instead.
+    pub fn problems(&self) -> &BTreeMap<PathBuf, ParseError> {
+        &self.problems
+    }
+
+    /// Brings the index up to date with what a [`Watcher`](crate::watch::Watcher) saw: the
+    /// changed notes, and the notes whose text was rendered from them.
+    pub fn apply(&mut self, changes: &Changes, templates: &TemplateMap) {
+        if changes.is_empty() {
+            return;
+        }
+        let touched: BTreeSet<&Path> = changes
+            .changed
+            .iter()
+            .chain(&changes.removed)
+            .map(PathBuf::as_path)
+            .collect();
+        let dependents: Vec<PathBuf> = self
+            .documents
+            .values()
+            .filter(|x| !touched.contains(x.note.as_path()))
+            .filter(|x| x.vault || x.sources.iter().any(|x| touched.contains(x.as_path())))
+            .map(|x| x.note.clone())
+            .collect();
+        for note in &changes.removed {
+            self.remove(note);
+        }
+        for note in changes.changed.iter().chain(&dependents) {
+            self.update(note, templates);
+        }
+    }
+
+    fn insert(&mut self, mut document: Document) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut count = 0;
+        for (position, (_, word)) in words(&document.text).enumerate() {
+            self.postings
+                .entry(word)
+                .or_default()
+                .entry(id)
+                .or_default()
+                .push(position as u32);
+            count += 1;
+        }
+        self.total_words += u64::from(count);
+        document.words = count;
+        self.ids.insert(document.note.clone(), id);
+        self.documents.insert(id, document);
+    }
+
+    /// The notes that match every part of `query`, best first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let terms = parse_query(query);
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        let mut scores: Option<BTreeMap<u32, f64>> = None;
+        for term in &terms {
+            let matched = self.score(term);
+            scores = Some(match scores {
+                None => matched,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(id, score)| matched.get(&id).map(|x| (id, score + x)))
+                    .collect(),
+            });
+        }
+        let mut ranked: Vec<(u32, f64)> = scores.unwrap_or_default().into_iter().collect();
+        ranked.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| self.documents[&a.0].note.cmp(&self.documents[&b.0].note))
+        });
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(id, score)| {
+                let document = &self.documents[&id];
+                SearchHit {
+                    note: document.note.clone(),
+                    score,
+                    snippet: snippet(&document.text, &terms),
+                }
+            })
+            .collect()
+    }
+
+    /// The BM25 score of one part of a query in each note it matches.
+    fn score(&self, term: &Term) -> BTreeMap<u32, f64> {
+        match term {
+            Term::Word(word) => match self.postings.get(word) {
+                Some(notes) => self.bm25(notes.iter().map(|(id, x)| (*id, x.len()))),
+                None => BTreeMap::new(),
+            },
+            // Each note counts with the best of the words that start with the prefix.
+            Term::Prefix(prefix) => {
+                let mut best = BTreeMap::new();
+                let words = self
+                    .postings
+                    .range(prefix.clone()..)
+                    .take_while(|(word, _)| word.starts_with(prefix.as_str()));
+                for (_, notes) in words {
+                    for (id, score) in self.bm25(notes.iter().map(|(id, x)| (*id, x.len()))) {
+                        let entry = best.entry(id).or_insert(score);
+                        *entry = score.max(*entry);
+                    }
+                }
+                best
+            }
+            Term::Phrase(words) => {
+                let lists: Option<Vec<&BTreeMap<u32, Vec<u32>>>> =
+                    words.iter().map(|x| self.postings.get(x)).collect();
+                let lists = match lists {
+                    Some(x) => x,
+                    None => return BTreeMap::new(),
+                };
+                let mut counts = Vec::new();
+                for (id, starts) in lists[0] {
+                    let count = starts
+                        .iter()
+                        .filter(|start| {
+                            lists[1..].iter().enumerate().all(|(offset, list)| {
+                                list.get(id).is_some_and(|positions| {
+                                    positions
+                                        .binary_search(&(*start + offset as u32 + 1))
+                                        .is_ok()
+                                })
+                            })
+                        })
+                        .count();
+                    if count > 0 {
+                        counts.push((*id, count));
+                    }
+                }
+                self.bm25(counts.into_iter())
+            }
+        }
+    }
+
+    /// Scores a term from how often it occurs in each note that has it.
+    fn bm25<I: ExactSizeIterator<Item = (u32, usize)>>(
+        &self,
+        frequencies: I,
+    ) -> BTreeMap<u32, f64> {
+        let notes = self.documents.len() as f64;
+        let containing = frequencies.len() as f64;
+        let idf = (1.0 + (notes - containing + 0.5) / (containing + 0.5)).ln();
+        let average = (self.total_words as f64 / notes.max(1.0)).max(1.0);
+        frequencies
+            .map(|(id, frequency)| {
+                let frequency = frequency as f64;
+                let length = f64::from(self.documents[&id].words);
+                let norm = K1 * (1.0 - B + B * length / average);
+                (id, idf * frequency * (K1 + 1.0) / (frequency + norm))
+            })
+            .collect()
+    }
+
+    /// The on-disk form: a header, then every note with its stamp, text and sources, then every
+    /// word with the notes it is in and its positions there. Words share their prefix with the one
+    /// before, and numbers are stored as differences from the one before in variable-length
+    /// bytes, which keeps the file close to the size of the text.
+    fn encode(&self) -> Vec<u8> {
+        let mut out = HEADER.to_vec();
+        // Notes are numbered afresh, in the same order, so the numbers stay small.
+        let renumbered: BTreeMap<u32, u64> = self
+            .documents
+            .keys()
+            .enumerate()
+            .map(|(new, old)| (*old, new as u64))
+            .collect();
+        put_number(&mut out, self.documents.len() as u64);
+        let relative = |file: &Path| {
+            let file = file.strip_prefix(&self.directory).unwrap_or(file);
+            file.to_string_lossy().to_string()
+        };
+        for document in self.documents.values() {
+            put_string(&mut out, &relative(&document.note));
+            put_number(&mut out, document.stamp.modified.as_secs());
+            put_number(&mut out, u64::from(document.stamp.modified.subsec_nanos()));
+            put_number(&mut out, document.stamp.length);
+            put_number(&mut out, u64::from(document.words));
+            put_string(&mut out, &document.text);
+            put_number(&mut out, document.sources.len() as u64);
+            for source in &document.sources {
+                put_string(&mut out, &relative(source));
+            }
+            put_number(&mut out, u64::from(document.vault));
+            put_number(&mut out, u64::from(document.rendered));
+        }
+        put_number(&mut out, self.postings.len() as u64);
+        let mut previous = "";
+        for (word, notes) in &self.postings {
+            let shared = previous
+                .char_indices()
+                .zip(word.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(previous.len().min(word.len()), |((at, _), _)| at);
+            put_number(&mut out, shared as u64);
+            put_string(&mut out, &word[shared..]);
+            put_number(&mut out, notes.len() as u64);
+            let mut previous_id = 0;
+            for (id, positions) in notes {
+                let id = renumbered[id];
+                put_number(&mut out, id - previous_id);
+                previous_id = id;
+                put_number(&mut out, positions.len() as u64);
+                let mut previous_position = 0;
+                for position in positions {
+                    put_number(&mut out, u64::from(position - previous_position));
+                    previous_position = *position;
+                }
+            }
+            previous = word;
+        }
+        out
+    }
+
+    fn decode(directory: &Path, bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader {
+            bytes: bytes
+                .strip_prefix(HEADER)
+                .ok_or_else(|| invalid("Unknown format"))?,
+        };
+        let mut index = Self {
+            directory: directory.to_path_buf(),
+            ..Self::default()
+        };
+        let count = reader.number()?;
+        for id in 0..count {
+            let id = u32::try_from(id).map_err(|_| invalid("Too many notes"))?;
+            let note = directory.join(reader.string()?);
+            let seconds = reader.number()?;
+            let nanos = u32::try_from(reader.number()?).map_err(|_| invalid("Bad time"))?;
+            let length = reader.number()?;
+            let words = u32::try_from(reader.number()?).map_err(|_| invalid("Bad length"))?;
+            let text = reader.string()?;
+            let mut sources = BTreeSet::new();
+            for _ in 0..reader.number()? {
+                sources.insert(directory.join(reader.string()?));
+            }
+            let vault = reader.number()? != 0;
+            let rendered = reader.number()? != 0;
+            index.total_words += u64::from(words);
+            index.ids.insert(note.clone(), id);
+            let stamp = Stamp {
+                modified: Duration::new(seconds, nanos),
+                length,
+            };
+            let document = Document {
+                note,
+                stamp,
+                text,
+                words,
+                sources,
+                vault,
+                rendered,
+            };
+            index.documents.insert(id, document);
+        }
+        index.next_id = index.documents.len() as u32;
+        let mut previous = String::new();
+        for _ in 0..reader.number()? {
+            let shared = reader.number()? as usize;
+            let mut word = previous
+                .get(..shared)
+                .ok_or_else(|| invalid("Bad word"))?
+                .to_string();
+            word.push_str(&reader.string()?);
+            let mut notes = BTreeMap::new();
+            let mut id = 0;
+            for _ in 0..reader.number()? {
+                id = u32::try_from(reader.number()?)
+                    .ok()
+                    .and_then(|x| x.checked_add(id))
+                    .ok_or_else(|| invalid("Bad note"))?;
+                if !index.documents.contains_key(&id) {
+                    return Err(invalid("Bad note"));
+                }
+                let mut positions = Vec::new();
+                let mut position = 0;
+                for _ in 0..reader.number()? {
+                    position = u32::try_from(reader.number()?)
+                        .ok()
+                        .and_then(|x| x.checked_add(position))
+                        .ok_or_else(|| invalid("Bad word"))?;
+                    positions.push(position);
+                }
+                notes.insert(id, positions);
+            }
+            index.postings.insert(word.clone(), notes);
+            previous = word;
+        }
+        Ok(index)
+    }
+}
+
+fn stamp(note: &Path) -> Option<Stamp> {
+    let metadata = note.metadata().ok()?;
+    let modified = metadata.modified().ok()?;
+    Some(Stamp {
+        modified: modified.duration_since(UNIX_EPOCH).unwrap_or_default(),
+        length: metadata.len(),
+    })
+}
+
+/// The words in `text`, lowercased, with the byte ranges they cover.
+fn words(text: &str) -> impl Iterator<Item = ((usize, usize), String)> + '_ {
+    let mut start = None;
+    text.char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+        .filter_map(
+            move |(at, character)| match (character.is_alphanumeric(), start) {
+                (true, None) => {
+                    start = Some(at);
+                    None
+                }
+                (false, Some(from)) => {
+                    start = None;
+                    Some(((from, at), text[from..at].to_lowercase()))
+                }
+                _ => None,
+            },
+        )
+}
+
+/// Reads a query: words, `"quoted phrases"`, and words ending in `*` that match as prefixes.
+/// Words joined by punctuation, like `well-known`, are a phrase as well.
+fn parse_query(query: &str) -> Vec<Term> {
+    let mut terms = Vec::new();
+    for (index, part) in query.split('"').enumerate() {
+        let quoted = index % 2 == 1;
+        let chunks = match quoted {
+            true => vec![part],
+            false => part.split_whitespace().collect(),
+        };
+        for chunk in chunks {
+            let mut found: Vec<String> = words(chunk).map(|(_, word)| word).collect();
+            let term = match found.len() {
+                0 => continue,
+                1 if !quoted && chunk.ends_with('*') => Term::Prefix(found.remove(0)),
+                1 => Term::Word(found.remove(0)),
+                _ => Term::Phrase(found),
+            };
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+    }
+    terms
+}
+
+/// The part of `text` with the most matches, with them in `<mark>`.
+fn snippet(text: &str, terms: &[Term]) -> String {
+    let found: Vec<((usize, usize), String)> = words(text).collect();
+    let mut marked = vec![false; found.len()];
+    for (index, (_, word)) in found.iter().enumerate() {
+        for term in terms {
+            match term {
+                Term::Word(x) => marked[index] |= word == x,
+                Term::Prefix(x) => marked[index] |= word.starts_with(x.as_str()),
+                Term::Phrase(phrase) => {
+                    let matches = found[index..]
+                        .iter()
+                        .map(|(_, x)| x)
+                        .take(phrase.len())
+                        .eq(phrase.iter());
+                    if matches {
+                        marked[index..index + phrase.len()].fill(true);
+                    }
+                }
+            }
+        }
+    }
+    if found.is_empty() {
+        return String::new();
+    }
+    let window = |first: usize| first.saturating_sub(SNIPPET_LEAD);
+    let first = (0..found.len())
+        .filter(|x| marked[*x])
+        .max_by_key(|x| {
+            let start = window(*x);
+            let count = marked[start..(start + SNIPPET_WORDS).min(found.len())]
+                .iter()
+                .filter(|x| **x)
+                .count();
+            // The earliest of the windows with the most matches.
+            (count, std::cmp::Reverse(*x))
+        })
+        .unwrap_or(0);
+    let start = window(first);
+    let end = (start + SNIPPET_WORDS).min(found.len());
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut copied = found[start].0 .0;
+    for index in start..end {
+        let (from, to) = found[index].0;
+        let gap = &text[copied..from];
+        if index > start && marked[index] && marked[index - 1] {
+            // Consecutive matches, like a phrase, share one mark.
+            push_text(&mut out, gap);
+        } else {
+            if index > start && marked[index - 1] {
+                out.push_str("</mark>");
+            }
+            push_text(&mut out, gap);
+            if marked[index] {
+                out.push_str("<mark>");
+            }
+        }
+        push_text(&mut out, &text[from..to]);
+        copied = to;
+    }
+    if marked[end - 1] {
+        out.push_str("</mark>");
+    }
+    match end < found.len() {
+        true => out.push('…'),
+        false => push_text(&mut out, text[copied..].trim_end()),
+    }
+    out
+}
+
+/// Escapes `text` for HTML, with every run of whitespace as one space.
+fn push_text(out: &mut String, text: &str) {
+    let mut collapsed = String::new();
+    for (index, part) in text.split_whitespace().enumerate() {
+        if index > 0 {
+            collapsed.push(' ');
+        }
+        collapsed.push_str(part);
+    }
+    if text.starts_with(char::is_whitespace) && !out.is_empty() {
+        collapsed.insert(0, ' ');
+    }
+    if text.ends_with(char::is_whitespace) && !collapsed.ends_with(' ') {
+        collapsed.push(' ');
+    }
+    out.push_str(&escape_html(&collapsed));
+}
+
+fn put_number(out: &mut Vec<u8>, mut number: u64) {
+    while number >= 0x80 {
+        out.push((number as u8) | 0x80);
+        number >>= 7;
+    }
+    out.push(number as u8);
+}
+
+fn put_string(out: &mut Vec<u8>, text: &str) {
+    put_number(out, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn number(&mut self) -> io::Result<u64> {
+        let mut number = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (byte, rest) = self
+                .bytes
+                .split_first()
+                .ok_or_else(|| invalid("Unexpected end"))?;
+            self.bytes = rest;
+            number |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(number);
+            }
+        }
+        Err(invalid("Number too long"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let length = usize::try_from(self.number()?).map_err(|_| invalid("Bad length"))?;
+        if length > self.bytes.len() {
+            return Err(invalid("Unexpected end"));
+        }
+        let (text, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        String::from_utf8(text.to_vec()).map_err(|_| invalid("Text is not UTF-8"))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn vault(name: &str, notes: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("confoosion-search-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for (note, source) in notes {
+            fs::write(directory.join(format!("{note}.md")), source).unwrap();
+        }
+        directory
+    }
+
+    fn found(index: &SearchIndex, query: &str) -> Vec<String> {
+        index
+            .search(query, 10)
+            .into_iter()
+            .map(|hit| hit.note.file_stem().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn notes_are_indexed_again_when_what_they_include_changes() {
+        let directory = vault("includes", &[("a", "{{include|b}}"), ("b", "zebrafish")]);
+        let templates = TemplateMap::with_builtins();
+        let mut index = SearchIndex::build(&directory, &templates);
+        assert_eq!(found(&index, "zebrafish"), ["a", "b"]);
+
+        let b = directory.join("b.md");
+        fs::write(&b, "giraffe").unwrap();
+        let changes = Changes {
+            changed: vec![b.clone()],
+            removed: Vec::new(),
+        };
+        index.apply(&changes, &templates);
+        assert!(found(&index, "zebrafish").is_empty());
+        assert_eq!(found(&index, "giraffe"), ["a", "b"]);
+
+        index.save().unwrap();
+        fs::write(&b, "okapi").unwrap();
+        let index = SearchIndex::open(&directory, &templates);
+        assert!(found(&index, "giraffe").is_empty());
+        assert_eq!(found(&index, "okapi"), ["a", "b"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn results_are_ranked_with_bm25() {
+        let directory = vault(
+            "ranking",
+            &[
+                ("a", "apple apple apple banana"),
+                ("b", "apple cherry cherry cherry cherry cherry cherry"),
+                ("c", "cherry"),
+            ],
+        );
+        let index = SearchIndex::build(&directory, &TemplateMap::with_builtins());
+        assert_eq!(found(&index, "apple"), ["a", "b"]);
+        assert_eq!(found(&index, "APPLE cherry"), ["b"]);
+        assert!(found(&index, "durian").is_empty());
+        let hits = index.search("cherry", 10);
+        assert_eq!(hits.len(), 2);
+        assert!(hits[0].score > hits[1].score, "{hits:?}");
+        assert_eq!(index.search("cherry", 1).len(), 1);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn phrases_and_prefixes() {
+        let directory = vault(
+            "phrases",
+            &[("a", "the quick brown fox"), ("b", "brown and quick fox")],
+        );
+        let index = SearchIndex::build(&directory, &TemplateMap::with_builtins());
+        assert_eq!(found(&index, "quick brown"), ["a", "b"]);
+        assert_eq!(found(&index, "\"quick brown\""), ["a"]);
+        assert_eq!(found(&index, "quick-brown"), ["a"]);
+        assert_eq!(found(&index, "\"brown quick\""), Vec::<String>::new());
+        assert_eq!(found(&index, "qui*"), ["a", "b"]);
+        assert!(found(&index, "qui").is_empty());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn snippets_mark_the_best_matches() {
+        let words: Vec<String> = (0..60).map(|x| format!("w{x}")).collect();
+        let directory = vault(
+            "snippets",
+            &[("a", "Alpha beta gamma delta"), ("b", &words.join(" "))],
+        );
+        let index = SearchIndex::build(&directory, &TemplateMap::with_builtins());
+        let hit = &index.search("\"beta gamma\" alpha", 1)[0];
+        assert_eq!(hit.snippet, "<mark>Alpha beta gamma</mark> delta");
+        assert_eq!(hit.plain_snippet(), "Alpha beta gamma delta");
+
+        let hit = &index.search("w30", 1)[0];
+        let expected = format!(
+            "…{} <mark>w30</mark> {}…",
+            words[24..30].join(" "),
+            words[31..48].join(" ")
+        );
+        assert_eq!(hit.snippet, expected);
+        assert_eq!(
+            hit.plain_snippet_with("[", "]"),
+            expected.replace("<mark>", "[").replace("</mark>", "]")
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn the_saved_index_reads_back_the_same() {
+        let directory = vault(
+            "round-trip",
+            &[
+                ("a", "# Über\n\nsee [[b]] and übermut überall"),
+                ("b", "{{backlinks}}"),
+                ("c", "{{missing_template}}"),
+            ],
+        );
+        let index = SearchIndex::build(&directory, &TemplateMap::with_builtins());
+        let bytes = index.encode();
+        let read = SearchIndex::decode(&directory, &bytes).unwrap();
+        assert_eq!(read.ids, index.ids);
+        assert_eq!(read.postings, index.postings);
+        assert_eq!(read.total_words, index.total_words);
+        assert_eq!(read.next_id, index.next_id);
+        for (id, document) in &index.documents {
+            let other = &read.documents[id];
+            assert_eq!(other.note, document.note);
+            assert_eq!(other.stamp, document.stamp);
+            assert_eq!(other.text, document.text);
+            assert_eq!(other.words, document.words);
+            assert_eq!(other.sources, document.sources);
+            assert_eq!(other.vault, document.vault);
+            assert_eq!(other.rendered, document.rendered);
+        }
+        assert!(index.documents[&index.ids[&directory.join("b.md")]].vault);
+        assert!(!index.documents[&index.ids[&directory.join("c.md")]].rendered);
+        assert_eq!(read.encode(), bytes);
+
+        assert!(SearchIndex::decode(&directory, &bytes[..bytes.len() - 1]).is_err());
+        assert!(SearchIndex::decode(&directory, b"confoosion-search 0\n").is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn numbers_that_overflow_are_rejected() {
+        // One empty note, and the word `x` in it at the given note and position differences.
+        let index = |notes: &[u64], positions: &[u64]| {
+            let mut out = HEADER.to_vec();
+            put_number(&mut out, 1);
+            put_string(&mut out, "a.md");
+            for number in [0, 0, 0, 0] {
+                put_number(&mut out, number);
+            }
+            put_string(&mut out, "");
+            for number in [0, 0, 1, 1, 0] {
+                put_number(&mut out, number);
+            }
+            put_string(&mut out, "x");
+            put_number(&mut out, notes.len() as u64);
+            for note in notes {
+                put_number(&mut out, *note);
+                put_number(&mut out, positions.len() as u64);
+                for position in positions {
+                    put_number(&mut out, *position);
+                }
+            }
+            SearchIndex::decode(Path::new("vault"), &out)
+        };
+        assert!(index(&[0], &[0, 1]).is_ok());
+        let e = index(&[0, u64::from(u32::MAX)], &[0]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(index(&[0], &[1, u64::from(u32::MAX)]).is_err());
+        assert!(index(&[0], &[u64::MAX]).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use confoosion_markdown_parser::escape_html;
+
+use crate::{
+    site::{percent_decode, BuildReport, Site},
+    watch::Watcher,
+};
+
+/// Where a page asks whether the vault changed since it was loaded.
+const CHANGES: &str = "__changes";
+const MAX_REQUEST: usize = 16 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A preview of a vault over HTTP, for browsing the notes before there is a frontend.
+///
+/// Every page is rendered when it is asked for, and links between notes point at the other
+/// pages. The server answers one request at a time, and looks for changed notes before each.
+/// Pages ask for `/__changes` every second and reload when the answer differs from the one
+/// they were served with.
+pub struct Server {
+    site: Site,
+    watcher: Watcher,
+    /// Counts how often the vault changed, which is what pages compare to know when to reload.
+    version: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn html(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "text/html; charset=utf-8",
+            body: body.into_bytes(),
+        }
+    }
+
+    fn not_found(path: &str) -> Self {
+        Self::html(
+            404,
+            format!(
+                "<!DOCTYPE html>\n<title>Not found</title>\n<p>There is nothing at /{}.</p>\n<p><a href=\"/index.html\">Index</a></p>\n",
+                escape_html(path)
+            ),
+        )
+    }
+}
+
+impl Server {
+    /// Serves the notes `site` was opened on. Only changes made after this call are noticed.
+    pub fn new(site: Site) -> io::Result<Self> {
+        let watcher = Watcher::new(site.vault().directory())?;
+        Ok(Self {
+            site,
+            watcher,
+            version: 0,
+        })
+    }
+
+    pub fn site(&self) -> &Site {
+        &self.site
+    }
+
+    /// Answers requests until accepting a connection fails.
+    pub fn run(&mut self, listener: &TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            // A client that goes away halfway is its own problem, not the server's.
+            if let Err(e) = self.handle(stream?) {
+                eprintln!("Could not answer a request: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn handle(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let request = read_request(&mut stream)?;
+        let mut words = request.lines().next().unwrap_or_default().split(' ');
+        let (method, target) = (words.next().unwrap_or_default(), words.next());
+        let response = match (method, target) {
+            ("GET" | "HEAD", Some(target)) => self.respond(target),
+            (_, Some(_)) => Response::html(405, "Only GET and HEAD are supported\n".to_string()),
+            _ => Response::html(400, "Malformed request\n".to_string()),
+        };
+        let header = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+            response.status,
+            reason(response.status),
+            response.content_type,
+            response.body.len()
+        );
+        stream.write_all(header.as_bytes())?;
+        if method != "HEAD" {
+            stream.write_all(&response.body)?;
+        }
+        stream.flush()
+    }
+
+    /// The response to a `GET` of `target`, such as `/my-note.html?x=1`.
+    pub fn respond(&mut self, target: &str) -> Response {
+        self.catch_up();
+        let path = target.split(['?', '#']).next().unwrap_or_default();
+        let path = percent_decode(path.trim_start_matches('/'));
+        if path == CHANGES {
+            return Response {
+                status: 200,
+                content_type: "text/plain; charset=utf-8",
+                body: self.version.to_string().into_bytes(),
+            };
+        }
+        if path.is_empty() || path == "index.html" {
+            let page = self.site.render_index();
+            return Response::html(200, self.live(page));
+        }
+        if let Some(tag) = self.site.tag_at(&path) {
+            let page = self.site.render_tag(&tag);
+            return Response::html(200, self.live(page));
+        }
+        if let Some(note) = self.site.note_at(&path) {
+            let mut report = BuildReport::default();
+            let rendered = self.site.render_note(&note, &mut report);
+            for message in report.errors.iter().chain(&report.warnings) {
+                eprintln!("{message}");
+            }
+            return match rendered {
+                Some((page, _)) => Response::html(200, self.live(page)),
+                None => {
+                    let mut page = String::from(
+                        "<!DOCTYPE html>\n<title>Error</title>\n<h1>This note could not be rendered</h1>\n<pre>\n",
+                    );
+                    for message in &report.errors {
+                        page.push_str(&escape_html(message));
+                        page.push('\n');
+                    }
+                    page.push_str("</pre>\n");
+                    Response::html(500, self.live(page))
+                }
+            };
+        }
+        match self.file(&path) {
+            Some(file) => match std::fs::read(&file) {
+                Ok(body) => Response {
+                    status: 200,
+                    content_type: content_type(&file),
+                    body,
+                },
+                Err(_) => Response::not_found(&path),
+            },
+            None => Response::not_found(&path),
+        }
+    }
+
+    /// Takes in the notes that changed since the last request.
+    fn catch_up(&mut self) {
+        let changes = match self.watcher.poll() {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("Could not look for changes: {e}");
+                return;
+            }
+        };
+        if changes.is_empty() {
+            return;
+        }
+        let mut report = BuildReport::default();
+        self.site.refresh(&changes, &mut report);
+        for message in &report.errors {
+            eprintln!("{message}");
+        }
+        self.version += 1;
+    }
+
+    /// A file in the vault, such as an image, as long as it is not hidden and does not lead
+    /// out of the vault.
+    fn file(&self, path: &str) -> Option<PathBuf> {
+        let relative = Path::new(path);
+        let visible = relative.components().all(|component| match component {
+            Component::Normal(x) => !x.to_string_lossy().starts_with('.'),
+            _ => false,
+        });
+        if path.is_empty() || !visible {
+            return None;
+        }
+        let file = self.site.vault().directory().join(relative);
+        file.is_file().then_some(file)
+    }
+
+    /// Adds the script that reloads the page when the vault changes.
+    fn live(&self, mut page: String) -> String {
+        let script = format!(
+            "<script>\n(function () {{\n  var version = \"{}\";\n  setInterval(function () {{\n    fetch(\"/{CHANGES}\").then(function (response) {{ return response.text(); }}).then(function (latest) {{\n      if (latest !== version) {{ location.reload(); }}\n    }}).catch(function () {{}});\n  }}, 1000);\n}})();\n</script>\n",
+            self.version
+        );
+        match page.rfind("</body>") {
+            Some(index) => page.insert_str(index, &script),
+            None => page.push_str(&script),
+        }
+        page
+    }
+}
+
+/// Reads up to the end of the request headers. Requests have no body worth reading here.
+fn read_request(stream: &mut TcpStream) -> io::Result<String> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|x| x == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    Ok(String::from_utf8_lossy(&request).to_string())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+fn content_type(file: &Path) -> &'static str {
+    let extension = file
+        .extension()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "md" | "txt" => "text/plain; charset=utf-8",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,943 @@
+use std::{
+    cell::Ref,
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use confoosion_markdown_parser::{
+    dependencies::Dependencies,
+    error::ParseError,
+    escape_html,
+    links::{tag_slug, RelativeHtmlLinks},
+    markdown_file_to_html,
+    template::TemplateMap,
+    vault::VaultQuery,
+    ParsedHTML,
+};
+
+use crate::{
+    atomic,
+    vault::{NoteEntry, SharedVault, Vault},
+    watch::Changes,
+};
+
+/// The page every note is wrapped in unless a skeleton is given. See [`SiteOptions::skeleton`].
+pub const DEFAULT_SKELETON: &str = "\
+<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>$title$</title>
+</head>
+<body>
+<nav><a href=\"$root$index.html\">Index</a></nav>
+<main>
+$body$
+</main>
+$tags$
+$backlinks$
+</body>
+</html>
+";
+
+const TAG_DIRECTORY: &str = "tags";
+
+#[derive(Debug, Clone)]
+pub struct SiteOptions {
+    pub output: PathBuf,
+    /// The HTML page every note is placed in. `$title$` is replaced with the plain-text
+    /// title, `$body$` with the rendered note, `$tags$` and `$backlinks$` with lists of links,
+    /// and `$root$` with the relative path to the top of the site, such as `../`.
+    pub skeleton: String,
+}
+
+impl Default for SiteOptions {
+    fn default() -> Self {
+        Self {
+            output: PathBuf::from("out"),
+            skeleton: DEFAULT_SKELETON.to_string(),
+        }
+    }
+}
+
+/// What a build did. Errors mean a note could not be published; warnings mean it was
+/// published with something wrong in it.
+#[derive(Debug, Clone, Default)]
+pub struct BuildReport {
+    pub pages: usize,
+    pub images: usize,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Publishes the notes in one directory as a static HTML site: one page per note, an index,
+/// a page per tag, and the local images the notes show.
+pub struct Site {
+    templates: TemplateMap,
+    vault: SharedVault,
+    options: SiteOptions,
+    /// What every published page was built from, to know which pages a change affects.
+    pages: BTreeMap<PathBuf, Page>,
+}
+
+/// The pages that have to be rendered again after a change. See [`Site::refresh`].
+#[derive(Debug, Clone, Default)]
+pub struct Outdated {
+    pub notes: BTreeSet<PathBuf>,
+    /// Whether the index and tag pages changed.
+    pub indexes: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Page {
+    dependencies: Dependencies,
+    /// Whether a link did not resolve, so that a new note or alias may change the page.
+    broken_links: bool,
+}
+
+impl Site {
+    /// Indexes `directory`. The templates' links are pointed at the generated pages.
+    pub fn new<P: AsRef<Path>>(
+        directory: P,
+        mut templates: TemplateMap,
+        options: SiteOptions,
+    ) -> io::Result<Self> {
+        templates.set_link_generator(Box::new(RelativeHtmlLinks));
+        let vault = SharedVault::new(Vault::scan(directory, &templates)?);
+        templates.set_vault(Rc::new(vault.clone()));
+        Ok(Self {
+            templates,
+            vault,
+            options,
+            pages: BTreeMap::new(),
+        })
+    }
+
+    pub fn vault(&self) -> Ref<'_, Vault> {
+        self.vault.borrow()
+    }
+
+    pub fn options(&self) -> &SiteOptions {
+        &self.options
+    }
+
+    /// Where the page for `note` is written.
+    pub fn page_path<P: AsRef<Path>>(&self, note: P) -> PathBuf {
+        self.options
+            .output
+            .join(self.templates.link_generator().url(note.as_ref()))
+    }
+
+    /// The notes that get a page of their own, which leaves out template notes.
+    pub fn published_notes(&self) -> Vec<PathBuf> {
+        self.vault
+            .notes()
+            .into_iter()
+            .filter(|note| self.is_published(note))
+            .collect()
+    }
+
+    pub fn is_published(&self, note: &Path) -> bool {
+        !note.file_name().is_some_and(|x| {
+            x.to_string_lossy()
+                .starts_with(self.templates.template_prefix())
+        })
+    }
+
+    pub fn build(&mut self) -> io::Result<BuildReport> {
+        fs::create_dir_all(&self.options.output)?;
+        self.pages.clear();
+        let mut report = BuildReport::default();
+        let mut pages: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+        let notes = self.published_notes();
+        for note in &notes {
+            let page = self.page_path(note);
+            if let Some(other) = pages.insert(page.clone(), note.clone()) {
+                report.errors.push(format!(
+                    "{}: error: its page {} is already taken by {}",
+                    note.display(),
+                    page.display(),
+                    other.display()
+                ));
+                continue;
+            }
+            self.build_note(note, &mut report)?;
+        }
+        self.build_indexes(&mut report)?;
+        Ok(report)
+    }
+
+    /// Renders one note to its page and copies the images it shows.
+    pub fn build_note<P: AsRef<Path>>(
+        &mut self,
+        note: P,
+        report: &mut BuildReport,
+    ) -> io::Result<()> {
+        let note = note.as_ref();
+        if let Some((page, parsed)) = self.render_note(note, report) {
+            self.copy_images(note, &parsed, report)?;
+            atomic::write(self.page_path(note), page)?;
+            report.pages += 1;
+        }
+        Ok(())
+    }
+
+    /// Renders one note into the page skeleton, and returns the page along with the note as
+    /// rendered. Returns `None` when the note has errors, which are added to `report`.
+    pub fn render_note<P: AsRef<Path>>(
+        &mut self,
+        note: P,
+        report: &mut BuildReport,
+    ) -> Option<(String, ParsedHTML)> {
+        let note = note.as_ref();
+        let parsed = match render_file(note, &self.templates) {
+            Ok(x) => x,
+            Err(e) => {
+                // Whatever the note is fixed with decides what it depends on.
+                self.pages.insert(
+                    note.to_path_buf(),
+                    Page {
+                        dependencies: Dependencies::new(),
+                        broken_links: true,
+                    },
+                );
+                report.errors.push(format!(
+                    "{}:{}:{}: error: {}",
+                    note.display(),
+                    e.line(),
+                    e.column(),
+                    e.comment
+                ));
+                return None;
+            }
+        };
+        for diagnostic in &parsed.diagnostics {
+            report.warnings.push(format!(
+                "{}:{}:{}: warning: {}",
+                note.display(),
+                diagnostic.line(),
+                diagnostic.column(),
+                diagnostic.comment
+            ));
+        }
+        for link in &parsed.broken_links {
+            report.warnings.push(format!(
+                "{}: warning: broken link [[{link}]]",
+                note.display()
+            ));
+        }
+        let page = self.page(note, &parsed.html, "");
+        self.pages.insert(
+            note.to_path_buf(),
+            Page {
+                broken_links: !parsed.broken_links.is_empty(),
+                dependencies: parsed.dependencies.clone(),
+            },
+        );
+        Some((page, parsed))
+    }
+
+    /// The published note whose page is at `url`, relative to the top of the site.
+    pub fn note_at(&self, url: &str) -> Option<PathBuf> {
+        self.published_notes()
+            .into_iter()
+            .find(|note| self.templates.link_generator().url(note) == url)
+    }
+
+    /// Brings the site up to date after notes changed on disk, rendering only the pages the
+    /// changes can show up on.
+    ///
+    /// Those are the changed notes, the pages that link to, include or show the title of a
+    /// changed note, the pages that list a changed note as a backlink, and, when notes appear,
+    /// disappear or change their names, the pages with broken links and those that query the
+    /// whole vault. The index and tag pages are only written again when a title, a tag or the
+    /// set of notes changed.
+    pub fn update(&mut self, changes: &Changes) -> io::Result<BuildReport> {
+        let mut report = BuildReport::default();
+        for note in &changes.removed {
+            if self.pages.contains_key(note) {
+                let page = self.page_path(note);
+                if page.exists() {
+                    atomic::remove(page)?;
+                }
+            }
+        }
+        let outdated = self.refresh(changes, &mut report);
+        for note in outdated.notes {
+            if self.is_published(&note) && self.vault.borrow().contains(&note) {
+                // One page that cannot be written should not stop the others from updating.
+                if let Err(e) = self.build_note(&note, &mut report) {
+                    report.errors.push(format!(
+                        "{}: error: could not write its page: {e}",
+                        note.display()
+                    ));
+                }
+            }
+        }
+        if outdated.indexes {
+            self.build_indexes(&mut report)?;
+        }
+        Ok(report)
+    }
+
+    /// Brings the index up to date after notes changed on disk, without writing anything,
+    /// and says which pages [`Site::update`] has to render again.
+    pub fn refresh(&mut self, changes: &Changes, report: &mut BuildReport) -> Outdated {
+        let mut diffs: BTreeMap<PathBuf, (Option<NoteEntry>, Option<NoteEntry>)> = BTreeMap::new();
+        for note in &changes.removed {
+            let old = self.vault.borrow_mut().remove(note, &self.templates);
+            diffs.insert(note.clone(), (old, None));
+            self.pages.remove(note);
+        }
+        for note in &changes.changed {
+            self.reindex(note, &mut diffs, report);
+        }
+
+        let names_changed = diffs.values().any(|(old, new)| match (old, new) {
+            (Some(old), Some(new)) => {
+                old.title != new.title || old.front_matter != new.front_matter
+            }
+            _ => true,
+        });
+        let vault_changed = diffs.values().any(|(old, new)| old != new);
+        let mut affected: BTreeSet<PathBuf> = changes.changed.iter().cloned().collect();
+        for (note, page) in &self.pages {
+            if diffs.keys().any(|x| page.dependencies.depends_on(x))
+                || (names_changed && page.broken_links)
+                || (vault_changed && page.dependencies.vault)
+            {
+                affected.insert(note.clone());
+            }
+        }
+        // Links that did not resolve before may do so now, which changes the link graph.
+        if names_changed {
+            for note in &affected {
+                if !diffs.contains_key(note) {
+                    self.reindex(note, &mut diffs, report);
+                }
+            }
+        }
+        for (old, new) in diffs.values() {
+            let old_links = old.as_ref().map(|x| x.links.as_slice()).unwrap_or_default();
+            let new_links = new.as_ref().map(|x| x.links.as_slice()).unwrap_or_default();
+            let title_changed = old.as_ref().map(|x| &x.title) != new.as_ref().map(|x| &x.title);
+            for target in old_links.iter().chain(new_links) {
+                if title_changed || old_links.contains(target) != new_links.contains(target) {
+                    affected.insert(target.clone());
+                }
+            }
+        }
+
+        let indexes = diffs.values().any(|(old, new)| match (old, new) {
+            (Some(old), Some(new)) => old.title != new.title || old.tags != new.tags,
+            _ => true,
+        });
+        Outdated {
+            notes: affected,
+            indexes,
+        }
+    }
+
+    /// Reads a note into the index again and remembers how its entry changed.
+    fn reindex(
+        &self,
+        note: &Path,
+        diffs: &mut BTreeMap<PathBuf, (Option<NoteEntry>, Option<NoteEntry>)>,
+        report: &mut BuildReport,
+    ) {
+        let old = self.vault.borrow().entry(note).cloned();
+        if let Err(e) = self.vault.update(note, &self.templates) {
+            report
+                .errors
+                .push(format!("{}: error: could not read: {e}", note.display()));
+        }
+        let new = self.vault.borrow().entry(note).cloned();
+        let old = match diffs.remove(note) {
+            Some((older, _)) => older,
+            None => old,
+        };
+        diffs.insert(note.to_path_buf(), (old, new));
+    }
+
+    /// Writes `index.html` and a page for every tag.
+    pub fn build_indexes(&self, report: &mut BuildReport) -> io::Result<()> {
+        atomic::write(self.options.output.join("index.html"), self.render_index())?;
+        report.pages += 1;
+        let tags = self.vault.borrow().all_tags();
+        if tags.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(self.options.output.join(TAG_DIRECTORY))?;
+        for tag in &tags {
+            atomic::write(
+                self.options.output.join(tag_url(tag, "")),
+                self.render_tag(tag),
+            )?;
+            report.pages += 1;
+        }
+        Ok(())
+    }
+
+    /// The page listing every published note and every tag.
+    pub fn render_index(&self) -> String {
+        let mut body = String::from("<h1>Index</h1>\n");
+        body.push_str(&self.note_list(&self.published_notes(), ""));
+        let tags = self.vault.borrow().all_tags();
+        if !tags.is_empty() {
+            body.push_str("<h2>Tags</h2>\n<ul>\n");
+            for tag in &tags {
+                body.push_str(&format!(
+                    "<li><a href=\"{}\">{}</a></li>\n",
+                    escape_html(&tag_url(tag, "")),
+                    escape_html(tag)
+                ));
+            }
+            body.push_str("</ul>\n");
+        }
+        fill(
+            &self.options.skeleton,
+            &[
+                ("title", "Index"),
+                ("body", &body),
+                ("root", ""),
+                ("tags", ""),
+                ("backlinks", ""),
+            ],
+        )
+    }
+
+    /// The page listing the published notes with `tag`, which lives under `tags/`.
+    pub fn render_tag(&self, tag: &str) -> String {
+        let tagged: Vec<PathBuf> = self
+            .vault
+            .tagged(tag)
+            .into_iter()
+            .filter(|note| self.is_published(note))
+            .collect();
+        let mut body = format!("<h1>Tag: {}</h1>\n", escape_html(tag));
+        body.push_str(&self.note_list(&tagged, "../"));
+        let title = format!("Tag: {tag}");
+        fill(
+            &self.options.skeleton,
+            &[
+                ("title", &escape_html(&title)),
+                ("body", &body),
+                ("root", "../"),
+                ("tags", ""),
+                ("backlinks", ""),
+            ],
+        )
+    }
+
+    /// The tag whose page is at `url`, relative to the top of the site.
+    pub fn tag_at(&self, url: &str) -> Option<String> {
+        self.vault
+            .borrow()
+            .all_tags()
+            .into_iter()
+            .find(|tag| tag_url(tag, "") == url)
+    }
+
+    fn page(&self, note: &Path, body: &str, root: &str) -> String {
+        let title = self.title(note);
+        let tags = self.vault.tags(note);
+        let tag_list = match tags.is_empty() {
+            true => String::new(),
+            false => {
+                let mut out = String::from("<ul class=\"tags\">\n");
+                for tag in &tags {
+                    out.push_str(&format!(
+                        "<li><a href=\"{}\">{}</a></li>\n",
+                        escape_html(&tag_url(tag, root)),
+                        escape_html(tag)
+                    ));
+                }
+                out.push_str("</ul>");
+                out
+            }
+        };
+        let backlinks = self.vault.backlinks(note);
+        let backlink_list = match backlinks.is_empty() {
+            true => String::new(),
+            false => format!(
+                "<section class=\"backlinks\">\n<h2>Linked from</h2>\n{}</section>",
+                self.note_list(&backlinks, root)
+            ),
+        };
+        fill(
+            &self.options.skeleton,
+            &[
+                ("title", &escape_html(&strip_tags(&title))),
+                ("body", body),
+                ("root", root),
+                ("tags", &tag_list),
+                ("backlinks", &backlink_list),
+            ],
+        )
+    }
+
+    /// The HTML title of a note, or its file name when it has none.
+    fn title(&self, note: &Path) -> String {
+        match self.vault.title(note) {
+            Some(title) => title,
+            None => escape_html(&note.file_stem().unwrap_or_default().to_string_lossy()),
+        }
+    }
+
+    fn note_list(&self, notes: &[PathBuf], root: &str) -> String {
+        let mut out = String::from("<ul>\n");
+        for note in notes {
+            out.push_str(&format!(
+                "<li><a href=\"{}{}\">{}</a></li>\n",
+                root,
+                escape_html(&self.templates.link_generator().url(note)),
+                self.title(note)
+            ));
+        }
+        out.push_str("</ul>\n");
+        out
+    }
+
+    /// Copies the images a note shows from next to the note to next to its page. Remote
+    /// images, and paths that lead out of the vault, are left alone.
+    fn copy_images(
+        &self,
+        note: &Path,
+        parsed: &ParsedHTML,
+        report: &mut BuildReport,
+    ) -> io::Result<()> {
+        let directory = self.vault.borrow().directory().to_path_buf();
+        for url in &parsed.images {
+            let relative = match local_path(url) {
+                Some(x) => x,
+                None => continue,
+            };
+            let source = directory.join(&relative);
+            if !source.is_file() {
+                report.warnings.push(format!(
+                    "{}: warning: image {url} does not exist",
+                    note.display()
+                ));
+                continue;
+            }
+            let target = self.options.output.join(&relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let contents = fs::read(&source)?;
+            atomic::write(&target, contents)?;
+            report.images += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces every `$name$` in `skeleton` in one pass, so replacements are never looked into.
+fn fill(skeleton: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::new();
+    let mut rest = skeleton;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('$').and_then(|end| {
+            values
+                .iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(name, value)| (name.len(), value))
+        });
+        match value {
+            Some((length, value)) => {
+                out.push_str(value);
+                rest = &after[length + 1..];
+            }
+            None => {
+                out.push('$');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn tag_url(tag: &str, root: &str) -> String {
+    format!("{root}{TAG_DIRECTORY}/{}.html", tag_slug(tag))
+}
+
+/// The path an image source refers to inside the vault, or `None` for anything else.
+fn local_path(url: &str) -> Option<PathBuf> {
+    if url.contains("://") || url.starts_with("data:") || url.starts_with("//") {
+        return None;
+    }
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    let path = PathBuf::from(percent_decode(url));
+    match path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        true if !url.is_empty() => Some(path),
+        _ => None,
+    }
+}
+
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|x| std::str::from_utf8(x).ok())
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Renders `note` like [`markdown_file_to_html`], except that a crash in the parser becomes an
+/// error, so that one note cannot take down a command that renders many.
+pub(crate) fn render_file(note: &Path, templates: &TemplateMap) -> Result<ParsedHTML, ParseError> {
+    render_guarded(templates, || markdown_file_to_html(note, templates))
+}
+
+/// Runs `render`, turning a panic into an error.
+pub(crate) fn render_guarded<T>(
+    templates: &TemplateMap,
+    render: impl FnOnce() -> Result<T, ParseError>,
+) -> Result<T, ParseError> {
+    match catch_unwind(AssertUnwindSafe(render)) {
+        Ok(result) => result,
+        Err(_) => {
+            templates.abandon_render();
+            Err(ParseError::empty("The parser crashed on this note"))
+        }
+    }
+}
+
+pub(crate) fn strip_tags(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for character in html.chars() {
+        match character {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => out.push(character),
+            _ => (),
+        }
+    }
+    unescape_html(&out)
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        process,
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+
+    /// A vault of its own for each test, in `notes` next to the site in `out`. The notes are
+    /// dated in the past, so that changing them later is sure to change their times.
+    fn new_site(name: &str, notes: &[(&str, &str)]) -> (PathBuf, Site) {
+        let root = std::env::temp_dir().join(format!("confoosion-site-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let directory = root.join("notes");
+        fs::create_dir_all(&directory).unwrap();
+        for (note, source) in notes {
+            let file = directory.join(format!("{note}.md"));
+            fs::write(&file, source).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&file)
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(100))
+                .unwrap();
+        }
+        let options = SiteOptions {
+            output: root.join("out"),
+            ..SiteOptions::default()
+        };
+        let site = Site::new(&directory, TemplateMap::with_builtins(), options).unwrap();
+        (directory, site)
+    }
+
+    const NOTES: &[(&str, &str)] = &[
+        ("a", "# Alpha\n\nSee [[b]]."),
+        ("b", "# Bravo\n\nText."),
+        ("c", "# Charlie\n\nAlone. #solo"),
+        ("d", "# Delta\n\n{{backlinks|b}}"),
+        ("e", "# Echo\n\n{{shout|hi}}"),
+        ("Template:shout", "**{{{1}}}**"),
+    ];
+
+    /// Changes `note` on disk and says what [`Site::refresh`] makes of it.
+    fn refresh(site: &mut Site, directory: &Path, note: &str, source: Option<&str>) -> Outdated {
+        let file = directory.join(format!("{note}.md"));
+        let changes = match source {
+            Some(source) => {
+                fs::write(&file, source).unwrap();
+                Changes {
+                    changed: vec![file],
+                    removed: Vec::new(),
+                }
+            }
+            None => {
+                fs::remove_file(&file).unwrap();
+                Changes {
+                    changed: Vec::new(),
+                    removed: vec![file],
+                }
+            }
+        };
+        site.refresh(&changes, &mut BuildReport::default())
+    }
+
+    fn names(outdated: &Outdated) -> Vec<String> {
+        outdated
+            .notes
+            .iter()
+            .map(|x| x.file_stem().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    fn page(site: &Site, directory: &Path, note: &str) -> String {
+        fs::read_to_string(site.page_path(directory.join(format!("{note}.md")))).unwrap()
+    }
+
+    #[test]
+    fn refresh_finds_the_pages_a_change_shows_up_on() {
+        let (directory, mut site) = new_site("refresh-text", NOTES);
+        site.build().unwrap();
+        let outdated = refresh(&mut site, &directory, "b", Some("# Bravo\n\nMore text."));
+        assert_eq!(names(&outdated), ["a", "b"]);
+        assert!(!outdated.indexes);
+
+        let (directory, mut site) = new_site("refresh-title", NOTES);
+        site.build().unwrap();
+        let outdated = refresh(&mut site, &directory, "b", Some("# Bravo two\n\nText."));
+        assert_eq!(names(&outdated), ["a", "b", "d"]);
+        assert!(outdated.indexes);
+
+        let (directory, mut site) = new_site("refresh-template", NOTES);
+        site.build().unwrap();
+        let outdated = refresh(&mut site, &directory, "Template:shout", Some("*{{{1}}}*"));
+        assert_eq!(names(&outdated), ["Template:shout", "e"]);
+
+        let (directory, mut site) = new_site("refresh-create", NOTES);
+        site.build().unwrap();
+        let outdated = refresh(
+            &mut site,
+            &directory,
+            "f",
+            Some("# Foxtrot\n\n[[a]], [[c]]"),
+        );
+        assert_eq!(names(&outdated), ["a", "c", "d", "f"]);
+        assert!(outdated.indexes);
+
+        let (directory, mut site) = new_site("refresh-delete", NOTES);
+        site.build().unwrap();
+        let outdated = refresh(&mut site, &directory, "a", None);
+        assert_eq!(names(&outdated), ["b", "d"]);
+        assert!(outdated.indexes);
+    }
+
+    #[test]
+    fn update_rewrites_the_pages_a_change_shows_up_on() {
+        let (directory, mut site) = new_site("update", NOTES);
+        let report = site.build().unwrap();
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(page(&site, &directory, "e").contains("<b>hi</b>"));
+
+        let template = directory.join("Template:shout.md");
+        fs::write(&template, "*{{{1}}}*").unwrap();
+        let changes = Changes {
+            changed: vec![template],
+            removed: Vec::new(),
+        };
+        let report = site.update(&changes).unwrap();
+        assert_eq!(report.pages, 1);
+        assert!(page(&site, &directory, "e").contains("<i>hi</i>"));
+
+        let f = directory.join("f.md");
+        fs::write(&f, "# Foxtrot\n\n[[a]], [[c]]").unwrap();
+        let changes = Changes {
+            changed: vec![f],
+            removed: Vec::new(),
+        };
+        site.update(&changes).unwrap();
+        assert!(page(&site, &directory, "f").contains("Foxtrot"));
+        assert!(page(&site, &directory, "a").contains("Linked from"));
+        assert!(page(&site, &directory, "c").contains("Foxtrot"));
+
+        let c = directory.join("c.md");
+        let c_page = site.page_path(&c);
+        fs::remove_file(&c).unwrap();
+        let changes = Changes {
+            changed: Vec::new(),
+            removed: vec![c],
+        };
+        let report = site.update(&changes).unwrap();
+        assert!(!c_page.exists());
+        assert!(report
+            .warnings
+            .iter()
+            .any(|x| x.contains("broken link [[c]]")));
+        let index = fs::read_to_string(site.options().output.join("index.html")).unwrap();
+        assert!(
+            index.contains("Foxtrot") && !index.contains("Charlie"),
+            "{index}"
+        );
+    }
+
+    #[test]
+    fn notes_whose_pages_collide_are_not_published() {
+        let (directory, mut site) =
+            new_site("collision", &[("My Note", "one"), ("my-note", "two")]);
+        let report = site.build().unwrap();
+        assert_eq!(report.errors.len(), 1, "{:?}", report.errors);
+        assert!(
+            report.errors[0].contains("is already taken by"),
+            "{:?}",
+            report.errors
+        );
+        assert!(report.errors[0].starts_with(&directory.join("my-note.md").display().to_string()));
+        assert!(page(&site, &directory, "My Note").contains("one"));
+    }
+
+    #[test]
+    fn only_images_inside_the_vault_are_copied() {
+        let (directory, mut site) = new_site(
+            "images",
+            &[(
+                "a",
+                "![one](img/pic%20one.png) ![two](../secret.png) ![three](https://example.com/x.png) \
+                 ![four](missing.png)",
+            )],
+        );
+        fs::create_dir_all(directory.join("img")).unwrap();
+        fs::write(directory.join("img/pic one.png"), "picture").unwrap();
+        fs::write(directory.join("../secret.png"), "secret").unwrap();
+        let report = site.build().unwrap();
+        assert_eq!(report.images, 1);
+        let output = &site.options().output;
+        assert_eq!(
+            fs::read_to_string(output.join("img/pic one.png")).unwrap(),
+            "picture"
+        );
+        assert_eq!(report.warnings.len(), 1, "{:?}", report.warnings);
+        assert!(report.warnings[0].contains("image missing.png does not exist"));
+
+        assert_eq!(
+            local_path("img/a%20b.png?x=1#top"),
+            Some(PathBuf::from("img/a b.png"))
+        );
+        assert_eq!(local_path("./a.png"), Some(PathBuf::from("./a.png")));
+        for url in [
+            "../secret.png",
+            "img/../../secret.png",
+            "%2E%2E/secret.png",
+            "/etc/passwd",
+            "//example.com/x.png",
+            "data:image/png;base64,AAAA",
+            "",
+        ] {
+            assert_eq!(local_path(url), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn skeleton_placeholders_are_filled_once() {
+        assert_eq!(
+            fill("$a$ $b$ $$ $c", &[("a", "$b$"), ("b", "x")]),
+            "$b$ x $$ $c"
+        );
+        let (directory, mut site) = new_site(
+            "skeleton",
+            &[
+                ("a", "# Alpha *one*\n\n#news and [[b]]"),
+                ("b", "# Bravo\n\nSee [[a]]."),
+            ],
+        );
+        site.options.skeleton =
+            "[$title$][$root$][$tags$][$backlinks$][$body$][$price$]".to_string();
+        site.build().unwrap();
+        let a = page(&site, &directory, "a");
+        assert!(a.starts_with("[Alpha one][]"), "{a}");
+        assert!(
+            a.contains(
+                "[<ul class=\"tags\">\n<li><a href=\"tags/news.html\">news</a></li>\n</ul>]"
+            ),
+            "{a}"
+        );
+        assert!(a.contains("<h2>Linked from</h2>"), "{a}");
+        assert!(a.contains("<a href=\"b.html\">Bravo</a>"), "{a}");
+        assert!(a.ends_with("[$price$]"), "{a}");
+        let b = page(&site, &directory, "b");
+        assert!(b.starts_with("[Bravo][][][<section"), "{b}");
+    }
+
+    #[test]
+    fn tags_and_notes_get_pages_that_link_to_each_other() {
+        let (directory, mut site) = new_site(
+            "tags",
+            &[
+                ("a", "# Alpha\n\n#project/one [[b]]"),
+                ("b", "---\ntags: [project]\n---\n# Bravo\n"),
+                ("Template:t", "#hidden"),
+            ],
+        );
+        let report = site.build().unwrap();
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        // Two notes, the index, and a page for each of the three tags.
+        assert_eq!(report.pages, 6);
+        let output = site.options().output.clone();
+        assert!(!site.page_path(directory.join("Template:t.md")).exists());
+
+        let index = fs::read_to_string(output.join("index.html")).unwrap();
+        assert!(index.contains("<a href=\"a.html\">Alpha</a>"), "{index}");
+        assert!(
+            index.contains("<a href=\"tags/project-one.html\">project/one</a>"),
+            "{index}"
+        );
+        let tag = fs::read_to_string(output.join("tags/project.html")).unwrap();
+        assert!(tag.contains("<title>Tag: project</title>"), "{tag}");
+        assert!(tag.contains("<a href=\"../a.html\">Alpha</a>"), "{tag}");
+        assert!(tag.contains("<a href=\"../b.html\">Bravo</a>"), "{tag}");
+        assert!(tag.contains("<a href=\"../index.html\">Index</a>"), "{tag}");
+        assert_eq!(
+            site.tag_at("tags/project-one.html").as_deref(),
+            Some("project/one")
+        );
+
+        let b = page(&site, &directory, "b");
+        assert!(
+            b.contains("<li><a href=\"tags/project.html\">project</a></li>"),
+            "{b}"
+        );
+        assert!(b.contains("<section class=\"backlinks\">"), "{b}");
+        assert!(b.contains("<a href=\"a.html\">Alpha</a>"), "{b}");
+        assert_eq!(site.note_at("b.html"), Some(directory.join("b.md")));
+    }
+}
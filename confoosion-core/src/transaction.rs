@@ -0,0 +1,738 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    process,
+};
+
+use crate::atomic;
+
+const STATE_DIRECTORY: &str = ".confoosion";
+const JOURNAL: &str = "journal";
+const LOCK: &str = "lock";
+const STAGING: &str = "staged";
+const HEADER: &str = "confoosion-journal 1";
+
+/// A batch of file modifications that is applied completely or not at all.
+///
+/// New file contents are staged next to the vault first. On commit a write-ahead journal
+/// describing every operation is synced to disk before the first target file is touched, so
+/// [`recover`] can finish an interrupted batch on the next start. A batch that was interrupted
+/// before its journal was written is rolled back instead, leaving every target untouched.
+///
+/// Only one batch runs in a vault at a time: it holds a lock on the state directory from
+/// [`Transaction::begin`] until it is committed, aborted or dropped.
+#[derive(Debug)]
+pub struct Transaction {
+    root: PathBuf,
+    operations: Vec<Operation>,
+    /// Released when the batch is dropped, which lets [`recover`] clean up after it.
+    _lock: File,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operation {
+    Write { path: PathBuf, staged: PathBuf },
+    Remove { path: PathBuf },
+    Rename { from: PathBuf, to: PathBuf },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    Clean,
+    RolledForward(usize),
+    /// The batch was finished, except for renames onto names that were taken meanwhile. The
+    /// files they would have moved are left in place.
+    RolledForwardExcept(usize, Vec<PathBuf>),
+    RolledBack(usize),
+}
+
+impl Transaction {
+    pub fn begin<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(staging_path(&root))?;
+        let lock = open_lock(&root)?;
+        match lock.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("Another batch is running in {}", root.display()),
+                ))
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        if journal_path(&root).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "An interrupted batch is pending in {}, recover it first",
+                    root.display()
+                ),
+            ));
+        }
+        Ok(Self {
+            root,
+            operations: Vec::new(),
+            _lock: lock,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn write<P, C>(&mut self, path: P, contents: C) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        let path = self.relative(path.as_ref())?;
+        let staged = PathBuf::from(STATE_DIRECTORY)
+            .join(STAGING)
+            .join(format!("{}-{}", process::id(), self.operations.len()));
+        atomic::write_synced(self.root.join(&staged), contents.as_ref())?;
+        self.operations.push(Operation::Write { path, staged });
+        Ok(())
+    }
+
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let path = self.relative(path.as_ref())?;
+        self.operations.push(Operation::Remove { path });
+        Ok(())
+    }
+
+    /// Renames `from` to `to`, which must not exist yet unless it is the same file, as when
+    /// only the case of a name changes on a filesystem that ignores case.
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) -> io::Result<()> {
+        let from = self.relative(from.as_ref())?;
+        let to = self.relative(to.as_ref())?;
+        check_vacant(&self.root.join(&from), &self.root.join(&to))?;
+        self.operations.push(Operation::Rename { from, to });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Applies the batch. A rename onto a name that was taken since it was staged fails the
+    /// whole batch before anything is touched, or, if the name is taken while the batch is
+    /// applied, leaves the file it would have moved in place and fails after the rest is done.
+    pub fn commit(self) -> io::Result<()> {
+        if self.operations.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = self.check_targets() {
+            self.abort()?;
+            return Err(e);
+        }
+        let mut journal = format!("{HEADER}\n");
+        for operation in &self.operations {
+            journal.push_str(&operation.encode());
+            journal.push('\n');
+        }
+        journal.push_str("commit\n");
+        atomic::write(journal_path(&self.root), journal)?;
+        let kept = replay(&self.root, &self.operations, 0, Vec::new())?;
+        finish(&self.root)?;
+        match kept.first() {
+            None => Ok(()),
+            Some(from) => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} could not be renamed because its new name was taken",
+                    from.display()
+                ),
+            )),
+        }
+    }
+
+    /// Checks that every rename goes to a name that is free by the time it runs, taking the
+    /// operations before it into account.
+    fn check_targets(&self) -> io::Result<()> {
+        let mut created: Vec<&Path> = Vec::new();
+        let mut vacated: Vec<&Path> = Vec::new();
+        for operation in &self.operations {
+            let (gone, taken) = match operation {
+                Operation::Write { path, .. } => (None, path.as_path()),
+                Operation::Remove { path } => (Some(path.as_path()), path.as_path()),
+                Operation::Rename { from, to } => {
+                    let (from_path, to_path) = (self.root.join(from), self.root.join(to));
+                    let exists = created.contains(&to.as_path())
+                        || (to_path.exists() && !vacated.contains(&to.as_path()));
+                    if exists && !atomic::same_file(&from_path, &to_path) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{} already exists", to_path.display()),
+                        ));
+                    }
+                    (Some(from.as_path()), to.as_path())
+                }
+            };
+            if let Some(gone) = gone {
+                created.retain(|x| *x != gone);
+                vacated.push(gone);
+            }
+            if !matches!(operation, Operation::Remove { .. }) {
+                vacated.retain(|x| *x != taken);
+                created.push(taken);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn abort(self) -> io::Result<()> {
+        for operation in &self.operations {
+            if let Operation::Write { staged, .. } = operation {
+                remove_if_exists(&self.root.join(staged))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn relative(&self, path: &Path) -> io::Result<PathBuf> {
+        let relative = if path.is_absolute() {
+            match path.strip_prefix(&self.root) {
+                Ok(x) => x.to_path_buf(),
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} lies outside of {}",
+                            path.display(),
+                            self.root.display()
+                        ),
+                    ))
+                }
+            }
+        } else {
+            path.to_path_buf()
+        };
+        if relative.to_str().is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not valid UTF-8", path.display()),
+            ));
+        }
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} may not leave the vault", path.display()),
+            ));
+        }
+        Ok(relative)
+    }
+}
+
+/// Finishes or undoes a batch that was interrupted by a crash. Call this once on start-up,
+/// before any [`Transaction`] is begun. A batch that another process is still running is
+/// waited for rather than undone.
+pub fn recover<P: AsRef<Path>>(root: P) -> io::Result<Recovery> {
+    let root = root.as_ref();
+    if !root.join(STATE_DIRECTORY).is_dir() {
+        return Ok(Recovery::Clean);
+    }
+    let lock = open_lock(root)?;
+    lock.lock()?;
+    let journal = journal_path(root);
+    if journal.exists() {
+        let contents = fs::read_to_string(&journal)?;
+        let (operations, done, kept) = decode_journal(&contents)?;
+        let kept = replay(root, &operations, done, kept)?;
+        finish(root)?;
+        return Ok(match kept.is_empty() {
+            true => Recovery::RolledForward(operations.len() - done),
+            false => Recovery::RolledForwardExcept(operations.len() - done, kept),
+        });
+    }
+    let mut removed = 0;
+    let staging = staging_path(root);
+    if staging.is_dir() {
+        for entry in fs::read_dir(&staging)? {
+            fs::remove_file(entry?.path())?;
+            removed += 1;
+        }
+    }
+    let state = root.join(STATE_DIRECTORY);
+    if state.is_dir() {
+        for entry in fs::read_dir(&state)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with(".journal.") && name.ends_with(".tmp") {
+                fs::remove_file(entry.path())?;
+            }
+        }
+    }
+    if removed == 0 {
+        Ok(Recovery::Clean)
+    } else {
+        Ok(Recovery::RolledBack(removed))
+    }
+}
+
+/// Applies `operations` from `done` on. `kept` are the files of earlier renames whose new name
+/// was taken; they and the files skipped now are returned, and nothing later touches them.
+fn replay(
+    root: &Path,
+    operations: &[Operation],
+    done: usize,
+    mut kept: Vec<PathBuf>,
+) -> io::Result<Vec<PathBuf>> {
+    let journal = journal_path(root);
+    for (index, operation) in operations.iter().enumerate().skip(done) {
+        let progress = match operation.apply(root, &kept)? {
+            true => "done",
+            false => {
+                if let Operation::Rename { from, .. } = operation {
+                    kept.push(from.clone());
+                }
+                "kept"
+            }
+        };
+        // Every operation is idempotent on its own, but not across the batch: a rename
+        // followed by a write to the old name must not be redone after the write.
+        let mut file = OpenOptions::new().append(true).open(&journal)?;
+        file.write_all(format!("{progress} {index}\n").as_bytes())?;
+        file.sync_data()?;
+    }
+    Ok(kept)
+}
+
+fn finish(root: &Path) -> io::Result<()> {
+    atomic::remove(journal_path(root))?;
+    let staging = staging_path(root);
+    if staging.is_dir() && fs::read_dir(&staging)?.next().is_none() {
+        fs::remove_dir(&staging)?;
+    }
+    Ok(())
+}
+
+impl Operation {
+    /// Applies the operation, unless it would touch one of the `kept` files. Returns false for
+    /// a rename whose new name is taken, which is not done either.
+    fn apply(&self, root: &Path, kept: &[PathBuf]) -> io::Result<bool> {
+        let touches = |path: &PathBuf| kept.contains(path);
+        match self {
+            Operation::Write { path, staged } if touches(path) => {
+                remove_if_exists(&root.join(staged))?;
+                Ok(true)
+            }
+            Operation::Remove { path } if touches(path) => Ok(true),
+            Operation::Rename { from, to } if touches(from) || touches(to) => Ok(false),
+            Operation::Write { path, staged } => {
+                let staged = root.join(staged);
+                if staged.exists() {
+                    let target = root.join(path);
+                    create_parent(&target)?;
+                    atomic::keep_permissions(&target, &staged)?;
+                    atomic::rename(staged, target)?;
+                }
+                Ok(true)
+            }
+            Operation::Remove { path } => {
+                let target = root.join(path);
+                if target.exists() {
+                    atomic::remove(target)?;
+                }
+                Ok(true)
+            }
+            Operation::Rename { from, to } => {
+                let from = root.join(from);
+                if from.exists() {
+                    let to = root.join(to);
+                    // Something may have taken the name since the batch was checked.
+                    if check_vacant(&from, &to).is_err() {
+                        return Ok(false);
+                    }
+                    create_parent(&to)?;
+                    atomic::rename(from, to)?;
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            Operation::Write { path, staged } => {
+                format!("write\t{}\t{}", escape(path), escape(staged))
+            }
+            Operation::Remove { path } => format!("remove\t{}", escape(path)),
+            Operation::Rename { from, to } => format!("rename\t{}\t{}", escape(from), escape(to)),
+        }
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let operation = match fields.next()? {
+            "write" => Operation::Write {
+                path: unescape(fields.next()?)?,
+                staged: unescape(fields.next()?)?,
+            },
+            "remove" => Operation::Remove {
+                path: unescape(fields.next()?)?,
+            },
+            "rename" => Operation::Rename {
+                from: unescape(fields.next()?)?,
+                to: unescape(fields.next()?)?,
+            },
+            _ => return None,
+        };
+        match fields.next() {
+            Some(_) => None,
+            None => Some(operation),
+        }
+    }
+}
+
+/// The operations of a journal, how many of them are done and the files of the renames among
+/// those that were skipped.
+fn decode_journal(contents: &str) -> io::Result<(Vec<Operation>, usize, Vec<PathBuf>)> {
+    let corrupt = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Corrupt journal: {reason}"),
+        )
+    };
+    let mut lines = contents.split_terminator('\n');
+    if lines.next() != Some(HEADER) {
+        return Err(corrupt("unknown header"));
+    }
+    let mut operations = Vec::new();
+    loop {
+        match lines.next() {
+            None => return Err(corrupt("missing commit marker")),
+            Some("commit") => break,
+            Some(line) => match Operation::decode(line) {
+                Some(operation) => operations.push(operation),
+                None => return Err(corrupt(line)),
+            },
+        }
+    }
+    let mut done = 0;
+    let mut kept = Vec::new();
+    for line in lines {
+        // A crash while appending can leave a torn progress line behind; it only means the
+        // operation is redone.
+        let (progress, index) = match line.split_once(' ') {
+            Some((progress, index)) => (progress, index.parse::<usize>()),
+            None => break,
+        };
+        match (progress, index) {
+            ("done", Ok(index)) if index == done => done += 1,
+            ("kept", Ok(index)) if index == done => {
+                if let Some(Operation::Rename { from, .. }) = operations.get(index) {
+                    kept.push(from.clone());
+                }
+                done += 1
+            }
+            _ => break,
+        }
+    }
+    let done = done.min(operations.len());
+    Ok((operations, done, kept))
+}
+
+fn escape(path: &Path) -> String {
+    let mut out = String::new();
+    for character in path.to_string_lossy().chars() {
+        match character {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+fn unescape(field: &str) -> Option<PathBuf> {
+    let mut out = String::new();
+    let mut chars = field.chars();
+    while let Some(character) = chars.next() {
+        match character {
+            '\\' => match chars.next()? {
+                '\\' => out.push('\\'),
+                't' => out.push('\t'),
+                'n' => out.push('\n'),
+                _ => return None,
+            },
+            other => out.push(other),
+        }
+    }
+    Some(PathBuf::from(out))
+}
+
+fn journal_path(root: &Path) -> PathBuf {
+    root.join(STATE_DIRECTORY).join(JOURNAL)
+}
+
+fn staging_path(root: &Path) -> PathBuf {
+    root.join(STATE_DIRECTORY).join(STAGING)
+}
+
+fn open_lock(root: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(root.join(STATE_DIRECTORY).join(LOCK))
+}
+
+fn check_vacant(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() && !atomic::same_file(from, to) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    Ok(())
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.exists() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread, time::Duration};
+
+    use super::*;
+
+    /// An empty directory of its own for each test.
+    fn vault(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("confoosion-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn read(root: &Path, name: &str) -> Option<String> {
+        fs::read_to_string(root.join(name)).ok()
+    }
+
+    fn staged_files(root: &Path) -> usize {
+        fs::read_dir(staging_path(root)).map_or(0, |entries| entries.count())
+    }
+
+    /// Does everything `commit` does up to the crash, which happens after the first `applied`
+    /// operations.
+    fn crash_during_commit(transaction: Transaction, applied: usize) {
+        let mut journal = format!("{HEADER}\n");
+        for operation in &transaction.operations {
+            journal.push_str(&operation.encode());
+            journal.push('\n');
+        }
+        journal.push_str("commit\n");
+        atomic::write(journal_path(&transaction.root), journal).unwrap();
+        replay(
+            &transaction.root,
+            &transaction.operations[..applied],
+            0,
+            Vec::new(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn commit_applies_every_operation() {
+        let root = vault("commit");
+        fs::write(root.join("a.md"), "a").unwrap();
+        fs::write(root.join("b.md"), "b").unwrap();
+        let mut transaction = Transaction::begin(&root).unwrap();
+        transaction.write(root.join("a.md"), "new a").unwrap();
+        transaction
+            .rename(root.join("b.md"), root.join("c.md"))
+            .unwrap();
+        transaction.remove("a.md").unwrap();
+        transaction.write("d/e.md", "e").unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(read(&root, "a.md"), None);
+        assert_eq!(read(&root, "b.md"), None);
+        assert_eq!(read(&root, "c.md").as_deref(), Some("b"));
+        assert_eq!(read(&root, "d/e.md").as_deref(), Some("e"));
+        assert!(!journal_path(&root).exists());
+        assert!(!staging_path(&root).exists());
+        assert_eq!(recover(&root).unwrap(), Recovery::Clean);
+    }
+
+    #[test]
+    fn recover_rolls_forward_after_the_journal_is_written() {
+        let root = vault("roll-forward");
+        fs::write(root.join("a.md"), "a").unwrap();
+        fs::write(root.join("b.md"), "b").unwrap();
+        let mut transaction = Transaction::begin(&root).unwrap();
+        transaction.write("a.md", "new a").unwrap();
+        transaction.rename("b.md", "c.md").unwrap();
+        transaction.write("b.md", "new b").unwrap();
+        crash_during_commit(transaction, 1);
+        assert_eq!(read(&root, "a.md").as_deref(), Some("new a"));
+        assert_eq!(read(&root, "b.md").as_deref(), Some("b"));
+        assert!(Transaction::begin(&root).is_err());
+
+        assert_eq!(recover(&root).unwrap(), Recovery::RolledForward(2));
+        assert_eq!(read(&root, "a.md").as_deref(), Some("new a"));
+        assert_eq!(read(&root, "b.md").as_deref(), Some("new b"));
+        assert_eq!(read(&root, "c.md").as_deref(), Some("b"));
+        assert!(!journal_path(&root).exists());
+        assert_eq!(recover(&root).unwrap(), Recovery::Clean);
+    }
+
+    #[test]
+    fn recover_redoes_an_operation_whose_progress_was_not_recorded() {
+        let root = vault("torn-progress");
+        fs::write(root.join("a.md"), "a").unwrap();
+        let mut transaction = Transaction::begin(&root).unwrap();
+        transaction.rename("a.md", "b.md").unwrap();
+        transaction.write("a.md", "new a").unwrap();
+        let operations = transaction.operations.clone();
+        crash_during_commit(transaction, 0);
+        // The rename happened, but the crash came before it was marked as done.
+        operations[0].apply(&root, &[]).unwrap();
+
+        assert_eq!(recover(&root).unwrap(), Recovery::RolledForward(2));
+        assert_eq!(read(&root, "a.md").as_deref(), Some("new a"));
+        assert_eq!(read(&root, "b.md").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn recover_rolls_back_before_the_journal_is_written() {
+        let root = vault("roll-back");
+        fs::write(root.join("a.md"), "a").unwrap();
+        let mut transaction = Transaction::begin(&root).unwrap();
+        transaction.write("a.md", "new a").unwrap();
+        transaction.write("b.md", "b").unwrap();
+        drop(transaction);
+        assert_eq!(staged_files(&root), 2);
+
+        assert_eq!(recover(&root).unwrap(), Recovery::RolledBack(2));
+        assert_eq!(read(&root, "a.md").as_deref(), Some("a"));
+        assert_eq!(read(&root, "b.md"), None);
+        assert_eq!(staged_files(&root), 0);
+    }
+
+    #[test]
+    fn recover_waits_for_a_running_batch() {
+        let root = vault("concurrent");
+        let mut transaction = Transaction::begin(&root).unwrap();
+        transaction.write("a.md", "a").unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let recovering = {
+            let root = root.clone();
+            thread::spawn(move || sender.send(recover(&root).unwrap()).unwrap())
+        };
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        assert_eq!(staged_files(&root), 1);
+        assert!(Transaction::begin(&root).is_err());
+
+        transaction.commit().unwrap();
+        assert_eq!(receiver.recv().unwrap(), Recovery::Clean);
+        recovering.join().unwrap();
+        assert_eq!(read(&root, "a.md").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn rename_does_not_replace_an_existing_file() {
+        let root = vault("rename-existing");
+        fs::write(root.join("a.md"), "a").unwrap();
+        fs::write(root.join("b.md"), "b").unwrap();
+        let mut transaction = Transaction::begin(&root).unwrap();
+        let error = transaction.rename("a.md", "b.md").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+
+        // Something else may take the name after the rename was staged, which fails the batch
+        // before anything is changed.
+        transaction.rename("a.md", "c.md").unwrap();
+        transaction.write("a.md", "new a").unwrap();
+        fs::write(root.join("c.md"), "c").unwrap();
+        assert!(transaction.commit().is_err());
+        assert_eq!(read(&root, "a.md").as_deref(), Some("a"));
+        assert_eq!(read(&root, "c.md").as_deref(), Some("c"));
+        assert!(!journal_path(&root).exists());
+        assert_eq!(staged_files(&root), 0);
+        assert_eq!(recover(&root).unwrap(), Recovery::Clean);
+
+        // Or while it is applied, which leaves the file in place along with everything that
+        // would have replaced it, and still finishes the batch.
+        fs::remove_file(root.join("c.md")).unwrap();
+        let mut transaction = Transaction::begin(&root).unwrap();
+        transaction.rename("a.md", "c.md").unwrap();
+        transaction.write("a.md", "new a").unwrap();
+        transaction.write("d.md", "d").unwrap();
+        crash_during_commit(transaction, 0);
+        fs::write(root.join("c.md"), "c").unwrap();
+        assert_eq!(
+            recover(&root).unwrap(),
+            Recovery::RolledForwardExcept(3, vec![PathBuf::from("a.md")])
+        );
+        assert_eq!(read(&root, "a.md").as_deref(), Some("a"));
+        assert_eq!(read(&root, "c.md").as_deref(), Some("c"));
+        assert_eq!(read(&root, "d.md").as_deref(), Some("d"));
+        assert_eq!(recover(&root).unwrap(), Recovery::Clean);
+        Transaction::begin(&root).unwrap().commit().unwrap();
+    }
+
+    #[test]
+    fn a_batch_may_rename_onto_a_name_it_frees() {
+        let root = vault("rename-freed");
+        fs::write(root.join("a.md"), "a").unwrap();
+        fs::write(root.join("b.md"), "b").unwrap();
+        let mut transaction = Transaction::begin(&root).unwrap();
+        transaction.rename("b.md", "c.md").unwrap();
+        transaction.operations.push(Operation::Rename {
+            from: PathBuf::from("a.md"),
+            to: PathBuf::from("b.md"),
+        });
+        transaction.write("d.md", "d").unwrap();
+        transaction.operations.push(Operation::Rename {
+            from: PathBuf::from("c.md"),
+            to: PathBuf::from("d.md"),
+        });
+        assert!(transaction.check_targets().is_err());
+        transaction.operations.pop();
+        transaction.commit().unwrap();
+        assert_eq!(read(&root, "b.md").as_deref(), Some("a"));
+        assert_eq!(read(&root, "c.md").as_deref(), Some("b"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_keep_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = vault("permissions");
+        let note = root.join("a.md");
+        fs::write(&note, "a").unwrap();
+        fs::set_permissions(&note, fs::Permissions::from_mode(0o600)).unwrap();
+        let mut transaction = Transaction::begin(&root).unwrap();
+        transaction.write(&note, "new a").unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(
+            fs::metadata(&note).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        fs::set_permissions(&note, fs::Permissions::from_mode(0o640)).unwrap();
+        atomic::write(&note, "newer a").unwrap();
+        assert_eq!(
+            fs::metadata(&note).unwrap().permissions().mode() & 0o777,
+            0o640
+        );
+        assert_eq!(read(&root, "a.md").as_deref(), Some("newer a"));
+    }
+}
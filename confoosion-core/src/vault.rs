@@ -0,0 +1,273 @@
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use confoosion_markdown_parser::{
+    frontmatter::FrontMatter,
+    markdown_to_html,
+    resolver::notes_in,
+    scan,
+    template::TemplateMap,
+    title::NoteHeader,
+    vault::{note_tags, tag_matches, VaultQuery},
+};
+
+use crate::site::render_guarded;
+
+/// An in-memory index of the notes in one directory, so that rendering many notes does not
+/// read every note again for every backlink or tag query.
+#[derive(Debug, Clone, Default)]
+pub struct Vault {
+    directory: PathBuf,
+    notes: BTreeMap<PathBuf, NoteEntry>,
+}
+
+/// What the index knows about a single note.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoteEntry {
+    pub front_matter: FrontMatter,
+    /// The `#` title rendered to HTML.
+    pub title: Option<String>,
+    /// The existing notes this note links to, sorted and without duplicates. These are the links
+    /// of the rendered note, so those that templates produce count as well.
+    pub links: Vec<PathBuf>,
+    /// The existing notes named by `parent` or `parents` in the front matter, sorted and without
+    /// duplicates.
+    pub parents: Vec<PathBuf>,
+    /// The tags in the front matter and the `#tags` in the text, sorted and without duplicates.
+    pub tags: Vec<String>,
+}
+
+impl Vault {
+    /// Indexes every note in `directory`. Links are resolved the way `templates` resolves them.
+    pub fn scan<P: AsRef<Path>>(directory: P, templates: &TemplateMap) -> io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        if !directory.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a directory", directory.display()),
+            ));
+        }
+        let mut vault = Self {
+            directory,
+            notes: BTreeMap::new(),
+        };
+        for note in notes_in(&vault.directory) {
+            vault.update(&note, templates)?;
+        }
+        Ok(vault)
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn entry<P: AsRef<Path>>(&self, note: P) -> Option<&NoteEntry> {
+        self.notes.get(note.as_ref())
+    }
+
+    /// Every indexed note with what is known about it, ordered by path.
+    pub fn entries(&self) -> impl Iterator<Item = (&Path, &NoteEntry)> {
+        self.notes
+            .iter()
+            .map(|(note, entry)| (note.as_path(), entry))
+    }
+
+    pub fn contains<P: AsRef<Path>>(&self, note: P) -> bool {
+        self.notes.contains_key(note.as_ref())
+    }
+
+    /// Reads `note` again after it was created or changed. See [`NoteEntry::read`].
+    pub fn update<P: AsRef<Path>>(&mut self, note: P, templates: &TemplateMap) -> io::Result<()> {
+        let note = note.as_ref();
+        let entry = NoteEntry::read(note, templates)?;
+        self.notes.insert(note.to_path_buf(), entry);
+        Ok(())
+    }
+
+    /// Forgets a note that was deleted or moved away, and returns what was known about it.
+    pub fn remove<P: AsRef<Path>>(
+        &mut self,
+        note: P,
+        templates: &TemplateMap,
+    ) -> Option<NoteEntry> {
+        templates.title_cache().invalidate(note.as_ref());
+        self.notes.remove(note.as_ref())
+    }
+}
+
+impl NoteEntry {
+    /// Reads `note` from disk and renders it with `templates` to find its links. A note that
+    /// cannot be rendered keeps the links written in it.
+    pub fn read(note: &Path, templates: &TemplateMap) -> io::Result<Self> {
+        let source = std::fs::read_to_string(note)?;
+        templates.title_cache().invalidate(note);
+        let directory = note.parent().unwrap_or(Path::new("."));
+        let header = NoteHeader::parse(&source, note.parent());
+        let links = match render_guarded(templates, || markdown_to_html(&source, note, templates)) {
+            // Links that did not resolve point at where their note would be.
+            Ok(parsed) => parsed
+                .dependencies
+                .links
+                .into_iter()
+                .filter(|to| to.parent() == Some(directory) && to.is_file())
+                .collect(),
+            Err(_) => {
+                let (_, body, _) = FrontMatter::split(&source);
+                let targets = scan::wiki_links(body).into_iter().map(|link| link.target);
+                resolve_all(directory, targets, templates)
+            }
+        };
+        let parents = resolve_all(directory, header.front_matter.parents(), templates);
+        Ok(Self {
+            tags: note_tags(&source),
+            front_matter: header.front_matter,
+            title: header.title,
+            links,
+            parents,
+        })
+    }
+}
+
+/// The existing notes that link targets resolve to, sorted and without duplicates.
+fn resolve_all<I: IntoIterator<Item = String>>(
+    directory: &Path,
+    targets: I,
+    templates: &TemplateMap,
+) -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> = targets
+        .into_iter()
+        .map(|target| {
+            templates
+                .resolver()
+                .resolve(&target, directory, templates.title_cache())
+        })
+        .filter(|resolution| resolution.exists())
+        .map(|resolution| resolution.path().to_path_buf())
+        .collect();
+    out.sort();
+    out.dedup();
+    out
+}
+
+impl VaultQuery for Vault {
+    fn notes(&self) -> Vec<PathBuf> {
+        self.notes.keys().cloned().collect()
+    }
+
+    fn front_matter(&self, note: &Path) -> FrontMatter {
+        self.entry(note)
+            .map(|entry| entry.front_matter.clone())
+            .unwrap_or_default()
+    }
+
+    fn title(&self, note: &Path) -> Option<String> {
+        self.entry(note).and_then(|entry| entry.title.clone())
+    }
+
+    fn links_from(&self, note: &Path) -> Vec<PathBuf> {
+        self.entry(note)
+            .map(|entry| entry.links.clone())
+            .unwrap_or_default()
+    }
+
+    fn backlinks(&self, note: &Path) -> Vec<PathBuf> {
+        self.notes
+            .iter()
+            .filter(|(other, entry)| *other != note && entry.links.iter().any(|x| x == note))
+            .map(|(other, _)| other.clone())
+            .collect()
+    }
+
+    fn tags(&self, note: &Path) -> Vec<String> {
+        self.entry(note)
+            .map(|entry| entry.tags.clone())
+            .unwrap_or_default()
+    }
+
+    fn tagged(&self, tag: &str) -> Vec<PathBuf> {
+        self.notes
+            .iter()
+            .filter(|(_, entry)| entry.tags.iter().any(|x| tag_matches(x, tag)))
+            .map(|(note, _)| note.clone())
+            .collect()
+    }
+
+    fn all_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .notes
+            .values()
+            .flat_map(|entry| entry.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+}
+
+/// A [`Vault`] that can still be updated after it was handed to a [`TemplateMap`].
+///
+/// Templates only borrow it while a note renders, so update it between renders.
+#[derive(Debug, Clone, Default)]
+pub struct SharedVault(Rc<RefCell<Vault>>);
+
+impl SharedVault {
+    pub fn new(vault: Vault) -> Self {
+        Self(Rc::new(RefCell::new(vault)))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, Vault> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, Vault> {
+        self.0.borrow_mut()
+    }
+
+    /// Reads `note` again like [`Vault::update`]. The note is rendered before the vault is
+    /// borrowed, so templates that query the vault can still read it meanwhile.
+    pub fn update<P: AsRef<Path>>(&self, note: P, templates: &TemplateMap) -> io::Result<()> {
+        let note = note.as_ref();
+        let entry = NoteEntry::read(note, templates)?;
+        self.borrow_mut().notes.insert(note.to_path_buf(), entry);
+        Ok(())
+    }
+}
+
+impl VaultQuery for SharedVault {
+    fn notes(&self) -> Vec<PathBuf> {
+        self.borrow().notes()
+    }
+
+    fn front_matter(&self, note: &Path) -> FrontMatter {
+        self.borrow().front_matter(note)
+    }
+
+    fn title(&self, note: &Path) -> Option<String> {
+        self.borrow().title(note)
+    }
+
+    fn links_from(&self, note: &Path) -> Vec<PathBuf> {
+        self.borrow().links_from(note)
+    }
+
+    fn backlinks(&self, note: &Path) -> Vec<PathBuf> {
+        self.borrow().backlinks(note)
+    }
+
+    fn tags(&self, note: &Path) -> Vec<String> {
+        self.borrow().tags(note)
+    }
+
+    fn tagged(&self, tag: &str) -> Vec<PathBuf> {
+        self.borrow().tagged(tag)
+    }
+
+    fn all_tags(&self) -> Vec<String> {
+        self.borrow().all_tags()
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use confoosion_markdown_parser::resolver::notes_in;
+
+/// Notices notes that were created, changed or deleted in a directory by polling their
+/// modification times, which works the same on every platform and needs no dependencies.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    directory: PathBuf,
+    notes: BTreeMap<PathBuf, Stamp>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    length: u64,
+}
+
+/// The notes that differ between two polls, sorted. Created notes count as changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub changed: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl Watcher {
+    /// Starts watching `directory`. Only changes made after this call are reported.
+    pub fn new<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        let notes = snapshot(&directory)?;
+        Ok(Self { directory, notes })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Looks at the directory again and reports what changed since the last poll.
+    pub fn poll(&mut self) -> io::Result<Changes> {
+        let notes = snapshot(&self.directory)?;
+        let mut changes = Changes::default();
+        for (note, stamp) in &notes {
+            if self.notes.get(note) != Some(stamp) {
+                changes.changed.push(note.clone());
+            }
+        }
+        for note in self.notes.keys() {
+            if !notes.contains_key(note) {
+                changes.removed.push(note.clone());
+            }
+        }
+        self.notes = notes;
+        Ok(changes)
+    }
+}
+
+fn snapshot(directory: &Path) -> io::Result<BTreeMap<PathBuf, Stamp>> {
+    if !directory.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a directory", directory.display()),
+        ));
+    }
+    let mut out = BTreeMap::new();
+    for note in notes_in(directory) {
+        // A note deleted while the directory is read is reported as removed on this poll.
+        let metadata = match note.metadata() {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        let stamp = Stamp {
+            modified: metadata.modified()?,
+            length: metadata.len(),
+        };
+        out.insert(note, stamp);
+    }
+    Ok(out)
+}
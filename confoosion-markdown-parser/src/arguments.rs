@@ -0,0 +1,341 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    context::{Span, TemplateContext},
+    error::ParseError,
+    ExitMode, ParsedHTML,
+};
+
+/// One template argument, kept as the Markdown source it was written as.
+///
+/// Nothing is rendered until the template asks for it, so a template only pays for the
+/// arguments it uses, and errors point at the argument's place in the note.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Argument {
+    raw: String,
+    span: Span,
+}
+
+impl Argument {
+    pub fn new(raw: String, span: Span) -> Self {
+        Self { raw, span }
+    }
+
+    /// The argument exactly as written, escapes included.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Where the argument starts. Zero when it did not come from a note.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The argument as plain text: escapes are resolved, everything else is kept as written.
+    pub fn literal(&self) -> String {
+        let mut out = String::new();
+        let mut chars = self.raw.chars();
+        while let Some(character) = chars.next() {
+            match character {
+                '\\' => match chars.next() {
+                    Some(escaped) => out.push(escaped),
+                    None => out.push('\\'),
+                },
+                other => out.push(other),
+            }
+        }
+        out
+    }
+
+    pub fn render(&self, context: &TemplateContext) -> Result<(ParsedHTML, ExitMode), ParseError> {
+        context.render_at(&self.raw, self.span)
+    }
+
+    pub fn render_inline(&self, context: &TemplateContext) -> Result<ParsedHTML, ParseError> {
+        context.render_inline_at(&self.raw, self.span)
+    }
+}
+
+/// The arguments of a template call, split into positional and `key=value` arguments.
+///
+/// An argument is named when everything before its first `=` is a plain identifier on one line
+/// (letters, digits, `_`, `-` and spaces). Write `\=` to pass such an argument positionally
+/// anyway.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TemplateArgs {
+    positional: Vec<Argument>,
+    named: Vec<(String, Argument)>,
+}
+
+impl TemplateArgs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(raw: Vec<Argument>) -> Self {
+        let mut out = Self::new();
+        for argument in raw {
+            match split_named(&argument) {
+                Some((key, value)) => out.named.push((key, value)),
+                None => out.positional.push(argument),
+            }
+        }
+        out
+    }
+
+    pub fn push(&mut self, argument: String) {
+        self.positional.push(Argument::new(argument, Span::default()));
+    }
+
+    pub fn insert(&mut self, key: String, value: String) {
+        let value = Argument::new(value, Span::default());
+        match self.named.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, old)) => *old = value,
+            None => self.named.push((key, value)),
+        }
+    }
+
+    /// The number of positional arguments.
+    pub fn len(&self) -> usize {
+        self.positional.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positional.is_empty() && self.named.is_empty()
+    }
+
+    pub fn positional(&self) -> &[Argument] {
+        &self.positional
+    }
+
+    /// The named arguments with their raw values.
+    pub fn named(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.named
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.raw()))
+    }
+
+    pub fn named_arguments(&self) -> impl Iterator<Item = (&str, &Argument)> {
+        self.named.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// The raw source of a positional argument.
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(Argument::raw)
+    }
+
+    pub fn argument(&self, index: usize) -> Option<&Argument> {
+        self.positional.get(index)
+    }
+
+    /// The raw source of a named argument. When a key is given more than once, the last one
+    /// wins.
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.named_argument(key).map(Argument::raw)
+    }
+
+    pub fn named_argument(&self, key: &str) -> Option<&Argument> {
+        self.named
+            .iter()
+            .rev()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn value_or<'a>(&'a self, key: &str, default: &'a str) -> &'a str {
+        self.value(key).unwrap_or(default)
+    }
+
+    pub fn require(&self, index: usize, template: &str) -> Result<&Argument, ParseError> {
+        match self.argument(index) {
+            Some(x) => Ok(x),
+            None => Err(ParseError::empty(
+                format!(
+                    "{{{{{template}}}}} requires at least {} positional argument(s)",
+                    index + 1
+                )
+                .as_str(),
+            )),
+        }
+    }
+
+    pub fn require_value(&self, key: &str, template: &str) -> Result<&Argument, ParseError> {
+        match self.named_argument(key) {
+            Some(x) => Ok(x),
+            None => Err(ParseError::empty(
+                format!("{{{{{template}}}}} requires the argument {key}=").as_str(),
+            )),
+        }
+    }
+
+    /// Parses a positional argument, failing with a readable error if it has the wrong type.
+    pub fn parse_at<T>(&self, index: usize) -> Result<Option<T>, ParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.get(index) {
+            Some(x) => parse_as(x, &format!("Argument {}", index + 1)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses a named argument, failing with a readable error if it has the wrong type.
+    pub fn parse_value<T>(&self, key: &str) -> Result<Option<T>, ParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.value(key) {
+            Some(x) => parse_as(x, &format!("Argument {key}")).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn parse_value_or<T>(&self, key: &str, default: T) -> Result<T, ParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        Ok(self.parse_value(key)?.unwrap_or(default))
+    }
+}
+
+fn parse_as<T>(value: &str, what: &str) -> Result<T, ParseError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match value.trim().parse() {
+        Ok(x) => Ok(x),
+        Err(e) => Err(ParseError::empty(
+            format!(
+                "{what} could not be read as {}: {e}",
+                std::any::type_name::<T>()
+            )
+            .as_str(),
+        )),
+    }
+}
+
+fn split_named(argument: &Argument) -> Option<(String, Argument)> {
+    let (key, value) = argument.raw.split_once('=')?;
+    let trimmed_key = key.trim();
+    if trimmed_key.is_empty()
+        || !trimmed_key
+            .chars()
+            .all(|x| x.is_alphanumeric() || matches!(x, '_' | '-' | ' '))
+    {
+        return None;
+    }
+    let leading = value.len() - value.trim_start().len();
+    let span = argument.span.advance(&argument.raw[..key.len() + 1 + leading]);
+    Some((
+        trimmed_key.to_string(),
+        Argument::new(value.trim().to_string(), span),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::TemplateMap;
+
+    fn args(raw: &[&str]) -> TemplateArgs {
+        let span = Span { line: 2, column: 3 };
+        TemplateArgs::parse(
+            raw.iter()
+                .map(|x| Argument::new(x.to_string(), span))
+                .collect(),
+        )
+    }
+
+    fn positional(args: &TemplateArgs) -> Vec<&str> {
+        args.positional().iter().map(Argument::raw).collect()
+    }
+
+    #[test]
+    fn arguments_are_positional_or_named() {
+        let args = args(&["one", " name = Ann ", "two", "name=Bob", "my-key_2=x=y"]);
+        assert_eq!(positional(&args), ["one", "two"]);
+        assert_eq!(args.len(), 2);
+        assert_eq!(args.get(1), Some("two"));
+        assert_eq!(args.value("name"), Some("Bob"));
+        assert_eq!(args.value("my-key_2"), Some("x=y"));
+        assert_eq!(args.value_or("missing", "default"), "default");
+        let named: Vec<(&str, &str)> = args.named().collect();
+        assert_eq!(
+            named,
+            [("name", "Ann"), ("name", "Bob"), ("my-key_2", "x=y")]
+        );
+        let span = args.named_argument("name").unwrap().span();
+        assert_eq!(span, Span { line: 2, column: 8 });
+    }
+
+    #[test]
+    fn keys_are_one_line_of_identifier_characters() {
+        let args = args(&[
+            "a\nb=c",
+            "a\tb=c",
+            "=c",
+            "1 + 1 = 2",
+            "[[x]]=y",
+            "\n  key = value",
+        ]);
+        assert_eq!(
+            positional(&args),
+            ["a\nb=c", "a\tb=c", "=c", "1 + 1 = 2", "[[x]]=y"]
+        );
+        assert_eq!(args.value("key"), Some("value"));
+        let span = args.named_argument("key").unwrap().span();
+        assert_eq!(span, Span { line: 3, column: 9 });
+    }
+
+    #[test]
+    fn an_escaped_equals_sign_keeps_an_argument_positional() {
+        let args = args(&["a\\=b", "x \\= y"]);
+        assert_eq!(positional(&args), ["a\\=b", "x \\= y"]);
+        assert_eq!(args.named().count(), 0);
+        assert_eq!(args.argument(0).unwrap().literal(), "a=b");
+        assert_eq!(args.argument(1).unwrap().literal(), "x = y");
+        assert_eq!(
+            Argument::new("*a*\\".to_string(), Span::default()).literal(),
+            "*a*\\"
+        );
+    }
+
+    #[test]
+    fn arguments_render_where_they_were_written() {
+        let templates = TemplateMap::new();
+        let context = TemplateContext::new(&templates, std::env::temp_dir(), None, Span::default());
+        let argument = Argument::new("**a** \\*b\\*".to_string(), Span { line: 4, column: 7 });
+        let html = argument.render_inline(&context).unwrap().html;
+        assert_eq!(html, "<b>a</b> *b*");
+        assert_eq!(argument.literal(), "**a** *b*");
+
+        let broken = Argument::new("text [[x".to_string(), Span { line: 4, column: 7 });
+        let error = broken.render_inline(&context).unwrap_err();
+        assert!(error.to_string().starts_with("4:"), "{error}");
+    }
+
+    #[test]
+    fn missing_and_unreadable_arguments_are_errors() {
+        let args = args(&["x", "count=three"]);
+        let error = args.require(1, "t").unwrap_err();
+        assert_eq!(
+            error.comment,
+            "{{t}} requires at least 2 positional argument(s)"
+        );
+        let error = args.require_value("size", "t").unwrap_err();
+        assert_eq!(error.comment, "{{t}} requires the argument size=");
+        let error = args.parse_value::<u32>("count").unwrap_err();
+        assert!(
+            error
+                .comment
+                .starts_with("Argument count could not be read as u32"),
+            "{}",
+            error.comment
+        );
+        assert_eq!(args.parse_value_or("width", 5u32).unwrap(), 5);
+    }
+}
@@ -0,0 +1,638 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    arguments::TemplateArgs,
+    context::TemplateContext,
+    date::{days_in_month, Date, DAILY_NOTE_PATTERN, WEEKDAY_NAMES},
+    error::ParseError,
+    frontmatter::FrontMatter,
+    links::{slug, NoteLink},
+    markdown_file_to_html, markdown_inline_to_html,
+    resolver::Resolution,
+    scan,
+    template::TemplateMap,
+    ExitMode, ParsedHTML,
+};
+
+type TemplateResult = Result<(ParsedHTML, ExitMode), ParseError>;
+
+/// Registers the standard templates, which [`TemplateMap::with_builtins`] does for you.
+///
+/// Templates that take an optional `note` argument work on the note being rendered when it is
+/// left out, and fail when there is none, for example when rendering a string.
+pub fn register(templates: &mut TemplateMap) {
+    templates.insert("date".to_string(), Box::new(date));
+    templates.insert("include".to_string(), Box::new(include));
+    templates.insert("toc".to_string(), Box::new(toc));
+    templates.insert("backlinks".to_string(), Box::new(backlinks));
+    templates.insert("tagged".to_string(), Box::new(tagged));
+    templates.insert("if".to_string(), Box::new(if_));
+    templates.insert("switch".to_string(), Box::new(switch));
+    templates.insert("lower".to_string(), Box::new(lower));
+    templates.insert("upper".to_string(), Box::new(upper));
+    templates.insert("note_title".to_string(), Box::new(note_title));
+    templates.insert("link_count".to_string(), Box::new(link_count));
+    templates.insert("calendar".to_string(), Box::new(calendar));
+    templates.insert("journal_nav".to_string(), Box::new(journal_nav));
+}
+
+/// `{{date}}`, `{{date|format}}`: today's local date, as `YYYY-MM-DD` by default. See
+/// [`Date::format`] for the format. `offset=N` moves the date by `N` days, which may be negative.
+pub fn date(args: TemplateArgs, _context: &TemplateContext) -> TemplateResult {
+    expect_at_most(&args, 1, "date")?;
+    let offset: i64 = args.parse_value_or("offset", 0)?;
+    let format = args.get(0).unwrap_or("%Y-%m-%d");
+    Ok(html(Date::today().add_days(offset).format(format)))
+}
+
+/// `{{include|note}}`: renders another note in place. Including a note that is already being
+/// rendered further up is an error rather than an endless loop.
+pub fn include(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_exactly(&args, 1, "include")?;
+    let note = match context.resolve(args.get(0).unwrap()) {
+        Resolution::Missing(_) => {
+            return Err(ParseError::empty(
+                format!("{{{{include}}}}: there is no note {}", args.get(0).unwrap()).as_str(),
+            ))
+        }
+        resolution => resolution.path().to_path_buf(),
+    };
+    if context.templates.is_rendering(&note) {
+        return Err(ParseError::empty(
+            format!("{{{{include}}}}: {} includes itself", note.display()).as_str(),
+        ));
+    }
+    let mut parsed = markdown_file_to_html(&note, context.templates)?;
+    parsed.dependencies.transclusions.insert(note);
+    Ok((parsed, ExitMode::EndOfFile))
+}
+
+/// `{{toc}}`, `{{toc|note}}`: a list of the headings of a note, linking to each of them.
+pub fn toc(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_at_most(&args, 1, "toc")?;
+    let note = target_note(&args, context, "toc")?;
+    let source = read_body(&note)?;
+    let mut out = String::from("<ul class=\"toc\">");
+    for heading in scan::headings(&source) {
+        let text =
+            match markdown_inline_to_html(&heading.text, &TemplateMap::new(), &context.directory) {
+                Ok(parsed) => parsed.html,
+                Err(_) => crate::escape_html(heading.text.trim()),
+            };
+        out.push_str(
+            format!(
+                "<li class=\"toc-level-{}\"><a href=\"#{}\">{text}</a></li>",
+                heading.level,
+                slug(&heading.text)
+            )
+            .as_str(),
+        );
+    }
+    out.push_str("</ul>");
+    let mut out = html(out);
+    out.0.dependencies.transclusions.insert(note);
+    Ok(out)
+}
+
+/// `{{backlinks}}`, `{{backlinks|note}}`: a list of the notes that link to a note.
+pub fn backlinks(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_at_most(&args, 1, "backlinks")?;
+    let note = target_note(&args, context, "backlinks")?;
+    let notes = context.vault().backlinks(&note);
+    Ok(note_list("backlinks", &notes, context))
+}
+
+/// `{{tagged|tag}}`: a list of the notes with the given tag.
+pub fn tagged(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_exactly(&args, 1, "tagged")?;
+    let tag = args.get(0).unwrap().trim().trim_start_matches('#');
+    let notes = context.vault().tagged(tag);
+    Ok(note_list("tagged", &notes, context))
+}
+
+/// `{{if|condition|then}}`, `{{if|condition|then|else}}`: renders `then` when the condition
+/// renders to anything but whitespace, and `else` (or nothing) otherwise. Only the chosen
+/// branch is rendered.
+pub fn if_(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    if !(2..=3).contains(&args.len()) {
+        return Err(ParseError::empty(
+            format!(
+                "{{{{if}}}} takes a condition and one or two branches, not {} arguments",
+                args.len()
+            )
+            .as_str(),
+        ));
+    }
+    let condition = args.argument(0).unwrap().render_inline(context)?;
+    let branch = if condition.html.trim().is_empty() {
+        args.argument(2)
+    } else {
+        args.argument(1)
+    };
+    match branch {
+        Some(branch) => Ok((branch.render_inline(context)?, ExitMode::EndOfFile)),
+        None => Ok(html(String::new())),
+    }
+}
+
+/// `{{switch|value|case=result|...|fallback}}`: renders the result of the case equal to the
+/// trimmed value, as written. Only a value that calls a template is rendered first, and its
+/// output compared instead. Without a match it renders `default=`, the positional fallback, or
+/// nothing.
+pub fn switch(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_at_most(&args, 2, "switch")?;
+    let value = args.require(0, "switch")?;
+    let value = match value.raw().contains("{{") {
+        true => value.render_inline(context)?.html,
+        false => value.literal(),
+    };
+    let value = value.trim();
+    let result = args
+        .named_arguments()
+        .filter(|(key, _)| *key != "default")
+        .find(|(key, _)| key.trim() == value)
+        .map(|(_, result)| result)
+        .or_else(|| args.named_argument("default"))
+        .or_else(|| args.argument(1));
+    match result {
+        Some(result) => Ok((result.render_inline(context)?, ExitMode::EndOfFile)),
+        None => Ok(html(String::new())),
+    }
+}
+
+/// `{{lower|text}}`: renders `text` in lower case. Markup is left alone.
+pub fn lower(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_exactly(&args, 1, "lower")?;
+    let mut parsed = args.argument(0).unwrap().render_inline(context)?;
+    parsed.html = map_text(&parsed.html, str::to_lowercase);
+    Ok((parsed, ExitMode::EndOfFile))
+}
+
+/// `{{upper|text}}`: renders `text` in upper case. Markup is left alone.
+pub fn upper(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_exactly(&args, 1, "upper")?;
+    let mut parsed = args.argument(0).unwrap().render_inline(context)?;
+    parsed.html = map_text(&parsed.html, str::to_uppercase);
+    Ok((parsed, ExitMode::EndOfFile))
+}
+
+/// `{{note_title}}`, `{{note_title|note}}`: the `#` title of a note, or its file name if it has
+/// none.
+pub fn note_title(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_at_most(&args, 1, "note_title")?;
+    let note = target_note(&args, context, "note_title")?;
+    let mut out = html(title_of(&note, context));
+    out.0.dependencies.titles.insert(note);
+    Ok(out)
+}
+
+/// `{{link_count}}`, `{{link_count|note}}`: how many wiki-links a note contains.
+pub fn link_count(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_at_most(&args, 1, "link_count")?;
+    let note = target_note(&args, context, "link_count")?;
+    let source = read_body(&note)?;
+    let mut out = html(scan::wiki_links(&source).len().to_string());
+    out.0.dependencies.transclusions.insert(note);
+    Ok(out)
+}
+
+/// `{{calendar}}`, `{{calendar|YYYY-MM}}`: a table of the days of a month, this month by
+/// default, where the days with a daily note link to it. `pattern=` is the file name pattern of
+/// daily notes, `%Y-%m-%d` by default.
+pub fn calendar(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_at_most(&args, 1, "calendar")?;
+    let pattern = args.value_or("pattern", DAILY_NOTE_PATTERN);
+    let today = Date::today();
+    let first = match args.get(0) {
+        Some(month) => Date::parse(&format!("{}-01", month.trim())).ok_or_else(|| {
+            ParseError::empty(
+                format!("{{{{calendar}}}}: {month} is not a month like 2024-01").as_str(),
+            )
+        })?,
+        None => Date::new(today.year, today.month, 1).unwrap(),
+    };
+    let mut out = format!(
+        "<table class=\"calendar\"><caption>{} {}</caption><thead><tr>",
+        first.month_name(),
+        first.year
+    );
+    for name in WEEKDAY_NAMES {
+        out.push_str(format!("<th>{}</th>", &name[..3]).as_str());
+    }
+    out.push_str("</tr></thead><tbody><tr>");
+    out.push_str(&"<td></td>".repeat(first.weekday() as usize));
+    for day in 1..=days_in_month(first.year, first.month) {
+        let date = Date { day, ..first };
+        if day > 1 && date.weekday() == 0 {
+            out.push_str("</tr><tr>");
+        }
+        out.push_str(match date == today {
+            true => "<td class=\"today\">",
+            false => "<td>",
+        });
+        let name = date.format(pattern);
+        match context.resolve(&name) {
+            Resolution::Missing(_) => out.push_str(&day.to_string()),
+            resolution => out.push_str(&context.templates.link_generator().render(&NoteLink {
+                name: &name,
+                target: resolution.path(),
+                exists: true,
+                label: &day.to_string(),
+            })),
+        }
+        out.push_str("</td>");
+    }
+    let last = Date::new(
+        first.year,
+        first.month,
+        days_in_month(first.year, first.month),
+    )
+    .unwrap();
+    out.push_str(&"<td></td>".repeat(6 - last.weekday() as usize));
+    out.push_str("</tr></tbody></table>");
+    let mut out = html(out);
+    out.0.dependencies.vault = true;
+    Ok(out)
+}
+
+/// `{{journal_nav}}`: links to the daily notes before and after the one being rendered, skipping
+/// the days without a note. `pattern=` is the file name pattern of daily notes, `%Y-%m-%d` by
+/// default.
+pub fn journal_nav(args: TemplateArgs, context: &TemplateContext) -> TemplateResult {
+    expect_at_most(&args, 0, "journal_nav")?;
+    let pattern = args.value_or("pattern", DAILY_NOTE_PATTERN);
+    let name = context
+        .note_name()
+        .ok_or_else(|| ParseError::empty("{{journal_nav}} only works in a note"))?;
+    let date = Date::parse_with(&name, pattern).ok_or_else(|| {
+        ParseError::empty(
+            format!("{{{{journal_nav}}}}: {name} is not named like a daily note, {pattern}")
+                .as_str(),
+        )
+    })?;
+    let mut previous: Option<(Date, PathBuf)> = None;
+    let mut next: Option<(Date, PathBuf)> = None;
+    for note in context.vault().notes() {
+        let other = match note.file_stem().map(|x| x.to_string_lossy()) {
+            Some(stem) => match Date::parse_with(&stem, pattern) {
+                Some(x) => x,
+                None => continue,
+            },
+            None => continue,
+        };
+        if other < date && previous.as_ref().is_none_or(|(x, _)| other > *x) {
+            previous = Some((other, note));
+        } else if other > date && next.as_ref().is_none_or(|(x, _)| other < *x) {
+            next = Some((other, note));
+        }
+    }
+    let mut links = Vec::new();
+    if let Some((_, note)) = previous {
+        links.push(format!(
+            "<span class=\"previous\">← {}</span>",
+            link_to(&note, context)
+        ));
+    }
+    if let Some((_, note)) = next {
+        links.push(format!(
+            "<span class=\"next\">{} →</span>",
+            link_to(&note, context)
+        ));
+    }
+    let out = format!("<nav class=\"journal\">{}</nav>", links.join(" "));
+    let mut out = html(out);
+    out.0.dependencies.vault = true;
+    Ok(out)
+}
+
+fn html(html: String) -> (ParsedHTML, ExitMode) {
+    let mut parsed = ParsedHTML::new();
+    parsed.html = html;
+    (parsed, ExitMode::EndOfFile)
+}
+
+fn target_note(
+    args: &TemplateArgs,
+    context: &TemplateContext,
+    template: &str,
+) -> Result<PathBuf, ParseError> {
+    if let Some(name) = args.get(0) {
+        return match context.resolve(name) {
+            Resolution::Missing(_) => Err(ParseError::empty(
+                format!("{{{{{template}}}}}: there is no note {name}").as_str(),
+            )),
+            resolution => Ok(resolution.path().to_path_buf()),
+        };
+    }
+    match context.note_path() {
+        Some(x) => Ok(x.to_path_buf()),
+        None => Err(ParseError::empty(
+            format!("{{{{{template}}}}} needs a note argument outside of a note").as_str(),
+        )),
+    }
+}
+
+fn read_body(note: &Path) -> Result<String, ParseError> {
+    match std::fs::read_to_string(note) {
+        Ok(contents) => Ok(FrontMatter::split(&contents).1.to_string()),
+        Err(e) => Err(ParseError::empty(
+            format!("Could not read {}, error: {e}", note.display()).as_str(),
+        )),
+    }
+}
+
+fn title_of(note: &Path, context: &TemplateContext) -> String {
+    match context.vault().title(note) {
+        Some(title) => title,
+        None => match note.file_stem() {
+            Some(stem) => crate::escape_html(&stem.to_string_lossy()),
+            None => String::new(),
+        },
+    }
+}
+
+fn note_list(class: &str, notes: &[PathBuf], context: &TemplateContext) -> (ParsedHTML, ExitMode) {
+    let mut out = format!("<ul class=\"{class}\">");
+    for note in notes {
+        out.push_str("<li>");
+        out.push_str(&link_to(note, context));
+        out.push_str("</li>");
+    }
+    out.push_str("</ul>");
+    let mut out = html(out);
+    out.0.dependencies.vault = true;
+    out
+}
+
+fn link_to(note: &Path, context: &TemplateContext) -> String {
+    let name = match note.file_stem() {
+        Some(x) => x.to_string_lossy().to_string(),
+        None => String::new(),
+    };
+    let label = title_of(note, context);
+    context.templates.link_generator().render(&NoteLink {
+        name: &name,
+        target: note,
+        exists: true,
+        label: &label,
+    })
+}
+
+/// Applies `f` to the text of an HTML fragment, leaving tags and entities untouched.
+fn map_text(html: &str, f: fn(&str) -> String) -> String {
+    let mut out = String::new();
+    let mut text = String::new();
+    let mut chars = html.chars();
+    while let Some(character) = chars.next() {
+        let terminator = match character {
+            '<' => '>',
+            '&' => ';',
+            _ => {
+                text.push(character);
+                continue;
+            }
+        };
+        out.push_str(&f(&text));
+        text.clear();
+        out.push(character);
+        for character in chars.by_ref() {
+            out.push(character);
+            if character == terminator {
+                break;
+            }
+        }
+    }
+    out.push_str(&f(&text));
+    out
+}
+
+fn expect_at_most(args: &TemplateArgs, count: usize, template: &str) -> Result<(), ParseError> {
+    if args.len() > count {
+        Err(ParseError::empty(
+            format!(
+                "{{{{{template}}}}} takes at most {count} positional argument(s), not {}",
+                args.len()
+            )
+            .as_str(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn expect_exactly(args: &TemplateArgs, count: usize, template: &str) -> Result<(), ParseError> {
+    if args.len() != count {
+        Err(ParseError::empty(
+            format!(
+                "{{{{{template}}}}} takes {count} positional argument(s), not {}",
+                args.len()
+            )
+            .as_str(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown_to_html;
+
+    /// A directory of its own for each test, holding `notes` as `(name, source)` pairs.
+    fn vault(name: &str, notes: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("confoosion-builtins-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        for (note, source) in notes {
+            std::fs::write(directory.join(format!("{note}.md")), source).unwrap();
+        }
+        directory
+    }
+
+    /// Renders `source` as the note `note` of `directory`, which does not have to exist.
+    fn render_as(directory: &Path, note: &str, source: &str) -> Result<String, ParseError> {
+        let templates = TemplateMap::with_builtins();
+        let file = directory.join(format!("{note}.md"));
+        markdown_to_html(source, file, &templates).map(|parsed| parsed.html)
+    }
+
+    fn render(source: &str) -> Result<String, ParseError> {
+        render_as(&std::env::temp_dir(), "test", source)
+    }
+
+    fn fails(result: Result<String, ParseError>, comment: &str) {
+        match result {
+            Ok(html) => panic!("expected an error, got {html}"),
+            Err(e) => assert!(e.comment.contains(comment), "{}", e.comment),
+        }
+    }
+
+    #[test]
+    fn date() {
+        let today = Date::today();
+        assert!(render("{{date}}").unwrap().contains(&today.to_string()));
+        assert!(render("{{date|%Y/%m|offset=-1}}")
+            .unwrap()
+            .contains(&today.add_days(-1).format("%Y/%m")));
+        fails(render("{{date|a|b}}"), "at most 1");
+        fails(render("{{date|offset=x}}"), "could not be read");
+    }
+
+    #[test]
+    fn include() {
+        let directory = vault(
+            "include",
+            &[("a", "Text of **a**"), ("self", "{{include|self}}")],
+        );
+        let html = render_as(&directory, "b", "{{include|a}}").unwrap();
+        assert!(html.contains("Text of <b>a</b>"), "{html}");
+        fails(
+            render_as(&directory, "b", "{{include|missing}}"),
+            "there is no note missing",
+        );
+        fails(
+            markdown_file_to_html(directory.join("self.md"), &TemplateMap::with_builtins())
+                .map(|x| x.html),
+            "includes itself",
+        );
+    }
+
+    #[test]
+    fn toc() {
+        let directory = vault("toc", &[("a", "# Title\n\n## First part\n\ntext\n")]);
+        let html = render_as(&directory, "b", "{{toc|a}}").unwrap();
+        assert!(html.contains("<ul class=\"toc\">"), "{html}");
+        assert!(
+            html.contains("<li class=\"toc-level-2\"><a href=\"#first-part\">First part</a></li>"),
+            "{html}"
+        );
+        fails(
+            render_as(&directory, "b", "{{toc|z}}"),
+            "there is no note z",
+        );
+    }
+
+    #[test]
+    fn backlinks() {
+        let directory = vault(
+            "backlinks",
+            &[
+                ("a", "# Alpha\n\nSee [[b]]."),
+                ("b", "# Bravo\n"),
+                ("c", ""),
+            ],
+        );
+        let html = render_as(&directory, "c", "{{backlinks|b}}").unwrap();
+        assert!(html.contains("<ul class=\"backlinks\"><li>"), "{html}");
+        assert!(html.contains("Alpha"), "{html}");
+        let html = render_as(&directory, "c", "{{backlinks}}").unwrap();
+        assert!(html.contains("<ul class=\"backlinks\"></ul>"), "{html}");
+    }
+
+    #[test]
+    fn tagged() {
+        let directory = vault(
+            "tagged",
+            &[
+                ("a", "# Alpha\n\nAbout #project/one"),
+                ("b", "---\ntags: [project]\n---\n# Bravo\n"),
+                ("c", "# Charlie\n#other"),
+            ],
+        );
+        let html = render_as(&directory, "d", "{{tagged|#project}}").unwrap();
+        assert!(html.contains("Alpha") && html.contains("Bravo"), "{html}");
+        assert!(!html.contains("Charlie"), "{html}");
+        fails(render("{{tagged}}"), "takes 1 positional");
+    }
+
+    #[test]
+    fn if_() {
+        assert!(render("{{if|x|yes|no}}").unwrap().contains("yes"));
+        assert!(render("{{if| |yes|no}}").unwrap().contains("no"));
+        assert!(!render("{{if| |yes}}").unwrap().contains("yes"));
+        // The branch that is not taken is never rendered, so its errors do not count.
+        assert!(render("{{if|x|yes|{{missing}}}}").unwrap().contains("yes"));
+        fails(render("{{if|x}}"), "one or two branches");
+    }
+
+    #[test]
+    fn switch() {
+        assert!(render("{{switch|b|a=1|b=2|0}}").unwrap().contains('2'));
+        assert!(render("{{switch| c |a=1|c=3}}").unwrap().contains('3'));
+        assert!(render("{{switch|z|a=1|0}}").unwrap().contains('0'));
+        assert!(render("{{switch|z|a=1|default=d}}").unwrap().contains('d'));
+        assert!(!render("{{switch|z|a=1}}").unwrap().contains('1'));
+        // Keys are compared with the value as written, not with its rendered markup.
+        assert!(render("{{switch|__a__|__a__=yes|no}}")
+            .unwrap()
+            .contains("yes"));
+        assert!(render("{{switch|{{lower|B}}|b=yes|no}}")
+            .unwrap()
+            .contains("yes"));
+        fails(render("{{switch}}"), "requires at least 1");
+    }
+
+    #[test]
+    fn lower_and_upper() {
+        assert!(render("{{lower|A **B** &amp; C}}")
+            .unwrap()
+            .contains("a <b>b</b> &amp; c"));
+        assert!(render("{{upper|a *b*}}").unwrap().contains("A <i>B</i>"));
+        fails(render("{{upper|a|b}}"), "takes 1 positional");
+    }
+
+    #[test]
+    fn note_title() {
+        let directory = vault("note-title", &[("a", "# The *first*\n"), ("b", "no title")]);
+        let html = render_as(&directory, "c", "{{note_title|a}}").unwrap();
+        assert!(html.contains("The <i>first</i>"), "{html}");
+        let html = render_as(&directory, "c", "{{note_title|b}}").unwrap();
+        assert!(html.contains('b'), "{html}");
+        fails(render_as(&directory, "c", "{{note_title|z}}"), "no note z");
+    }
+
+    #[test]
+    fn link_count() {
+        let directory = vault("link-count", &[("a", "[[b]] [[c|see c]] `[[d]]`")]);
+        let html = render_as(&directory, "e", "{{link_count|a}}").unwrap();
+        assert!(html.contains('2'), "{html}");
+    }
+
+    #[test]
+    fn calendar() {
+        let directory = vault("calendar", &[("2024-02-03", "")]);
+        let html = render_as(&directory, "x", "{{calendar|2024-02}}").unwrap();
+        assert!(html.contains("<caption>February 2024</caption>"), "{html}");
+        assert!(html.contains("<td>29</td>"), "{html}");
+        assert!(!html.contains("<td>30</td>"), "{html}");
+        // Weeks start on Monday, and the first was a Thursday.
+        assert!(
+            html.contains("<tr><td></td><td></td><td></td><td>1</td>"),
+            "{html}"
+        );
+        assert!(html.contains("2024-02-03"), "{html}");
+        fails(render("{{calendar|February}}"), "not a month");
+    }
+
+    #[test]
+    fn journal_nav() {
+        let directory = vault(
+            "journal-nav",
+            &[("2024-01-30", ""), ("2024-02-01", ""), ("2024-02-10", "")],
+        );
+        let html = render_as(&directory, "2024-02-03", "{{journal_nav}}").unwrap();
+        assert!(html.contains("<nav class=\"journal\">"), "{html}");
+        assert!(html.contains("<span class=\"previous\">"), "{html}");
+        assert!(
+            html.contains("2024-02-01") && html.contains("2024-02-10"),
+            "{html}"
+        );
+        assert!(!html.contains("2024-01-30"), "{html}");
+        fails(
+            render_as(&directory, "x", "{{journal_nav}}"),
+            "not named like",
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    error::ParseError,
+    frontmatter::FrontMatter,
+    markdown_charbuff_to_html, markdown_inline_to_html_at,
+    putback::PutBackChars,
+    resolver::Resolution,
+    template::TemplateMap,
+    vault::{DirectoryVault, VaultQuery},
+    ExitMode, ParsedHTML,
+};
+
+/// A note that is being rendered.
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub path: PathBuf,
+    pub front_matter: FrontMatter,
+}
+
+impl Note {
+    /// The name other notes link to this one by.
+    pub fn name(&self) -> String {
+        match self.path.file_stem() {
+            Some(x) => x.to_string_lossy().to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+/// Where in a note something was written. Lines and columns start at one; zero means the
+/// position is unknown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn is_known(self) -> bool {
+        self.line != 0
+    }
+
+    /// The position right after `text`, if `text` starts here.
+    pub fn advance(self, text: &str) -> Self {
+        if !self.is_known() {
+            return self;
+        }
+        let mut out = self;
+        for character in text.chars() {
+            if character == '\n' {
+                out.line += 1;
+                out.column = 1;
+            } else {
+                out.column += 1;
+            }
+        }
+        out
+    }
+}
+
+/// Everything a template may know about the place it is called from.
+pub struct TemplateContext<'a> {
+    /// The templates and render settings in effect: link resolution, link generation and the
+    /// title cache.
+    pub templates: &'a TemplateMap,
+    /// The directory links and templates are resolved in.
+    pub directory: PathBuf,
+    /// The note being rendered. `None` when rendering a string rather than a file.
+    pub note: Option<Rc<Note>>,
+    /// Where the template call starts.
+    pub span: Span,
+    directory_vault: DirectoryVault<'a>,
+}
+
+impl<'a> TemplateContext<'a> {
+    pub fn new(
+        templates: &'a TemplateMap,
+        directory: PathBuf,
+        note: Option<Rc<Note>>,
+        span: Span,
+    ) -> Self {
+        Self {
+            templates,
+            directory_vault: DirectoryVault {
+                directory: directory.clone(),
+                templates,
+            },
+            directory,
+            note,
+            span,
+        }
+    }
+
+    pub fn note_path(&self) -> Option<&Path> {
+        self.note.as_ref().map(|note| note.path.as_path())
+    }
+
+    pub fn note_name(&self) -> Option<String> {
+        self.note.as_ref().map(|note| note.name())
+    }
+
+    pub fn front_matter(&self) -> Option<&FrontMatter> {
+        self.note.as_ref().map(|note| &note.front_matter)
+    }
+
+    /// The vault index set with [`TemplateMap::set_vault`], or else the notes in the current
+    /// directory.
+    pub fn vault(&self) -> &dyn VaultQuery {
+        match self.templates.vault() {
+            Some(vault) => vault,
+            None => &self.directory_vault,
+        }
+    }
+
+    pub fn resolve(&self, name: &str) -> Resolution {
+        self.templates
+            .resolver()
+            .resolve(name, &self.directory, self.templates.title_cache())
+    }
+
+    /// Renders Markdown as a block, the way a note's body is rendered.
+    pub fn render(&self, text: &str) -> Result<(ParsedHTML, ExitMode), ParseError> {
+        self.render_at(text, Span::default())
+    }
+
+    /// Renders Markdown without wrapping it in a paragraph.
+    pub fn render_inline(&self, text: &str) -> Result<ParsedHTML, ParseError> {
+        self.render_inline_at(text, Span::default())
+    }
+
+    /// Like [`Self::render`], for text that starts at `span` in the current note.
+    pub fn render_at(&self, text: &str, span: Span) -> Result<(ParsedHTML, ExitMode), ParseError> {
+        let mut chars: PutBackChars = text.chars().into();
+        if span.is_known() {
+            chars.line_number = span.line;
+            chars.column_number = span.column;
+        }
+        markdown_charbuff_to_html(&mut chars, self.templates, &self.directory)
+    }
+
+    /// Like [`Self::render_inline`], for text that starts at `span` in the current note.
+    pub fn render_inline_at(&self, text: &str, span: Span) -> Result<ParsedHTML, ParseError> {
+        markdown_inline_to_html_at(text, self.templates, &self.directory, span)
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::timezone::local_offset;
+
+/// The file name pattern of daily notes, for [`Date::format`].
+pub const DAILY_NOTE_PATTERN: &str = "%Y-%m-%d";
+
+/// A day in the proleptic Gregorian calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+pub const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Weekday names, starting on Monday.
+pub const WEEKDAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+impl Date {
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(Self { year, month, day })
+    }
+
+    /// Today in the local time zone, see [`local_offset`].
+    pub fn today() -> Self {
+        let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(x) => x.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+        Self::from_days((seconds + local_offset(seconds)).div_euclid(86_400))
+    }
+
+    /// The date `days` days after 1970-01-01.
+    pub fn from_days(days: i64) -> Self {
+        // Howard Hinnant's civil_from_days.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+        Self { year, month, day }
+    }
+
+    /// The number of days since 1970-01-01.
+    pub fn days(self) -> i64 {
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = self.month as i64;
+        let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    pub fn add_days(self, days: i64) -> Self {
+        Self::from_days(self.days() + days)
+    }
+
+    /// Zero for Monday up to six for Sunday.
+    pub fn weekday(self) -> u32 {
+        (self.days() + 3).rem_euclid(7) as u32
+    }
+
+    /// Reads `YYYY-MM-DD`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim().splitn(3, '-');
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        let day = parts.next()?.parse().ok()?;
+        Self::new(year, month, day)
+    }
+
+    /// Reads a date written by [`Date::format`] with `pattern`, such as a daily note's name.
+    /// The pattern needs a year, a month and a day; weekday names have to match the date.
+    pub fn parse_with(text: &str, pattern: &str) -> Option<Self> {
+        let (mut year, mut month, mut day, mut weekday) = (None, None, None, None);
+        let mut rest = text;
+        let mut chars = pattern.chars();
+        while let Some(character) = chars.next() {
+            if character != '%' {
+                rest = rest.strip_prefix(character)?;
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => year = Some(take_number(&mut rest, 4, 4)? as i32),
+                Some('m') => month = Some(take_number(&mut rest, 2, 2)?),
+                Some('d') => day = Some(take_number(&mut rest, 2, 2)?),
+                Some('e') => day = Some(take_number(&mut rest, 1, 2)?),
+                Some('B') => month = Some(take_name(&mut rest, &MONTH_NAMES, None)? + 1),
+                Some('b') => month = Some(take_name(&mut rest, &MONTH_NAMES, Some(3))? + 1),
+                Some('A') => weekday = Some(take_name(&mut rest, &WEEKDAY_NAMES, None)?),
+                Some('a') => weekday = Some(take_name(&mut rest, &WEEKDAY_NAMES, Some(3))?),
+                Some(other) if other != '%' => {
+                    rest = rest.strip_prefix('%')?.strip_prefix(other)?
+                }
+                _ => rest = rest.strip_prefix('%')?,
+            }
+        }
+        let date = Self::new(year?, month?, day?)?;
+        match rest.is_empty() && weekday.is_none_or(|x| x == date.weekday()) {
+            true => Some(date),
+            false => None,
+        }
+    }
+
+    /// Formats the date with `%Y` (year), `%m` (month), `%d` (day), `%e` (day without padding),
+    /// `%B` and `%b` (full and short month name), `%A` and `%a` (full and short weekday name),
+    /// and `%%`. Everything else is copied as is.
+    pub fn format(self, pattern: &str) -> String {
+        let mut out = String::new();
+        let mut chars = pattern.chars();
+        while let Some(character) = chars.next() {
+            if character != '%' {
+                out.push(character);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => out.push_str(format!("{:04}", self.year).as_str()),
+                Some('m') => out.push_str(format!("{:02}", self.month).as_str()),
+                Some('d') => out.push_str(format!("{:02}", self.day).as_str()),
+                Some('e') => out.push_str(self.day.to_string().as_str()),
+                Some('B') => out.push_str(self.month_name()),
+                Some('b') => out.push_str(&self.month_name()[..3]),
+                Some('A') => out.push_str(self.weekday_name()),
+                Some('a') => out.push_str(&self.weekday_name()[..3]),
+                Some('%') => out.push('%'),
+                Some(other) => {
+                    out.push('%');
+                    out.push(other);
+                }
+                None => out.push('%'),
+            }
+        }
+        out
+    }
+
+    pub fn month_name(self) -> &'static str {
+        MONTH_NAMES[self.month as usize - 1]
+    }
+
+    pub fn weekday_name(self) -> &'static str {
+        WEEKDAY_NAMES[self.weekday() as usize]
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+pub fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Takes `min` to `max` digits from the start of `text`.
+fn take_number(text: &mut &str, min: usize, max: usize) -> Option<u32> {
+    let length = text
+        .bytes()
+        .take(max)
+        .take_while(u8::is_ascii_digit)
+        .count();
+    if length < min {
+        return None;
+    }
+    let number = text[..length].parse().ok()?;
+    *text = &text[length..];
+    Some(number)
+}
+
+/// Takes one of `names`, or its first `length` characters, from the start of `text`, ignoring
+/// case, and returns its index.
+fn take_name(text: &mut &str, names: &[&str], length: Option<usize>) -> Option<u32> {
+    names.iter().enumerate().find_map(|(index, name)| {
+        let name = &name[..length.unwrap_or(name.len())];
+        let start = text.get(..name.len())?;
+        if !start.eq_ignore_ascii_case(name) {
+            return None;
+        }
+        *text = &text[name.len()..];
+        Some(index as u32)
+    })
+}
@@ -0,0 +1,63 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+/// Everything the output of a render was built from, besides the note itself.
+///
+/// When any of these change, the note has to be rendered again. Links are resolved by file
+/// name, title and alias, so adding or renaming notes next to a note can change it as well; the
+/// `links` set only covers the notes its links ended up at.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dependencies {
+    /// The names of the templates that were called.
+    pub templates: BTreeSet<String>,
+    /// The template notes those templates came from.
+    pub template_notes: BTreeSet<PathBuf>,
+    /// Where wiki-links pointed, including notes that do not exist yet.
+    pub links: BTreeSet<PathBuf>,
+    /// The notes whose titles were used as link labels.
+    pub titles: BTreeSet<PathBuf>,
+    /// The notes whose contents were read, by `{{include}}` for example.
+    pub transclusions: BTreeSet<PathBuf>,
+    /// Whether the output depends on the vault as a whole, like a list of backlinks does.
+    pub vault: bool,
+}
+
+impl Dependencies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn merge(&mut self, mut other: Dependencies) {
+        self.templates.append(&mut other.templates);
+        self.template_notes.append(&mut other.template_notes);
+        self.links.append(&mut other.links);
+        self.titles.append(&mut other.titles);
+        self.transclusions.append(&mut other.transclusions);
+        self.vault |= other.vault;
+    }
+
+    /// Every file a change to which can change the output.
+    pub fn files(&self) -> BTreeSet<&Path> {
+        self.template_notes
+            .iter()
+            .chain(&self.links)
+            .chain(&self.titles)
+            .chain(&self.transclusions)
+            .map(PathBuf::as_path)
+            .collect()
+    }
+
+    pub fn depends_on<P: AsRef<Path>>(&self, file: P) -> bool {
+        let file = file.as_ref();
+        self.template_notes.contains(file)
+            || self.links.contains(file)
+            || self.titles.contains(file)
+            || self.transclusions.contains(file)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
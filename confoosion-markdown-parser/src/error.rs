@@ -30,6 +30,20 @@ impl ParseError {
             column: chars.column_number,
         }
     }
+    pub fn at(line: usize, column: usize, message: String) -> Self {
+        Self {
+            comment: message,
+            line,
+            column,
+        }
+    }
+    /// The line the error occurred on, or zero if it is not tied to a place in the note.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+    pub fn column(&self) -> usize {
+        self.column
+    }
     pub fn empty(message: &str) -> Self {
         Self {
             comment: message.to_string(),
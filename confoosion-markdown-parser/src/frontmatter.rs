@@ -0,0 +1,157 @@
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontMatter {
+    entries: Vec<(String, FrontMatterValue)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrontMatterValue {
+    Text(String),
+    List(Vec<String>),
+}
+
+impl FrontMatter {
+    /// Splits a `---` delimited block of `key: value` lines off the start of a note.
+    ///
+    /// Returns the front matter, the remaining body, and the number of lines the block took up.
+    /// Notes without front matter are returned unchanged.
+    pub fn split(contents: &str) -> (Self, &str, usize) {
+        let rest = match contents.strip_prefix("---\n") {
+            Some(x) => x,
+            None => match contents.strip_prefix("---\r\n") {
+                Some(x) => x,
+                None => return (Self::default(), contents, 0),
+            },
+        };
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        for line in rest.split_inclusive('\n') {
+            offset += line.len();
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line == "---" || line == "..." {
+                return (Self::parse(&lines), &rest[offset..], lines.len() + 2);
+            }
+            lines.push(line);
+        }
+        (Self::default(), contents, 0)
+    }
+
+    fn parse(lines: &[&str]) -> Self {
+        let mut entries: Vec<(String, FrontMatterValue)> = Vec::new();
+        for line in lines {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if let Some(item) = trimmed.strip_prefix("- ") {
+                if let Some((_, value)) = entries.last_mut() {
+                    match value {
+                        FrontMatterValue::List(items) => items.push(unquote(item)),
+                        FrontMatterValue::Text(text) if text.is_empty() => {
+                            *value = FrontMatterValue::List(vec![unquote(item)])
+                        }
+                        FrontMatterValue::Text(_) => (),
+                    }
+                }
+                continue;
+            }
+            let (key, value) = match trimmed.split_once(':') {
+                Some(x) => x,
+                None => continue,
+            };
+            let value = value.trim();
+            let value = match value
+                .strip_prefix('[')
+                .and_then(|inner| inner.strip_suffix(']'))
+            {
+                Some(inner) => FrontMatterValue::List(
+                    inner
+                        .split(',')
+                        .map(unquote)
+                        .filter(|item| !item.is_empty())
+                        .collect(),
+                ),
+                None => FrontMatterValue::Text(unquote(value)),
+            };
+            entries.push((key.trim().to_string(), value));
+        }
+        Self { entries }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&FrontMatterValue> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn text(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            FrontMatterValue::Text(x) => Some(x),
+            FrontMatterValue::List(_) => None,
+        }
+    }
+
+    /// Reads `key` as a list. A plain value counts as a list of one.
+    pub fn list(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(FrontMatterValue::List(items)) => items.clone(),
+            Some(FrontMatterValue::Text(x)) if !x.is_empty() => vec![x.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn aliases(&self) -> Vec<String> {
+        let mut out = self.list("aliases");
+        out.append(&mut self.list("alias"));
+        out
+    }
+
+    /// The `tags` (or `tag`) list, without the `#` some write in front of tags.
+    pub fn tags(&self) -> Vec<String> {
+        let mut out = self.list("tags");
+        out.append(&mut self.list("tag"));
+        out.iter()
+            .map(|x| x.trim().trim_start_matches('#').to_string())
+            .filter(|x| !x.is_empty())
+            .collect()
+    }
+
+    /// The notes named by `parent` and `parents`, as link targets. They may be written as
+    /// `[[wiki-links]]`.
+    pub fn parents(&self) -> Vec<String> {
+        let mut out = self.list("parent");
+        out.append(&mut self.list("parents"));
+        out.iter()
+            .map(|x| {
+                let x = x.trim();
+                let x = x
+                    .strip_prefix("[[")
+                    .and_then(|x| x.strip_suffix("]]"))
+                    .unwrap_or(x);
+                x.split('|').next().unwrap_or_default().trim().to_string()
+            })
+            .filter(|x| !x.is_empty())
+            .collect()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &FrontMatterValue)> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|inner| inner.strip_suffix(quote))
+        {
+            return inner.to_string();
+        }
+    }
+    value.to_string()
+}
@@ -1,17 +1,88 @@
+pub mod arguments;
+pub mod builtins;
+pub mod context;
+pub mod date;
+pub mod dependencies;
 pub mod error;
+pub mod frontmatter;
+pub mod limits;
+pub mod links;
+pub mod notetemplate;
 pub mod putback;
+pub mod resolver;
+pub mod scan;
+pub mod script;
 pub mod template;
+pub mod timezone;
+pub mod title;
+pub mod vault;
 
+use arguments::{Argument, TemplateArgs};
+use context::{Note, Span};
+use dependencies::Dependencies;
 use error::ParseError;
+use frontmatter::FrontMatter;
+use links::NoteLink;
 use putback::PutBackChars;
+use resolver::Resolution;
 use std::path::{Path, PathBuf};
 use template::{read_template_argument, TemplateMap};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParsedHTML {
     pub html: String,
     pub links_to: Vec<String>,
+    /// The existing notes named by `parent` or `parents` in the front matter, by file name.
     pub parents: Vec<String>,
+    /// Wiki-link targets, as written, that did not resolve to an existing note.
+    pub broken_links: Vec<String>,
+    /// Image sources as written, for copying local images along with the page.
+    pub images: Vec<String>,
+    /// The note's tags: those in its front matter, then the `#tags` in its text, without
+    /// duplicates.
+    pub tags: Vec<String>,
+    pub diagnostics: Vec<ParseError>,
+    pub dependencies: Dependencies,
+}
+
+impl ParsedHTML {
+    pub fn new() -> Self {
+        Self {
+            html: String::new(),
+            links_to: Vec::new(),
+            parents: Vec::new(),
+            broken_links: Vec::new(),
+            images: Vec::new(),
+            tags: Vec::new(),
+            diagnostics: Vec::new(),
+            dependencies: Dependencies::new(),
+        }
+    }
+
+    pub fn append(&mut self, mut other: ParsedHTML) {
+        self.html.push_str(&other.html);
+        self.links_to.append(&mut other.links_to);
+        self.parents.append(&mut other.parents);
+        self.broken_links.append(&mut other.broken_links);
+        self.images.append(&mut other.images);
+        for tag in other.tags {
+            push_tag(&mut self.tags, tag);
+        }
+        self.diagnostics.append(&mut other.diagnostics);
+        self.dependencies.merge(other.dependencies);
+    }
+}
+
+fn push_tag(tags: &mut Vec<String>, tag: String) {
+    if !tags.contains(&tag) {
+        tags.push(tag);
+    }
+}
+
+impl Default for ParsedHTML {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -33,6 +104,7 @@ enum ExclusiveModifier {
     Paragraph,
     Link,
     Image,
+    NoWiki,
     EndOfArgument,
     EndOfTemplate,
     EndOfLink,
@@ -51,10 +123,7 @@ pub enum ExitMode {
     EndOfFile,
 }
 
-pub fn markdown_file_to_html<T>(
-    file: T,
-    templates: &mut TemplateMap,
-) -> Result<ParsedHTML, ParseError>
+pub fn markdown_file_to_html<T>(file: T, templates: &TemplateMap) -> Result<ParsedHTML, ParseError>
 where
     T: AsRef<Path>,
 {
@@ -70,6 +139,19 @@ where
             ));
         }
     };
+    markdown_to_html(&contents, file, templates)
+}
+
+/// Renders the contents of a note without reading it. `file` is where the note lives, which
+/// decides where its links and templates are looked up; it does not have to exist.
+pub fn markdown_to_html<T>(
+    contents: &str,
+    file: T,
+    templates: &TemplateMap,
+) -> Result<ParsedHTML, ParseError>
+where
+    T: AsRef<Path>,
+{
     let dir = match file.as_ref().parent() {
         Some(x) => x,
         None => {
@@ -78,15 +160,45 @@ where
             ))
         }
     };
-    let mut chars: PutBackChars = contents.chars().into();
+    let (front_matter, body, front_matter_lines) = FrontMatter::split(contents);
+    let mut chars: PutBackChars = body.chars().into();
     chars.putback('\n');
-    chars.line_number = 1;
-    return match markdown_charbuff_to_html(&mut chars, templates, dir)? {
-        (parsed, ExitMode::EndOfFile) => Ok(parsed),
+    chars.line_number = front_matter_lines;
+    let front_matter_tags = front_matter.tags();
+    let parents = front_matter.parents();
+    templates.enter_note(Note {
+        path: file.as_ref().to_path_buf(),
+        front_matter,
+    });
+    let result = markdown_charbuff_to_html(&mut chars, templates, dir);
+    templates.leave_note();
+    match result? {
+        (mut parsed, ExitMode::EndOfFile) => {
+            let mut tags = Vec::new();
+            for tag in front_matter_tags.into_iter().chain(parsed.tags) {
+                push_tag(&mut tags, tag);
+            }
+            parsed.tags = tags;
+            for parent in parents {
+                let resolution =
+                    templates
+                        .resolver()
+                        .resolve(&parent, dir, templates.title_cache());
+                match resolution.path().file_stem() {
+                    Some(stem) if resolution.exists() => {
+                        parsed.parents.push(stem.to_string_lossy().to_string())
+                    }
+                    _ => parsed.diagnostics.push(ParseError::empty(&format!(
+                        "Parent {parent} does not exist"
+                    ))),
+                }
+            }
+            Ok(parsed)
+        }
         (_parsed, ExitMode::EndOfArgument) => Err(ParseError::empty("Stray argument separator")),
         (_parsed, ExitMode::EndOfTemplate) => Err(ParseError::empty("Stray template terminator")),
         (_parsed, ExitMode::EndOfLink) => Err(ParseError::empty("Stray wiki-link terminator")),
-    };
+    }
 }
 
 pub fn markdown_charbuff_to_html<P: AsRef<Path>>(
@@ -94,13 +206,11 @@ pub fn markdown_charbuff_to_html<P: AsRef<Path>>(
     templates: &TemplateMap,
     directory: P,
 ) -> Result<(ParsedHTML, ExitMode), ParseError> {
-    let mut parsed_html = ParsedHTML {
-        html: String::new(),
-        links_to: Vec::new(),
-        parents: Vec::new(),
-    };
+    let mut parsed_html = ParsedHTML::new();
 
     let mut modifier_stack = Vec::new();
+    // Whether the text so far ends in whitespace, so that a `#` starts a tag.
+    let mut word_start = true;
 
     parsed_html.html.push_str("<p>");
     while let Some(character) = chars.next() {
@@ -109,6 +219,18 @@ pub fn markdown_charbuff_to_html<P: AsRef<Path>>(
             if has_close_delimiter(chars, open_delimiter) {
                 let _ = modifier_stack.pop().unwrap();
                 parsed_html.html.push_str(open_delimiter.close().as_str());
+                // A heading ends with the line break it consumed.
+                word_start |= matches!(open_delimiter, TextModifier::Heading(_));
+                continue;
+            }
+        }
+        if word_start {
+            if let Some(tag) = read_tag(chars) {
+                parsed_html
+                    .html
+                    .push_str(templates.link_generator().render_tag(&tag).as_str());
+                push_tag(&mut parsed_html.tags, tag);
+                word_start = false;
                 continue;
             }
         }
@@ -116,7 +238,13 @@ pub fn markdown_charbuff_to_html<P: AsRef<Path>>(
             match delimiter {
                 Delimiter::TextModifier(text_modifier) => {
                     modifier_stack.push(text_modifier);
-                    parsed_html.html.push_str(text_modifier.open().as_str());
+                    parsed_html
+                        .html
+                        .push_str(text_modifier.open(chars).as_str());
+                    word_start |= matches!(
+                        text_modifier,
+                        TextModifier::Quote | TextModifier::Heading(_)
+                    );
                 }
                 Delimiter::ExclusiveModifier(ExclusiveModifier::EndOfArgument) => {
                     return Ok((parsed_html, ExitMode::EndOfArgument));
@@ -124,20 +252,32 @@ pub fn markdown_charbuff_to_html<P: AsRef<Path>>(
                 Delimiter::ExclusiveModifier(ExclusiveModifier::EndOfTemplate) => {
                     return Ok((parsed_html, ExitMode::EndOfTemplate));
                 }
+                Delimiter::ExclusiveModifier(ExclusiveModifier::EndOfLink) => {
+                    return Ok((parsed_html, ExitMode::EndOfLink));
+                }
                 Delimiter::ExclusiveModifier(exclusive_modifier) => {
-                    match exclusive_modifier.to_html(
+                    if let Some(e) = exclusive_modifier.to_html(
                         chars,
                         &mut parsed_html,
-                        &templates,
+                        templates,
                         directory.as_ref().to_path_buf(),
                     ) {
-                        Some(e) => return Err(e),
-                        None => (),
+                        return Err(e);
                     }
+                    word_start = match exclusive_modifier {
+                        ExclusiveModifier::Paragraph => true,
+                        // An escaped space or line break still ends a word.
+                        ExclusiveModifier::Escape => {
+                            parsed_html.html.ends_with(char::is_whitespace)
+                        }
+                        _ => false,
+                    };
                 }
             }
         } else {
-            parsed_html.html.push(chars.next().unwrap());
+            let character = chars.next().unwrap();
+            word_start = character.is_whitespace();
+            parsed_html.html.push(character);
         }
     }
     parsed_html.html.push_str("</p>");
@@ -145,12 +285,82 @@ pub fn markdown_charbuff_to_html<P: AsRef<Path>>(
         Ok((parsed_html, ExitMode::EndOfFile))
     } else {
         Err(ParseError::from_str(
-            &chars,
+            chars,
             "Unclosed modifiers left on the stack",
         ))
     }
 }
 
+/// Renders a short piece of Markdown, such as a title or a template argument, without wrapping
+/// it in a paragraph.
+pub fn markdown_inline_to_html<P: AsRef<Path>>(
+    text: &str,
+    templates: &TemplateMap,
+    directory: P,
+) -> Result<ParsedHTML, ParseError> {
+    markdown_inline_to_html_at(text, templates, directory, Span::default())
+}
+
+/// Like [`markdown_inline_to_html`], counting lines and columns from `span` for errors.
+pub fn markdown_inline_to_html_at<P: AsRef<Path>>(
+    text: &str,
+    templates: &TemplateMap,
+    directory: P,
+    span: Span,
+) -> Result<ParsedHTML, ParseError> {
+    let mut chars: PutBackChars = text.chars().into();
+    if span.is_known() {
+        chars.line_number = span.line;
+        chars.column_number = span.column;
+    }
+    let mut parsed = match markdown_charbuff_to_html(&mut chars, templates, directory)? {
+        (parsed, ExitMode::EndOfFile) => parsed,
+        (_parsed, ExitMode::EndOfArgument) => {
+            return Err(ParseError::from_str(&chars, "Stray argument separator"))
+        }
+        (_parsed, ExitMode::EndOfTemplate) => {
+            return Err(ParseError::from_str(&chars, "Stray template terminator"))
+        }
+        (_parsed, ExitMode::EndOfLink) => {
+            return Err(ParseError::from_str(&chars, "Stray wiki-link terminator"))
+        }
+    };
+    if let Some(inner) = parsed
+        .html
+        .strip_prefix("<p>")
+        .and_then(|inner| inner.strip_suffix("</p>"))
+    {
+        parsed.html = inner.trim().to_string();
+    }
+    Ok(parsed)
+}
+
+/// Reads a `#tag` if one comes next, and leaves everything as it was otherwise.
+fn read_tag(chars: &mut PutBackChars) -> Option<String> {
+    let mut ahead = chars.clone();
+    if ahead.next() != Some('#') {
+        return None;
+    }
+    let text: String = ahead.take_while(|x| scan::is_tag_character(*x)).collect();
+    let tag = &text[..scan::tag_length(&text)?];
+    for _ in 0..=tag.chars().count() {
+        chars.next();
+    }
+    Some(tag.to_string())
+}
+
+fn heading_id(chars: &PutBackChars) -> String {
+    let mut ahead = chars.clone();
+    let mut text = String::new();
+    for character in ahead.by_ref() {
+        if character == '\n' {
+            break;
+        }
+        text.push(character);
+    }
+    links::slug(&text)
+}
+
 fn has_close_delimiter(chars: &mut PutBackChars, delimiter: TextModifier) -> bool {
     match delimiter {
         TextModifier::Bold => match chars.next() {
@@ -158,6 +368,7 @@ fn has_close_delimiter(chars: &mut PutBackChars, delimiter: TextModifier) -> boo
                 Some('*') => true,
                 other => {
                     chars.putback_maybe(other);
+                    chars.putback('*');
                     false
                 }
             },
@@ -188,6 +399,7 @@ fn has_close_delimiter(chars: &mut PutBackChars, delimiter: TextModifier) -> boo
                 Some('~') => true,
                 other => {
                     chars.putback_maybe(other);
+                    chars.putback('~');
                     false
                 }
             },
@@ -201,6 +413,7 @@ fn has_close_delimiter(chars: &mut PutBackChars, delimiter: TextModifier) -> boo
                 Some('_') => true,
                 other => {
                     chars.putback_maybe(other);
+                    chars.putback('_');
                     false
                 }
             },
@@ -235,7 +448,17 @@ fn has_close_delimiter(chars: &mut PutBackChars, delimiter: TextModifier) -> boo
             }
         },
         TextModifier::Heading(_) => match chars.next() {
-            Some('\n') => true,
+            Some('\n') => {
+                // The next line may be a heading as well, which needs the line break.
+                match chars.next() {
+                    Some('#') => {
+                        chars.putback('#');
+                        chars.putback('\n');
+                    }
+                    other => chars.putback_maybe(other),
+                }
+                true
+            }
             other => {
                 chars.putback_maybe(other);
                 false
@@ -302,6 +525,18 @@ fn find_open_delimiter(chars: &mut PutBackChars) -> Option<Delimiter> {
                 }
             },
             Some('#') => {
+                // A line starting with `#tag` starts with a tag, which the caller reads.
+                let mut ahead = chars.clone();
+                let text: String = ahead
+                    .by_ref()
+                    .take(256)
+                    .take_while(|x| *x != '\n')
+                    .collect();
+                if scan::tag_length(&text).is_some() {
+                    chars.putback('#');
+                    chars.putback('\n');
+                    return None;
+                }
                 let mut header_level: u8 = 1;
                 loop {
                     match chars.next() {
@@ -321,6 +556,9 @@ fn find_open_delimiter(chars: &mut PutBackChars) -> Option<Delimiter> {
             }
         },
         '\\' => Some(Delimiter::ExclusiveModifier(ExclusiveModifier::Escape)),
+        '<' if chars.skip_if("nowiki>") => {
+            Some(Delimiter::ExclusiveModifier(ExclusiveModifier::NoWiki))
+        }
         '!' => match chars.next() {
             Some('[') => Some(Delimiter::ExclusiveModifier(ExclusiveModifier::Image)),
             other => {
@@ -384,14 +622,17 @@ impl TextModifier {
             TextModifier::Heading(level) => format!("</h{level}>\n<p>"),
         }
     }
-    pub(crate) fn open(self) -> String {
+    /// The opening tag, for which a heading looks ahead at its text in `chars` to get its id.
+    pub(crate) fn open(self, chars: &PutBackChars) -> String {
         match self {
             TextModifier::Bold => "<b>".to_string(),
             TextModifier::Italics => "<i>".to_string(),
             TextModifier::Strikethrough => "<del>".to_string(),
             TextModifier::Underline => "<u>".to_string(),
             TextModifier::Quote => "<blockquote>".to_string(),
-            TextModifier::Heading(level) => format!("</p>\n<h{level}>"),
+            TextModifier::Heading(level) => {
+                format!("</p>\n<h{level} id=\"{}\">", heading_id(chars))
+            }
         }
     }
 }
@@ -417,45 +658,127 @@ impl ExclusiveModifier {
                 }
             }
             ExclusiveModifier::Template => {
-                let (name, name_exit) = read_template_argument(chars);
+                let span = Span {
+                    line: chars.line_number,
+                    column: chars.column_number.saturating_sub(2),
+                };
+                let (name, name_exit) = match read_template_argument(chars) {
+                    Ok(x) => x,
+                    Err(e) => return Some(e),
+                };
+                if name.trim() == NOWIKI {
+                    return nowiki_template(chars, parsed, name_exit, span);
+                }
+                match name_exit {
+                    ExitMode::EndOfFile => {
+                        return Some(ParseError::at(
+                            span.line,
+                            span.column,
+                            format!("Unclosed template {{{{{}", name.trim()),
+                        ))
+                    }
+                    ExitMode::EndOfLink => {
+                        return Some(ParseError::from_str(
+                            chars,
+                            "Cannot close wikilink inside template.",
+                        ))
+                    }
+                    _ => (),
+                }
                 let mut args = Vec::new();
-                // let _ = read_template_argument(chars);
                 if name_exit == ExitMode::EndOfArgument {
                     loop {
-                        match read_template_argument(chars) {
-                            (result, reason) => {
-                                args.push(result);
-                                match reason {
-                                    ExitMode::EndOfArgument => continue,
-                                    ExitMode::EndOfTemplate => break,
-                                    ExitMode::EndOfFile => {
-                                        panic!("End of file inside template argument")
-                                    }
-                                    ExitMode::EndOfLink => panic!(
-                                        "Stray wiki-link terminator inside template argument"
-                                    ),
-                                }
+                        let argument_span = Span {
+                            line: chars.line_number,
+                            column: chars.column_number,
+                        };
+                        let (result, reason) = match read_template_argument(chars) {
+                            Ok(x) => x,
+                            Err(e) => return Some(e),
+                        };
+                        args.push(Argument::new(result, argument_span));
+                        match reason {
+                            ExitMode::EndOfArgument => continue,
+                            ExitMode::EndOfTemplate => break,
+                            ExitMode::EndOfFile => {
+                                return Some(ParseError::at(
+                                    span.line,
+                                    span.column,
+                                    format!("Unclosed template {{{{{}", name.trim()),
+                                ))
+                            }
+                            ExitMode::EndOfLink => {
+                                return Some(ParseError::from_str(
+                                    chars,
+                                    "Cannot close wikilink inside template.",
+                                ))
                             }
                         }
                     }
                 }
-                let mut result: ParsedHTML = match templates.call(name.clone(), args, directory) {
+                let args = TemplateArgs::parse(args);
+                let result: ParsedHTML = match templates.call(name.clone(), args, directory, span) {
                     Ok((result, ExitMode::EndOfFile)) => result,
                     Ok(_) => return Some(ParseError::from_str(chars, "If you ever get this error, please send a bug report. I'm very curious how you can get this")),
-                    Err(e) => return Some(ParseError::from_string(chars, format!("Error occurred while parsing template {name}:\n{}", e.comment))),
+                    Err(e) if e.line() != 0 => return Some(ParseError::at(e.line(), e.column(), format!("Error occurred while parsing template {name}:\n{}", e.comment))),
+                    Err(e) => return Some(ParseError::at(span.line, span.column, format!("Error occurred while parsing template {name}:\n{}", e.comment))),
                 };
-                parsed.html.push_str(&result.html);
-                parsed.links_to.append(&mut result.links_to);
-                parsed.parents.append(&mut result.parents);
+                parsed.append(result);
                 None
             }
             ExclusiveModifier::WikiLink => {
-                let (name, reason) = read_template_argument(chars);
-                let full_name = format!("{name}.md");
-                let absolute_path = directory.join(&full_name);
+                let (name, reason) = match read_template_argument(chars) {
+                    Ok(x) => x,
+                    Err(e) => return Some(e),
+                };
+                match reason {
+                    ExitMode::EndOfFile => {
+                        return Some(ParseError::from_str(chars, "Unclosed wikilink."))
+                    }
+                    ExitMode::EndOfTemplate => {
+                        return Some(ParseError::from_str(
+                            chars,
+                            "Cannot close template inside wikilink.",
+                        ))
+                    }
+                    _ => (),
+                }
+                let resolution =
+                    templates
+                        .resolver()
+                        .resolve(&name, &directory, templates.title_cache());
+                if let Resolution::Ambiguous(candidates) = &resolution {
+                    let candidates: Vec<String> =
+                        candidates.iter().map(|x| x.display().to_string()).collect();
+                    parsed.diagnostics.push(ParseError::from_string(
+                        chars,
+                        format!(
+                            "Wiki-link [[{name}]] is ambiguous, candidates are: {}",
+                            candidates.join(", ")
+                        ),
+                    ));
+                }
+                let absolute_path = resolution.path();
+                let full_name = match absolute_path.file_name() {
+                    Some(x) => x.to_string_lossy().to_string(),
+                    None => format!("{name}.md"),
+                };
+                if let Some(stem) = absolute_path.file_stem() {
+                    parsed.links_to.push(stem.to_string_lossy().to_string());
+                }
+                if !resolution.exists() {
+                    parsed.broken_links.push(name.clone());
+                }
+                parsed
+                    .dependencies
+                    .links
+                    .insert(absolute_path.to_path_buf());
 
                 let display_name = if reason == ExitMode::EndOfArgument {
-                    let (out, reason) = read_template_argument(chars);
+                    let (out, reason) = match read_template_argument(chars) {
+                        Ok(x) => x,
+                        Err(e) => return Some(e),
+                    };
                     match reason {
                         ExitMode::EndOfArgument => {
                             return Some(ParseError::from_str(
@@ -474,19 +797,27 @@ impl ExclusiveModifier {
                             return Some(ParseError::from_str(chars, "Unclosed wikilink."))
                         }
                     }
+                } else if !resolution.exists() {
+                    escape_html(&name)
                 } else {
-                    match read_title(&absolute_path) {
+                    parsed
+                        .dependencies
+                        .titles
+                        .insert(absolute_path.to_path_buf());
+                    match templates.title_cache().title(absolute_path) {
                         Some(title) => title,
-                        None => full_name.clone(),
+                        None => full_name,
                     }
                 };
-                let path_name = match absolute_path.to_str() {
-                    Some(x) => x,
-                    None => return Some(ParseError::from_str(chars, "Path could not be resolved")),
+                let link = NoteLink {
+                    name: &name,
+                    target: absolute_path,
+                    exists: resolution.exists(),
+                    label: &display_name,
                 };
                 parsed
                     .html
-                    .push_str(format!("<a href={path_name}>{display_name}</a>").as_str());
+                    .push_str(templates.link_generator().render(&link).as_str());
                 None
             }
             ExclusiveModifier::InlineCode => {
@@ -650,6 +981,21 @@ impl ExclusiveModifier {
                 parsed
                     .html
                     .push_str(format!("<img src=\"{url}\" alt=\"{name}\"/>\n").as_str());
+                parsed.images.push(url);
+                None
+            }
+            ExclusiveModifier::NoWiki => {
+                let mut text = String::new();
+                loop {
+                    if chars.skip_if(NOWIKI_END) {
+                        break;
+                    }
+                    match chars.next() {
+                        Some(character) => text.push(character),
+                        None => return Some(ParseError::from_str(chars, "Unclosed <nowiki>")),
+                    }
+                }
+                parsed.html.push_str(&escape_html(&text));
                 None
             }
             ExclusiveModifier::EndOfArgument => panic!("Unreachable state"),
@@ -659,20 +1005,64 @@ impl ExclusiveModifier {
     }
 }
 
-fn read_title<T>(file: T) -> Option<String>
-where
-    T: AsRef<Path>,
-{
-    let contents = match std::fs::read_to_string(&file) {
-        Ok(x) => x,
-        Err(_) => {
-            return None;
+/// `{{nowiki|...}}` shows its arguments as written, joined by `|`, instead of calling a template.
+/// `<nowiki>...</nowiki>` does the same for a longer region, which may span lines and contain
+/// unbalanced brackets. Either way the text is still HTML-escaped.
+const NOWIKI: &str = "nowiki";
+const NOWIKI_END: &str = "</nowiki>";
+
+fn nowiki_template(
+    chars: &mut PutBackChars,
+    parsed: &mut ParsedHTML,
+    name_exit: ExitMode,
+    span: Span,
+) -> Option<ParseError> {
+    let mut args = Vec::new();
+    let mut exit = name_exit;
+    while exit == ExitMode::EndOfArgument {
+        let (argument, reason) = match read_template_argument(chars) {
+            Ok(x) => x,
+            Err(e) => return Some(e),
+        };
+        args.push(argument);
+        exit = reason;
+    }
+    if exit != ExitMode::EndOfTemplate {
+        return Some(ParseError::at(
+            span.line,
+            span.column,
+            "Unclosed {{nowiki}}".to_string(),
+        ));
+    }
+    let text = Argument::new(args.join("|"), span).literal();
+    parsed.html.push_str(&escape_html(&text));
+    None
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::new();
+    for character in text.chars() {
+        match character {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            other => out.push(other),
         }
-    };
-    let mut chars: PutBackChars = contents.chars().into();
+    }
+    out
+}
+
+pub(crate) fn raw_title(body: &str) -> Option<String> {
+    let mut chars: PutBackChars = body.chars().into();
     if chars.next() != Some('#') {
         return None;
     }
+    // A note starting with `#tag` has a tag there, not a title.
+    if scan::starts_with_tag(body) {
+        return None;
+    }
     let mut out_unparsed = String::new();
     loop {
         match chars.next() {
@@ -686,11 +1076,72 @@ where
             Some(other) => out_unparsed.push(other),
         }
     }
-    let mut out_chars: PutBackChars = out_unparsed.chars().into();
-    let result =
-        markdown_charbuff_to_html(&mut out_chars, &TemplateMap::new(), file.as_ref().parent()?);
-    match result {
-        Err(_) => None,
-        Ok(x) => Some(x.0.html),
+    Some(out_unparsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str) -> ParsedHTML {
+        markdown_to_html(source, "note.md", &TemplateMap::new()).unwrap()
+    }
+
+    #[test]
+    fn a_lone_marker_inside_its_pair_is_text() {
+        assert!(render("__a_b__\n").html.contains("<u>a_b</u>"));
+        assert!(render("~~a~b~~\n").html.contains("<del>a~b</del>"));
+        assert!(render("**a *b* c**\n").html.contains("<b>a <i>b</i> c</b>"));
+    }
+
+    #[test]
+    fn tags_start_after_escaped_spaces_and_headings() {
+        assert_eq!(
+            render("x \\ #space and x\\\n#break\n").tags,
+            ["space", "break"]
+        );
+        assert_eq!(render("##*#heading*\n#*#next*\n").tags, ["heading", "next"]);
+        assert_eq!(render("# A\n#tag\n").tags, ["tag"]);
+    }
+
+    #[test]
+    fn putting_back_a_line_break_is_no_crash() {
+        let result = markdown_to_html("``\nx#[\n", "note.md", &TemplateMap::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn a_heading_may_follow_a_heading() {
+        let html = render("# A\n## B\ntext\n").html;
+        assert!(html.contains("<h1 id=\"a\"> A</h1>"), "{html}");
+        assert!(html.contains("<h2 id=\"b\"> B</h2>"), "{html}");
+    }
+
+    #[test]
+    fn nowiki_keeps_syntax_as_written() {
+        let html = render("<nowiki>[[a]] {{b|c}} **d** <i> & ]] }}</nowiki> **e**\n").html;
+        assert!(
+            html.contains("[[a]] {{b|c}} **d** &lt;i&gt; &amp; ]] }} <b>e</b>"),
+            "{html}"
+        );
+        let html = render("<nowiki>line\n\n# not a title\n</nowiki>\n").html;
+        assert!(html.contains("line\n\n# not a title\n"), "{html}");
+
+        let parsed = render("{{nowiki|[[a]]|**b** <c>|d=\\|e}}\n");
+        assert!(
+            parsed.html.contains("[[a]]|**b** &lt;c&gt;|d=|e"),
+            "{}",
+            parsed.html
+        );
+        assert!(parsed.links_to.is_empty());
+        assert!(parsed.dependencies.template_notes.is_empty());
+
+        let unclosed = markdown_to_html("<nowiki>[[a]]", "note.md", &TemplateMap::new());
+        assert_eq!(unclosed.unwrap_err().comment, "Unclosed <nowiki>");
+        let unclosed = markdown_to_html("x {{nowiki|a", "note.md", &TemplateMap::new());
+        assert_eq!(
+            unclosed.unwrap_err().to_string(),
+            "1:3. Unclosed {{nowiki}}"
+        );
     }
 }
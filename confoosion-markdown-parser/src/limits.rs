@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+
+/// How much work template expansion may do for one document.
+///
+/// A document is a note rendered with [`crate::markdown_file_to_html`], includes and all. When
+/// rendering a string instead, every outermost template call gets a budget of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// How deeply template calls may nest.
+    pub max_recursion_depth: usize,
+    /// How many bytes of HTML template calls may produce, counted at every level of nesting.
+    pub max_output_bytes: usize,
+    /// How many templates may be called.
+    pub max_invocations: usize,
+    /// How long template expansion may take.
+    pub max_duration: Duration,
+    /// How many steps a single run of a template script may take.
+    pub max_script_steps: usize,
+}
+
+impl Limits {
+    /// No limits at all, for trusted notes.
+    pub fn unlimited() -> Self {
+        Self {
+            max_recursion_depth: usize::MAX,
+            max_output_bytes: usize::MAX,
+            max_invocations: usize::MAX,
+            max_duration: Duration::MAX,
+            max_script_steps: usize::MAX,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_recursion_depth: 128,
+            max_output_bytes: 64 * 1024 * 1024,
+            max_invocations: 100_000,
+            max_duration: Duration::from_secs(10),
+            max_script_steps: 1_000_000,
+        }
+    }
+}
+
+/// What template expansion has used up so far.
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    pub output_bytes: usize,
+    pub invocations: usize,
+    pub started: Instant,
+}
+
+impl Usage {
+    pub fn new() -> Self {
+        Self {
+            output_bytes: 0,
+            invocations: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Which limit has been crossed, if any, described for an error message.
+    pub fn exceeded(&self, limits: &Limits, depth: usize) -> Option<String> {
+        if depth > limits.max_recursion_depth {
+            Some(format!(
+                "templates nested more than {} deep",
+                limits.max_recursion_depth
+            ))
+        } else if self.invocations > limits.max_invocations {
+            Some(format!(
+                "more than {} template calls",
+                limits.max_invocations
+            ))
+        } else if self.output_bytes > limits.max_output_bytes {
+            Some(format!(
+                "templates produced more than {} bytes",
+                limits.max_output_bytes
+            ))
+        } else if self.started.elapsed() > limits.max_duration {
+            Some(format!(
+                "templates took longer than {:?}",
+                limits.max_duration
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Usage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,254 @@
+use std::path::Path;
+
+use crate::escape_html;
+
+/// A rendered wiki-link, handed to a [`LinkGenerator`] to turn into HTML.
+#[derive(Debug, Clone, Copy)]
+pub struct NoteLink<'a> {
+    /// The link target as written between the brackets.
+    pub name: &'a str,
+    /// The note the target resolved to, or where it would be created if it does not exist.
+    pub target: &'a Path,
+    pub exists: bool,
+    /// Already rendered HTML for the link text.
+    pub label: &'a str,
+}
+
+/// Decides where wiki-links point to and what they look like.
+pub trait LinkGenerator {
+    fn url(&self, note: &Path) -> String;
+
+    fn render(&self, link: &NoteLink) -> String {
+        if link.exists {
+            format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html(&self.url(link.target)),
+                link.label
+            )
+        } else {
+            self.render_missing(link)
+        }
+    }
+
+    fn render_missing(&self, link: &NoteLink) -> String {
+        format!(
+            "<a class=\"wikilink-missing\" href=\"{}\">{}</a>",
+            escape_html(&self.url(link.target)),
+            link.label
+        )
+    }
+
+    /// Where a `#tag` points to. By default that is its page in a `tags` directory next to the
+    /// current page, the way a static site lays them out.
+    fn tag_url(&self, tag: &str) -> String {
+        format!("tags/{}.html", tag_slug(tag))
+    }
+
+    fn render_tag(&self, tag: &str) -> String {
+        format!(
+            "<a class=\"tag\" href=\"{}\">#{}</a>",
+            escape_html(&self.tag_url(tag)),
+            escape_html(tag)
+        )
+    }
+}
+
+/// Links to `<slug>.html` next to the current page, for statically generated sites.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RelativeHtmlLinks;
+
+impl LinkGenerator for RelativeHtmlLinks {
+    fn url(&self, note: &Path) -> String {
+        format!("{}.html", slug(&stem(note)))
+    }
+}
+
+/// Links to `<prefix><slug>`, for example `/wiki/my-note` for a web server.
+#[derive(Debug, Clone)]
+pub struct RouteLinks {
+    pub prefix: String,
+}
+
+impl LinkGenerator for RouteLinks {
+    fn url(&self, note: &Path) -> String {
+        format!("{}{}", self.prefix, slug(&stem(note)))
+    }
+
+    fn tag_url(&self, tag: &str) -> String {
+        format!("{}tags/{}", self.prefix, tag_slug(tag))
+    }
+}
+
+/// Links to `<scheme>://note/<name>`, for a frontend that handles its own URI scheme.
+#[derive(Debug, Clone)]
+pub struct UriLinks {
+    pub scheme: String,
+}
+
+impl Default for UriLinks {
+    fn default() -> Self {
+        Self {
+            scheme: "confoosion".to_string(),
+        }
+    }
+}
+
+impl LinkGenerator for UriLinks {
+    fn url(&self, note: &Path) -> String {
+        format!("{}://note/{}", self.scheme, percent_encode(&stem(note)))
+    }
+
+    fn tag_url(&self, tag: &str) -> String {
+        format!("{}://tag/{}", self.scheme, percent_encode(tag))
+    }
+}
+
+/// Links to the note's location on disk. Only useful for viewing notes locally.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilePathLinks;
+
+impl LinkGenerator for FilePathLinks {
+    fn url(&self, note: &Path) -> String {
+        note.to_string_lossy().to_string()
+    }
+}
+
+/// Turns a note name into something safe to use in URLs and file names.
+pub fn slug(name: &str) -> String {
+    let mut out = String::new();
+    for character in name.chars() {
+        if character.is_alphanumeric() {
+            out.extend(character.to_lowercase());
+        } else if matches!(character, ' ' | '-' | '_' | '.') && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    let out = out.trim_matches('-');
+    if out.is_empty() {
+        percent_encode(name)
+    } else {
+        out.to_string()
+    }
+}
+
+/// Turns a tag into something safe to use in URLs and file names. Nested tags keep their
+/// parts apart with `-`, so `project/alpha` becomes `project-alpha`.
+pub fn tag_slug(tag: &str) -> String {
+    slug(&tag.replace('/', "-"))
+}
+
+pub fn percent_encode(text: &str) -> String {
+    let mut out = String::new();
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(format!("%{byte:02X}").as_str());
+        }
+    }
+    out
+}
+
+fn stem(note: &Path) -> String {
+    match note.file_stem() {
+        Some(x) => x.to_string_lossy().to_string(),
+        None => note.to_string_lossy().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+    use crate::{markdown_to_html, template::TemplateMap};
+
+    const NOTE: &str = "/vault/My Note.md";
+
+    fn link(exists: bool) -> NoteLink<'static> {
+        NoteLink {
+            name: "My Note",
+            target: Path::new(NOTE),
+            exists,
+            label: "<b>Mine</b>",
+        }
+    }
+
+    #[test]
+    fn each_generator_has_its_own_urls() {
+        let generators: [(Box<dyn LinkGenerator>, &str, &str); 4] = [
+            (
+                Box::new(RelativeHtmlLinks),
+                "my-note.html",
+                "tags/project-alpha.html",
+            ),
+            (
+                Box::new(RouteLinks {
+                    prefix: "/wiki/".to_string(),
+                }),
+                "/wiki/my-note",
+                "/wiki/tags/project-alpha",
+            ),
+            (
+                Box::new(UriLinks::default()),
+                "confoosion://note/My%20Note",
+                "confoosion://tag/project%2Falpha",
+            ),
+            (Box::new(FilePathLinks), NOTE, "tags/project-alpha.html"),
+        ];
+        for (generator, url, tag_url) in generators {
+            assert_eq!(generator.url(Path::new(NOTE)), url);
+            assert_eq!(generator.tag_url("project/alpha"), tag_url);
+            assert_eq!(
+                generator.render(&link(true)),
+                format!("<a href=\"{}\"><b>Mine</b></a>", escape_html(url))
+            );
+            assert_eq!(
+                generator.render(&link(false)),
+                format!(
+                    "<a class=\"wikilink-missing\" href=\"{}\"><b>Mine</b></a>",
+                    escape_html(url)
+                )
+            );
+            assert_eq!(
+                generator.render_tag("project/alpha"),
+                format!("<a class=\"tag\" href=\"{tag_url}\">#project/alpha</a>")
+            );
+        }
+    }
+
+    #[test]
+    fn slugs_keep_letters_and_digits() {
+        assert_eq!(slug("My Note"), "my-note");
+        assert_eq!(slug("  a -- b_c.d  "), "a-b-c-d");
+        assert_eq!(slug("Ünïcödé 2"), "ünïcödé-2");
+        assert_eq!(slug("?!"), "%3F%21");
+        assert_eq!(tag_slug("project/alpha"), "project-alpha");
+    }
+
+    #[test]
+    fn missing_notes_are_marked_and_reported_as_broken() {
+        let directory: PathBuf =
+            std::env::temp_dir().join(format!("confoosion-links-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("here.md"), "# Here\n").unwrap();
+        let parsed = markdown_to_html(
+            "[[here]] [[Gone Away]] [[nowhere|<b>x</b>]]",
+            directory.join("note.md"),
+            &TemplateMap::new(),
+        )
+        .unwrap();
+        assert!(
+            parsed.html.contains(concat!(
+                "<a href=\"here.html\">Here</a> ",
+                "<a class=\"wikilink-missing\" href=\"gone-away.html\">Gone Away</a> ",
+                "<a class=\"wikilink-missing\" href=\"nowhere.html\"><b>x</b></a>"
+            )),
+            "{}",
+            parsed.html
+        );
+        assert_eq!(parsed.broken_links, ["Gone Away", "nowhere"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
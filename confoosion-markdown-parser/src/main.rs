@@ -1,25 +1,287 @@
-use core::panic;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
+use confoosion_markdown_parser::arguments::TemplateArgs;
+use confoosion_markdown_parser::context::TemplateContext;
 use confoosion_markdown_parser::error::ParseError;
-use confoosion_markdown_parser::putback::PutBackChars;
+use confoosion_markdown_parser::markdown_to_html;
 use confoosion_markdown_parser::template::TemplateMap;
-use confoosion_markdown_parser::{markdown_charbuff_to_html, markdown_file_to_html};
+use confoosion_markdown_parser::title::NoteHeader;
+use confoosion_markdown_parser::ParsedHTML;
 
-fn main() {
-    let mut templates: TemplateMap = TemplateMap::new();
+const USAGE: &str = "\
+Usage: confoosion-markdown-parser <command> [arguments]
+
+Commands:
+  render <file> [-o <output>]  Render a note to HTML, on standard output by default
+  check <directory>            Render every note in a directory and report problems
+  links <file>                 List the notes a note links to, as found or missing
+  tags <file>                  List the tags of a note, from its front matter and its text
+  title <file> [--html]        Print the title of a note, rendered to HTML with --html
+
+A <file> of `-` reads the note from standard input, as if it were in the current directory.
+
+Exit codes: 0 on success, 1 when a note has errors or problems, 2 on wrong usage, 3 when a
+file cannot be read or written.";
+
+const EXIT_PROBLEMS: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_IO: u8 = 3;
+
+/// Why a command failed, which decides the exit code.
+enum Failure {
+    Problems,
+    Usage(String),
+    Io(String),
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("render") => render(&args[1..]),
+        Some("check") => check(&args[1..]),
+        Some("links") => links(&args[1..]),
+        Some("tags") => tags(&args[1..]),
+        Some("title") => title(&args[1..]),
+        Some("-h" | "--help" | "help") => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(other) => Err(Failure::Usage(format!("Unknown command {other}"))),
+        None => Err(Failure::Usage("No command given".to_string())),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Problems) => ExitCode::from(EXIT_PROBLEMS),
+        Err(Failure::Usage(message)) => {
+            eprintln!("{message}\n\n{USAGE}");
+            ExitCode::from(EXIT_USAGE)
+        }
+        Err(Failure::Io(message)) => {
+            eprintln!("{message}");
+            ExitCode::from(EXIT_IO)
+        }
+    }
+}
+
+fn render(args: &[String]) -> Result<(), Failure> {
+    let mut file = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next() {
+                Some(x) => output = Some(PathBuf::from(x)),
+                None => return Err(Failure::Usage(format!("{arg} needs a file name"))),
+            },
+            _ if file.is_none() => file = Some(arg.as_str()),
+            _ => return Err(Failure::Usage(format!("Unexpected argument {arg}"))),
+        }
+    }
+    let file = file.ok_or(Failure::Usage("render needs a file".to_string()))?;
+    let (path, contents) = read_note(file)?;
+    let parsed = render_note(&path, &contents, &templates())?;
+    report_diagnostics(&path, &parsed);
+    match output {
+        Some(output) => std::fs::write(&output, &parsed.html)
+            .map_err(|e| Failure::Io(format!("Could not write {}: {e}", output.display())))?,
+        None => write_stdout(&parsed.html)?,
+    }
+    Ok(())
+}
+
+fn check(args: &[String]) -> Result<(), Failure> {
+    let directory = match args {
+        [directory] => Path::new(directory),
+        _ => {
+            return Err(Failure::Usage(
+                "check needs exactly one directory".to_string(),
+            ))
+        }
+    };
+    let entries = std::fs::read_dir(directory)
+        .map_err(|e| Failure::Io(format!("Could not read {}: {e}", directory.display())))?;
+    let mut notes: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|x| x.path()))
+        .filter(|path| path.extension().is_some_and(|x| x == "md") && path.is_file())
+        .collect();
+    notes.sort();
+    let templates = templates();
+    let mut problems = false;
+    let mut unreadable = 0;
+    for note in notes {
+        if note
+            .file_name()
+            .is_some_and(|x| x.to_string_lossy().starts_with(templates.template_prefix()))
+        {
+            continue;
+        }
+        let contents = match std::fs::read_to_string(&note) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("{}: error: could not read: {e}", note.display());
+                unreadable += 1;
+                continue;
+            }
+        };
+        match render_note(&note, &contents, &templates) {
+            Ok(parsed) => {
+                problems |= report_diagnostics(&note, &parsed);
+                for link in &parsed.broken_links {
+                    eprintln!("{}: warning: broken link [[{link}]]", note.display());
+                    problems = true;
+                }
+            }
+            Err(_) => problems = true,
+        }
+    }
+    if unreadable > 0 {
+        return Err(Failure::Io(format!(
+            "Could not read {unreadable} note(s) in {}",
+            directory.display()
+        )));
+    }
+    match problems {
+        true => Err(Failure::Problems),
+        false => Ok(()),
+    }
+}
+
+fn links(args: &[String]) -> Result<(), Failure> {
+    let file = match args {
+        [file] => file,
+        _ => return Err(Failure::Usage("links needs exactly one file".to_string())),
+    };
+    let (path, contents) = read_note(file)?;
+    let parsed = render_note(&path, &contents, &templates())?;
+    let mut out = String::new();
+    for link in &parsed.dependencies.links {
+        let state = if link.exists() { "found" } else { "missing" };
+        out.push_str(&format!("{state}\t{}\n", link.display()));
+    }
+    write_stdout(&out)
+}
+
+fn tags(args: &[String]) -> Result<(), Failure> {
+    let file = match args {
+        [file] => file,
+        _ => return Err(Failure::Usage("tags needs exactly one file".to_string())),
+    };
+    let (path, contents) = read_note(file)?;
+    let parsed = render_note(&path, &contents, &templates())?;
+    let out: String = parsed.tags.iter().map(|tag| format!("{tag}\n")).collect();
+    write_stdout(&out)
+}
+
+fn title(args: &[String]) -> Result<(), Failure> {
+    let (file, html) = match args {
+        [file] => (file, false),
+        [flag, file] | [file, flag] if flag == "--html" => (file, true),
+        _ => return Err(Failure::Usage("title needs exactly one file".to_string())),
+    };
+    let (path, contents) = read_note(file)?;
+    let header =
+        catch_unwind(|| NoteHeader::parse(&contents, path.parent())).map_err(|_| crashed(&path))?;
+    let title = match html {
+        true => header.title,
+        false => header.raw_title.map(|x| x.trim().to_string()),
+    };
+    match title {
+        Some(title) => write_stdout(&format!("{title}\n")),
+        None => {
+            eprintln!("{}: no title", path.display());
+            Err(Failure::Problems)
+        }
+    }
+}
+
+fn templates() -> TemplateMap {
+    let mut templates = TemplateMap::with_builtins();
     templates.insert("double".to_string(), Box::new(template_double));
-    let parsed = match markdown_file_to_html("examples/stars.md", &mut templates) {
-        Ok(x) => x,
-        Err(e) => panic!("{e}"),
+    templates
+}
+
+/// Reads a note from a file, or from standard input for `-`.
+fn read_note(file: &str) -> Result<(PathBuf, String), Failure> {
+    if file == "-" {
+        let mut contents = String::new();
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .map_err(|e| Failure::Io(format!("Could not read standard input: {e}")))?;
+        let directory = std::env::current_dir()
+            .map_err(|e| Failure::Io(format!("Could not find the current directory: {e}")))?;
+        return Ok((directory.join("-"), contents));
+    }
+    let path = PathBuf::from(file);
+    let path = match path.parent() {
+        Some(x) if x.as_os_str().is_empty() => Path::new(".").join(path),
+        _ => path,
     };
-    println!("{}", parsed.html);
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok((path, contents)),
+        Err(e) => Err(Failure::Io(format!("Could not read {file}: {e}"))),
+    }
 }
 
-fn template_double<P: AsRef<Path>>(
-    args: Vec<String>,
+/// Renders a note and prints its error, if any. A crash of the parser counts as an error too.
+fn render_note(
+    path: &Path,
+    contents: &str,
     templates: &TemplateMap,
-    dir: P,
+) -> Result<ParsedHTML, Failure> {
+    let result = match catch_unwind(AssertUnwindSafe(|| {
+        markdown_to_html(contents, path, templates)
+    })) {
+        Ok(x) => x,
+        // A panic leaves the templates halfway through a render.
+        Err(_) => {
+            templates.abandon_render();
+            return Err(crashed(path));
+        }
+    };
+    result.map_err(|e| {
+        eprintln!(
+            "{}:{}:{}: error: {}",
+            path.display(),
+            e.line(),
+            e.column(),
+            e.comment
+        );
+        Failure::Problems
+    })
+}
+
+fn crashed(path: &Path) -> Failure {
+    eprintln!("{}: error: the parser crashed on this note", path.display());
+    Failure::Problems
+}
+
+/// Prints the diagnostics of a render, and says whether there were any.
+fn report_diagnostics(path: &Path, parsed: &ParsedHTML) -> bool {
+    for diagnostic in &parsed.diagnostics {
+        eprintln!(
+            "{}:{}:{}: warning: {}",
+            path.display(),
+            diagnostic.line(),
+            diagnostic.column(),
+            diagnostic.comment
+        );
+    }
+    !parsed.diagnostics.is_empty()
+}
+
+fn write_stdout(text: &str) -> Result<(), Failure> {
+    std::io::stdout()
+        .write_all(text.as_bytes())
+        .map_err(|e| Failure::Io(format!("Could not write to standard output: {e}")))
+}
+
+/// `{{double|text}}`: renders `text` twice, to show how templates are written.
+fn template_double(
+    args: TemplateArgs,
+    context: &TemplateContext,
 ) -> Result<
     (
         confoosion_markdown_parser::ParsedHTML,
@@ -28,8 +290,7 @@ fn template_double<P: AsRef<Path>>(
     ParseError,
 > {
     if args.len() == 1 {
-        let mut chars: PutBackChars = args.first().unwrap().chars().into();
-        let (mut parsed, exit) = markdown_charbuff_to_html(&mut chars, templates, dir)?;
+        let (mut parsed, exit) = args.argument(0).unwrap().render(context)?;
         parsed.html.push_str(parsed.html.clone().as_str());
         Ok((parsed, exit))
     } else {
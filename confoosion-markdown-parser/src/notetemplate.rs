@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+use crate::{arguments::TemplateArgs, error::ParseError, frontmatter::FrontMatter};
+
+/// A template written as a note, such as `Template:infobox.md`.
+///
+/// Its body is plain Markdown in which `{{{1}}}` stands for the first positional argument and
+/// `{{{title}}}` for the named argument `title`. `{{{title|default}}}` falls back to `default`
+/// when the argument is not given, and placeholders without a default become empty.
+///
+/// With `pure: true` in its front matter, the template promises that its output depends only on
+/// its arguments, so that [`crate::template::TemplateMap`] may reuse it. With `script: true`, its
+/// body is a [`crate::script`] that runs before the placeholders are filled in.
+#[derive(Debug, Clone)]
+pub struct NoteTemplate {
+    pub path: PathBuf,
+    pub front_matter: FrontMatter,
+    pub source: String,
+    /// The line of the note the body starts on, after the front matter.
+    pub body_line: usize,
+}
+
+impl NoteTemplate {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, ParseError> {
+        let path = path.as_ref();
+        let contents = match std::fs::read_to_string(path) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(ParseError::empty(
+                    format!("Could not read template {}, error: {e}", path.display()).as_str(),
+                ))
+            }
+        };
+        let (front_matter, body, front_matter_lines) = FrontMatter::split(&contents);
+        Ok(Self {
+            path: path.to_path_buf(),
+            front_matter,
+            source: body.to_string(),
+            body_line: front_matter_lines + 1,
+        })
+    }
+
+    pub fn is_pure(&self) -> bool {
+        self.front_matter.text("pure").map(str::trim) == Some("true")
+    }
+
+    pub fn is_script(&self) -> bool {
+        self.front_matter.text("script").map(str::trim) == Some("true")
+    }
+
+    pub fn substitute(&self, args: &TemplateArgs) -> Result<String, ParseError> {
+        substitute(&self.source, args)
+    }
+}
+
+pub fn substitute(source: &str, args: &TemplateArgs) -> Result<String, ParseError> {
+    let mut out = String::new();
+    let mut rest = source;
+    while let Some(start) = rest.find("{{{") {
+        out.push_str(&rest[..start]);
+        let inner = &rest[start + 3..];
+        let end = match find_parameter_end(inner) {
+            Some(x) => x,
+            None => {
+                return Err(ParseError::empty(
+                    "Unclosed {{{parameter}}} in template note",
+                ))
+            }
+        };
+        let parameter = &inner[..end];
+        let (name, default) = match parameter.split_once('|') {
+            Some((name, default)) => (name.trim(), Some(default)),
+            None => (parameter.trim(), None),
+        };
+        let value = match name.parse::<usize>() {
+            Ok(index) if index > 0 => args.get(index - 1),
+            _ => args.value(name),
+        };
+        match (value, default) {
+            (Some(value), _) => out.push_str(value),
+            (None, Some(default)) => out.push_str(&substitute(default, args)?),
+            (None, None) => (),
+        }
+        rest = &inner[end + 3..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn find_parameter_end(inner: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut index = 0;
+    while index < inner.len() {
+        let rest = &inner[index..];
+        if rest.starts_with("{{{") {
+            depth += 1;
+            index += 3;
+        } else if rest.starts_with("}}}") {
+            if depth == 0 {
+                return Some(index);
+            }
+            depth -= 1;
+            index += 3;
+        } else {
+            index += rest.chars().next()?.len_utf8();
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{markdown_to_html, template::TemplateMap};
+
+    fn args() -> TemplateArgs {
+        let mut args = TemplateArgs::new();
+        args.push("one".to_string());
+        args.push("two".to_string());
+        args.insert("name".to_string(), "Ann".to_string());
+        args
+    }
+
+    #[test]
+    fn placeholders_take_arguments_or_defaults() {
+        let substituted = |source| substitute(source, &args()).unwrap();
+        assert_eq!(
+            substituted("{{{1}}}, {{{2}}} and {{{ name }}}"),
+            "one, two and Ann"
+        );
+        assert_eq!(substituted("[{{{3}}}][{{{0}}}][{{{missing}}}]"), "[][][]");
+        assert_eq!(substituted("{{{3|three}}} {{{name|Bob}}}"), "three Ann");
+        assert_eq!(substituted("{{{missing|}}}|{{{size|a|b}}}"), "|a|b");
+        assert_eq!(substituted("{{{missing|{{{1}}} {{{3|x}}}}}}"), "one x");
+        assert_eq!(substituted("{{template|{{{1}}}}}"), "{{template|one}}");
+        let error = substitute("text {{{1|{{{2}}}", &args()).unwrap_err();
+        assert_eq!(error.comment, "Unclosed {{{parameter}}} in template note");
+    }
+
+    #[test]
+    fn front_matter_marks_pure_and_script_templates() {
+        let directory =
+            std::env::temp_dir().join(format!("confoosion-notetemplate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let template = |name: &str, source: &str| {
+            let path = directory.join(format!("Template:{name}.md"));
+            fs::write(&path, source).unwrap();
+            NoteTemplate::read(path).unwrap()
+        };
+
+        let plain = template("plain", "{{{1}}}");
+        assert!(!plain.is_pure() && !plain.is_script());
+        assert_eq!(plain.body_line, 1);
+        let pure = template("pure", "---\npure: true\n---\n{{{1}}}");
+        assert!(pure.is_pure() && !pure.is_script());
+        assert_eq!((pure.source.as_str(), pure.body_line), ("{{{1}}}", 4));
+        let other = template("other", "---\npure: yes\nscript: false\n---\n");
+        assert!(!other.is_pure() && !other.is_script());
+        let script = template(
+            "script",
+            "---\nscript: true\n---\n{%= arg(\"name\") %} {{{1}}}",
+        );
+        assert!(script.is_script() && !script.is_pure());
+
+        let templates = TemplateMap::new();
+        let render = |source: &str| markdown_to_html(source, directory.join("note.md"), &templates);
+        let html = render("{{pure|**a**}} {{plain|b}}").unwrap().html;
+        assert!(html.contains("<p><b>a</b></p> <p>b</p>"), "{html}");
+        let html = render("{{script|one|name=Ann}}").unwrap().html;
+        assert!(html.contains("Ann one"), "{html}");
+
+        template("script", "---\nscript: true\n---\nfine\n{% = %}");
+        let error = render("{{script}}").unwrap_err();
+        assert!(
+            error.comment.contains("Template:script.md:5:"),
+            "{}",
+            error.comment
+        );
+        assert!(NoteTemplate::read(directory.join("Template:none.md")).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -39,8 +39,10 @@ impl<'a> Iterator for UnmarkedPutBackChars<'a> {
     }
 }
 
-impl<'a> PutBackChars<'a> {
-    pub fn next(&mut self) -> Option<char> {
+impl<'a> Iterator for PutBackChars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
         let ch = self.internal.next();
         if ch == Some('\n') {
             self.line_number += 1;
@@ -50,13 +52,16 @@ impl<'a> PutBackChars<'a> {
         }
         ch
     }
+}
 
+impl<'a> PutBackChars<'a> {
     pub fn putback(&mut self, value: char) {
         self.internal.putback(value);
+        // Columns before a line break that was put back are not known, so they stay at zero.
         if value == '\n' {
-            self.line_number -= 1;
+            self.line_number = self.line_number.saturating_sub(1);
         } else {
-            self.column_number -= 1;
+            self.column_number = self.column_number.saturating_sub(1);
         }
     }
 
@@ -65,6 +70,24 @@ impl<'a> PutBackChars<'a> {
             self.putback(value);
         }
     }
+
+    /// Consumes `text` if it comes next, and leaves everything as it was otherwise.
+    pub fn skip_if(&mut self, text: &str) -> bool {
+        let mut read = Vec::new();
+        for expected in text.chars() {
+            match self.next() {
+                Some(character) if character == expected => read.push(character),
+                other => {
+                    self.putback_maybe(other);
+                    for character in read.into_iter().rev() {
+                        self.putback(character);
+                    }
+                    return false;
+                }
+            }
+        }
+        true
+    }
 }
 
 impl<'a> UnmarkedPutBackChars<'a> {
@@ -0,0 +1,246 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::title::TitleCache;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    Found(PathBuf),
+    /// No note matched. Holds the path a note of that name would be created at.
+    Missing(PathBuf),
+    /// Several notes matched equally well. The candidates are sorted; the first one is used.
+    Ambiguous(Vec<PathBuf>),
+}
+
+/// Turns the target of a `[[wiki-link]]` into the note it refers to.
+pub trait LinkResolver {
+    fn resolve(&self, name: &str, directory: &Path, titles: &TitleCache) -> Resolution;
+}
+
+/// Resolves links against the notes in the linking note's directory.
+///
+/// A link matches, in order of preference, a file stem exactly, a file stem ignoring case, or
+/// a note's `#` title or front-matter alias ignoring case. Only the first kind of match that
+/// finds anything is considered. Names that could reach outside the directory, such as
+/// `../secret`, match nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DirectoryResolver;
+
+impl LinkResolver for DirectoryResolver {
+    fn resolve(&self, name: &str, directory: &Path, titles: &TitleCache) -> Resolution {
+        let name = name.trim();
+        let exact = directory.join(format!("{name}.md"));
+        if !is_plain_name(name) {
+            return Resolution::Missing(exact);
+        }
+        if exact.is_file() {
+            return Resolution::Found(exact);
+        }
+        let notes = notes_in(directory);
+
+        let by_stem: Vec<PathBuf> = notes
+            .iter()
+            .filter(|note| match note.file_stem().and_then(|x| x.to_str()) {
+                Some(stem) => eq_ignore_case(stem, name),
+                None => false,
+            })
+            .cloned()
+            .collect();
+        if !by_stem.is_empty() {
+            return Resolution::from_candidates(by_stem, exact);
+        }
+
+        let by_title: Vec<PathBuf> = notes
+            .into_iter()
+            .filter(|note| match titles.header(note) {
+                Some(header) => header.names().iter().any(|x| eq_ignore_case(x, name)),
+                None => false,
+            })
+            .collect();
+        Resolution::from_candidates(by_title, exact)
+    }
+}
+
+impl Resolution {
+    fn from_candidates(mut candidates: Vec<PathBuf>, missing: PathBuf) -> Self {
+        match candidates.len() {
+            0 => Resolution::Missing(missing),
+            1 => Resolution::Found(candidates.pop().unwrap()),
+            _ => {
+                candidates.sort();
+                Resolution::Ambiguous(candidates)
+            }
+        }
+    }
+
+    /// The note the link should point at, whether or not it exists.
+    pub fn path(&self) -> &Path {
+        match self {
+            Resolution::Found(x) => x,
+            Resolution::Missing(x) => x,
+            Resolution::Ambiguous(candidates) => &candidates[0],
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        !matches!(self, Resolution::Missing(_))
+    }
+}
+
+/// The `.md` files in a directory, sorted.
+pub fn notes_in(directory: &Path) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(x) => x,
+        Err(_) => return Vec::new(),
+    };
+    let mut notes: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|x| x == "md") && path.is_file())
+        .collect();
+    notes.sort();
+    notes
+}
+
+/// Whether `name` stays inside the directory it is joined to: a single file name, without path
+/// separators, `..` or a drive.
+fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\'])
+}
+
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{markdown_to_html, template::TemplateMap};
+
+    #[test]
+    fn names_never_reach_outside_the_directory() {
+        let root = std::env::temp_dir().join(format!("confoosion-resolver-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let directory = root.join("vault");
+        fs::create_dir_all(directory.join("sub")).unwrap();
+        fs::write(root.join("secret.md"), "# Secret\n\npassword").unwrap();
+        fs::write(directory.join("sub").join("inner.md"), "# Inner\n").unwrap();
+        let titles = TitleCache::new();
+        for name in [
+            "../secret",
+            "..\\secret",
+            "sub/inner",
+            "/etc/hosts",
+            "..",
+            ".",
+        ] {
+            let resolution = DirectoryResolver.resolve(name, &directory, &titles);
+            assert!(!resolution.exists(), "{name} resolved to {resolution:?}");
+        }
+
+        let templates = TemplateMap::with_builtins();
+        let error = markdown_to_html("{{include|../secret}}", directory.join("a.md"), &templates)
+            .unwrap_err();
+        assert!(
+            error.comment.contains("no note ../secret"),
+            "{}",
+            error.comment
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// A directory of notes of its own for each test.
+    fn vault(name: &str, notes: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("confoosion-resolver-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for (note, source) in notes {
+            fs::write(directory.join(format!("{note}.md")), source).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn exact_names_come_before_stems_and_stems_before_titles() {
+        let directory = vault(
+            "order",
+            &[
+                (
+                    "Apple", "# Fruit
+",
+                ),
+                (
+                    "fruit",
+                    "# Something else
+",
+                ),
+                (
+                    "banana",
+                    "---
+alias: Apple Pie
+---
+# Yellow
+",
+                ),
+            ],
+        );
+        let titles = TitleCache::new();
+        let resolve = |name| DirectoryResolver.resolve(name, &directory, &titles);
+        let note = |name| directory.join(format!("{name}.md"));
+        assert_eq!(resolve("Apple"), Resolution::Found(note("Apple")));
+        assert_eq!(resolve(" apple "), Resolution::Found(note("Apple")));
+        assert_eq!(resolve("fruit"), Resolution::Found(note("fruit")));
+        assert_eq!(resolve("FRUIT"), Resolution::Found(note("fruit")));
+        assert_eq!(resolve("yellow"), Resolution::Found(note("banana")));
+        assert_eq!(resolve("apple pie"), Resolution::Found(note("banana")));
+        assert_eq!(resolve("cherry"), Resolution::Missing(note("cherry")));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn equally_good_matches_are_ambiguous_and_reported() {
+        let directory = vault(
+            "ambiguous",
+            &[
+                (
+                    "y", "# Twin
+",
+                ),
+                (
+                    "x",
+                    "---
+aliases: [twin]
+---
+",
+                ),
+            ],
+        );
+        let titles = TitleCache::new();
+        let resolution = DirectoryResolver.resolve("Twin", &directory, &titles);
+        let candidates = vec![directory.join("x.md"), directory.join("y.md")];
+        assert_eq!(resolution, Resolution::Ambiguous(candidates));
+        assert_eq!(resolution.path(), directory.join("x.md"));
+        assert!(resolution.exists());
+
+        let parsed =
+            markdown_to_html("[[twin]]", directory.join("z.md"), &TemplateMap::new()).unwrap();
+        assert!(parsed.html.contains("href=\"x.html\""), "{}", parsed.html);
+        let comments: Vec<&str> = parsed
+            .diagnostics
+            .iter()
+            .map(|x| x.comment.as_str())
+            .collect();
+        let expected = format!(
+            "Wiki-link [[twin]] is ambiguous, candidates are: {}, {}",
+            directory.join("x.md").display(),
+            directory.join("y.md").display()
+        );
+        assert_eq!(comments, [expected]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,361 @@
+/// A `[[target|label]]` in a note's source. `start` and `end` are byte offsets of the whole
+/// link, brackets included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikiLinkOccurrence {
+    pub target: String,
+    pub label: Option<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A `#tag` in a note's source. `start` and `end` are byte offsets of the whole tag, `#`
+/// included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagOccurrence {
+    pub tag: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u8,
+    /// The heading as written, without the leading `#`s.
+    pub text: String,
+    /// Byte offset of the line the heading is on.
+    pub start: usize,
+}
+
+pub fn wiki_links(source: &str) -> Vec<WikiLinkOccurrence> {
+    let mut out = Vec::new();
+    for (start, end) in outside_verbatim(source) {
+        let section = &source[start..end];
+        let mut offset = 0;
+        while let Some(open) = section[offset..].find("[[") {
+            let open = offset + open;
+            if is_escaped(section, open) {
+                offset = open + 2;
+                continue;
+            }
+            let close = match section[open + 2..].find("]]") {
+                Some(x) => open + 2 + x,
+                None => break,
+            };
+            let inner = &section[open + 2..close];
+            if inner.contains('\n') || inner.contains("[[") {
+                offset = open + 2;
+                continue;
+            }
+            let (target, label) = match inner.split_once('|') {
+                Some((target, label)) => (target, Some(label.to_string())),
+                None => (inner, None),
+            };
+            out.push(WikiLinkOccurrence {
+                target: target.trim().to_string(),
+                label,
+                start: start + open,
+                end: start + close + 2,
+            });
+            offset = close + 2;
+        }
+    }
+    out
+}
+
+pub fn headings(source: &str) -> Vec<Heading> {
+    let mut out = Vec::new();
+    for (start, end) in outside_verbatim(source) {
+        let mut line_start = start;
+        for line in source[start..end].split_inclusive('\n') {
+            if line_start == 0 || source.as_bytes()[line_start - 1] == b'\n' {
+                let level = line.chars().take_while(|x| *x == '#').count();
+                if level > 0 && !starts_with_tag(line) {
+                    out.push(Heading {
+                        level: level.min(u8::MAX as usize) as u8,
+                        text: line[level..].trim_end_matches(['\n', '\r']).to_string(),
+                        start: line_start,
+                    });
+                }
+            }
+            line_start += line.len();
+        }
+    }
+    out
+}
+
+/// The `#tags` in a note, outside code and links. A `#` only starts a tag at the start of a
+/// word, so `C#` and `page#anchor` are not tags, and a line starting with `#tag` is a tag rather
+/// than a heading.
+pub fn tags(source: &str) -> Vec<TagOccurrence> {
+    let mut links: Vec<(usize, usize)> = wiki_links(source)
+        .into_iter()
+        .map(|x| (x.start, x.end))
+        .collect();
+    links.append(&mut markdown_links(source));
+    let mut out = Vec::new();
+    for (start, end) in outside_verbatim(source) {
+        for (index, _) in source[start..end].match_indices('#') {
+            let index = start + index;
+            if is_escaped(source, index)
+                || !at_word_start(source, index)
+                || links.iter().any(|x| x.0 < index && index < x.1)
+            {
+                continue;
+            }
+            if let Some(length) = tag_length(&source[index + 1..end]) {
+                out.push(TagOccurrence {
+                    tag: source[index + 1..index + 1 + length].to_string(),
+                    start: index,
+                    end: index + 1 + length,
+                });
+            }
+        }
+    }
+    out
+}
+
+/// The length in bytes of the tag name at the start of `text`, the text after a `#`, if it
+/// starts with one.
+///
+/// Tag names are letters, digits, `_`, `-`, and `/` between the parts of a nested tag like
+/// `project/alpha`. They start with a letter, digit or `_`, and need something besides digits,
+/// so that `#1` stays text.
+pub fn tag_length(text: &str) -> Option<usize> {
+    let length = text
+        .char_indices()
+        .find(|(_, x)| !is_tag_character(*x))
+        .map_or(text.len(), |(at, _)| at);
+    let name = text[..length].trim_end_matches('/');
+    let valid = name.starts_with(|x: char| x.is_alphanumeric() || x == '_')
+        && !name.contains("//")
+        && name.contains(|x: char| !x.is_ascii_digit() && x != '/');
+    valid.then_some(name.len())
+}
+
+/// Whether `character` can be part of a tag name. [`tag_length`] decides where a name ends.
+pub fn is_tag_character(character: char) -> bool {
+    character.is_alphanumeric() || matches!(character, '_' | '-' | '/')
+}
+
+/// Whether `line` starts with a `#tag`, which makes it a line of text rather than a heading.
+pub fn starts_with_tag(line: &str) -> bool {
+    line.strip_prefix('#').and_then(tag_length).is_some()
+}
+
+/// Whether the character at `index` starts a word, looking past the markers of bold, italics
+/// and the like, which the renderer does not count as text. A lone `_` or `~` is text, and the
+/// text of a heading starts a word.
+fn at_word_start(source: &str, index: usize) -> bool {
+    let mut before = &source[..index];
+    let mut markers = false;
+    while let Some(rest) = before
+        .strip_suffix("__")
+        .or_else(|| before.strip_suffix("~~"))
+        .or_else(|| before.strip_suffix('*'))
+    {
+        before = rest;
+        markers = true;
+    }
+    let line_start = before.rfind('\n').map_or(0, |x| x + 1);
+    let line = &before[line_start..];
+    let heading = markers
+        && !line.is_empty()
+        && line.bytes().all(|x| x == b'#')
+        && !starts_with_tag(&source[line_start..]);
+    heading || before.chars().next_back().is_none_or(char::is_whitespace)
+}
+
+/// Byte ranges of `[text](url)` links and images. The renderer shows their text as written,
+/// and drops a `[text]` without a URL.
+fn markdown_links(source: &str) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    for (start, end) in outside_verbatim(source) {
+        let mut index = start;
+        while let Some(found) = source[index..end].find('[') {
+            let open = index + found;
+            if source[open..].starts_with("[[") {
+                index = open + 2;
+                continue;
+            }
+            index = open + 1;
+            if is_escaped(source, open) {
+                continue;
+            }
+            let Some(close) = source[open..].find(']') else {
+                break;
+            };
+            let mut link_end = open + close + 1;
+            if source[link_end..].starts_with('(') {
+                if let Some(paren) = source[link_end..].find(')') {
+                    link_end += paren + 1;
+                }
+            }
+            out.push((open, link_end));
+            if link_end >= end {
+                break;
+            }
+            index = link_end;
+        }
+    }
+    out
+}
+
+/// Byte ranges of `source` that are not inside code, `<nowiki>` regions or `{{nowiki|...}}`.
+fn outside_verbatim(source: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    for (start, end) in outside_code(source) {
+        let mut gap_start = start;
+        let mut index = start;
+        while index < end {
+            let rest = &source[index..end];
+            let verbatim_end = if rest.starts_with("<nowiki>") && !is_escaped(source, index) {
+                rest.find("</nowiki>")
+                    .map(|x| index + x + "</nowiki>".len())
+            } else if (rest.starts_with("{{nowiki|") || rest.starts_with("{{nowiki}}"))
+                && !is_escaped(source, index)
+            {
+                template_end(rest).map(|x| index + x)
+            } else {
+                None
+            };
+            match verbatim_end {
+                Some(verbatim_end) => {
+                    if index > gap_start {
+                        ranges.push((gap_start, index));
+                    }
+                    gap_start = verbatim_end;
+                    index = verbatim_end;
+                }
+                None => index += rest.chars().next().map_or(1, char::len_utf8),
+            }
+        }
+        if end > gap_start {
+            ranges.push((gap_start, end));
+        }
+    }
+    ranges
+}
+
+/// The byte offset just past the `}}` closing the template `text` starts with.
+fn template_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut index = 0;
+    while index < text.len() {
+        let rest = &text[index..];
+        if let Some(escaped) = rest.strip_prefix('\\') {
+            index += 1 + escaped.chars().next().map_or(0, char::len_utf8);
+        } else if rest.starts_with("{{") {
+            depth += 1;
+            index += 2;
+        } else if rest.starts_with("}}") {
+            depth -= 1;
+            index += 2;
+            if depth == 0 {
+                return Some(index);
+            }
+        } else {
+            index += rest.chars().next()?.len_utf8();
+        }
+    }
+    None
+}
+
+/// Byte ranges of `source` that are not inside code blocks or inline code.
+fn outside_code(source: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut in_block = false;
+    let mut line_start = 0;
+    for line in source.split_inclusive('\n') {
+        if line.starts_with("```") {
+            if in_block {
+                start = line_start + line.len();
+            } else {
+                inline_code_gaps(source, start, line_start, &mut ranges);
+            }
+            in_block = !in_block;
+        }
+        line_start += line.len();
+    }
+    if !in_block {
+        inline_code_gaps(source, start, source.len(), &mut ranges);
+    }
+    ranges
+}
+
+fn inline_code_gaps(source: &str, start: usize, end: usize, ranges: &mut Vec<(usize, usize)>) {
+    let mut gap_start = start;
+    let mut in_code = false;
+    for (index, character) in source[start..end].char_indices() {
+        let index = start + index;
+        if character != '`' || is_escaped(source, index) {
+            continue;
+        }
+        if in_code {
+            gap_start = index + 1;
+        } else if index > gap_start {
+            ranges.push((gap_start, index));
+        }
+        in_code = !in_code;
+    }
+    if !in_code && end > gap_start {
+        ranges.push((gap_start, end));
+    }
+}
+
+fn is_escaped(text: &str, index: usize) -> bool {
+    text[..index]
+        .bytes()
+        .rev()
+        .take_while(|x| *x == b'\\')
+        .count()
+        % 2
+        == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{markdown_to_html, raw_title, template::TemplateMap};
+
+    const SAMPLES: &[&str] = &[
+        "#Foo\nbody\n",
+        "# Title\n\nAbout #project/alpha and #beta, #beta again.\n",
+        "#tag at the start, then C# and page#anchor\n",
+        "*#bold* _#under_ ~~#gone~~ ~#tilde\n",
+        "#1 is not a tag, #1a is\n",
+        "`#code` and [[note#heading]] and \\#escaped\n",
+        "#-x and #_ and #a//b and #a/\n",
+        "x \\ #space and x\\\n#break\n",
+        "##*#heading*\n#*#next*\n#__#not\n",
+        "[see #link](url) and [#dropped] and ![#alt](image.png)\n",
+        "#\n",
+    ];
+
+    #[test]
+    fn the_renderer_finds_the_same_tags() {
+        for source in SAMPLES {
+            let rendered = markdown_to_html(source, "note.md", &TemplateMap::new())
+                .unwrap()
+                .tags;
+            let mut scanned: Vec<String> = Vec::new();
+            for tag in tags(source) {
+                if !scanned.contains(&tag.tag) {
+                    scanned.push(tag.tag);
+                }
+            }
+            assert_eq!(rendered, scanned, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn a_line_starting_with_a_tag_is_no_heading() {
+        assert_eq!(raw_title("#Foo\nbody\n"), None);
+        assert!(headings("#Foo\nbody\n").is_empty());
+        assert_eq!(raw_title("#1 Foo\n").as_deref(), Some("1 Foo"));
+        assert_eq!(headings("#1 Foo\n")[0].text, "1 Foo");
+        assert_eq!(raw_title("# Foo\n").as_deref(), Some(" Foo"));
+        assert_eq!(headings("## Foo #bar\n")[0].level, 2);
+        assert!(starts_with_tag("#Foo") && !starts_with_tag("# Foo") && !starts_with_tag("#1"));
+    }
+}
@@ -0,0 +1,1433 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    arguments::TemplateArgs,
+    context::TemplateContext,
+    dependencies::Dependencies,
+    error::ParseError,
+    frontmatter::{FrontMatter, FrontMatterValue},
+};
+
+/// A value in a template script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    List(Vec<Value>),
+}
+
+impl Value {
+    /// `nil`, `false`, `0`, `""` and `[]` are false, everything else is true.
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Bool(x) => *x,
+            Value::Num(x) => *x != 0.0,
+            Value::Str(x) => !x.is_empty(),
+            Value::List(x) => !x.is_empty(),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Num(_) => "number",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+        }
+    }
+
+    /// Roughly how many bytes the value takes up, to keep scripts from building huge values.
+    fn size(&self) -> usize {
+        match self {
+            Value::Str(x) => x.len(),
+            Value::List(x) => x.iter().map(|x| x.size() + 1).sum(),
+            _ => 1,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => Ok(()),
+            Value::Bool(x) => write!(f, "{x}"),
+            Value::Num(x) if x.fract() == 0.0 && x.abs() < 1e15 => write!(f, "{}", *x as i64),
+            Value::Num(x) => write!(f, "{x}"),
+            Value::Str(x) => write!(f, "{x}"),
+            Value::List(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Runs the script in a template note and returns the Markdown it produces, along with the
+/// vault lookups it made.
+///
+/// Text is copied as is. `{%= expression %}` writes the value of an expression, and statements
+/// control what is written:
+///
+/// ```text
+/// {% let name = arg("name", "nobody") %}
+/// {% if len(tagged("todo")) > 0 %}
+/// {% for note in tagged("todo") %}
+/// - [[{%= note %}]]
+/// {% end %}
+/// {% elif name == "nobody" %}
+/// Nothing to do.
+/// {% else %}
+/// Nothing to do for {%= upper(name) %}.
+/// {% end %}
+/// ```
+///
+/// `let` changes a variable that already exists, or else creates one that lasts until the end of
+/// the block. A line holding nothing but a statement disappears from the output. Scripts can read the
+/// template's arguments, the calling note's front matter and the vault through
+/// [`crate::vault::VaultQuery`], with notes named by file name; they cannot do anything else. They
+/// run under the [`crate::limits::Limits`] of the [`crate::template::TemplateMap`].
+pub fn run(
+    source: &str,
+    args: &TemplateArgs,
+    context: &TemplateContext,
+) -> Result<(String, Dependencies), ParseError> {
+    let nodes = parse(source)?;
+    let mut interpreter = Interpreter::new(args, context);
+    let mut out = String::new();
+    interpreter.block(&nodes, &mut out)?;
+    Ok((out, interpreter.dependencies))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn error(self, message: String) -> ParseError {
+        ParseError::at(self.line, self.column, message)
+    }
+}
+
+enum Piece {
+    Text(String),
+    Output(String, Position),
+    Statement(String, Position),
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Output(Expr, Position),
+    Let(String, Expr, Position),
+    If(Vec<(Expr, Position, Vec<Node>)>, Vec<Node>),
+    For(String, Expr, Position, Vec<Node>),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Variable(String),
+    List(Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// Splits a script into text, `{%= %}` and `{% %}` pieces.
+fn pieces(source: &str) -> Result<Vec<Piece>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut out = Vec::new();
+    let mut text = String::new();
+    let mut position = Position { line: 1, column: 1 };
+    let mut index = 0;
+    while index < chars.len() {
+        if chars[index] == '{' && chars.get(index + 1) == Some(&'%') {
+            let start = position;
+            let output = chars.get(index + 2) == Some(&'=');
+            let body_start = index + if output { 3 } else { 2 };
+            let mut end = body_start;
+            let mut quoted = false;
+            while end < chars.len() {
+                match chars[end] {
+                    '\\' if quoted => end += 1,
+                    '"' => quoted = !quoted,
+                    '%' if !quoted && chars.get(end + 1) == Some(&'}') => break,
+                    _ => (),
+                }
+                end += 1;
+            }
+            if end >= chars.len() {
+                return Err(start.error("Unclosed {% in script".to_string()));
+            }
+            let body: String = chars[body_start..end].iter().collect();
+            for character in &chars[index..end + 2] {
+                advance(&mut position, *character);
+            }
+            index = end + 2;
+            if output {
+                out.push(Piece::Text(std::mem::take(&mut text)));
+                out.push(Piece::Output(body, start));
+                continue;
+            }
+            // A statement alone on its line takes the line with it.
+            let line_start = text.rfind('\n').map_or(0, |x| x + 1);
+            let alone_before = text[line_start..].trim().is_empty()
+                && (line_start > 0 || out.is_empty() || ends_line(&out));
+            let rest_of_line = chars[index..]
+                .iter()
+                .position(|x| *x == '\n')
+                .map_or(chars.len(), |x| index + x);
+            let alone_after = chars[index..rest_of_line].iter().all(|x| x.is_whitespace());
+            if alone_before && alone_after {
+                text.truncate(line_start);
+                while index < chars.len() && index <= rest_of_line {
+                    advance(&mut position, chars[index]);
+                    index += 1;
+                }
+            }
+            out.push(Piece::Text(std::mem::take(&mut text)));
+            out.push(Piece::Statement(body, start));
+            continue;
+        }
+        advance(&mut position, chars[index]);
+        text.push(chars[index]);
+        index += 1;
+    }
+    out.push(Piece::Text(text));
+    Ok(out)
+}
+
+/// Whether the text before the last piece ended a line, for a statement that follows it
+/// directly.
+fn ends_line(pieces: &[Piece]) -> bool {
+    for piece in pieces.iter().rev() {
+        match piece {
+            Piece::Text(text) if text.is_empty() => continue,
+            Piece::Text(text) => return text.ends_with('\n'),
+            Piece::Output(..) => return false,
+            Piece::Statement(..) => return true,
+        }
+    }
+    true
+}
+
+fn advance(position: &mut Position, character: char) {
+    if character == '\n' {
+        position.line += 1;
+        position.column = 1;
+    } else {
+        position.column += 1;
+    }
+}
+
+fn parse(source: &str) -> Result<Vec<Node>, ParseError> {
+    let mut pieces = pieces(source)?.into_iter();
+    match block(&mut pieces)? {
+        (nodes, None) => Ok(nodes),
+        (_, Some((keyword, _, position))) => Err(position.error(format!(
+            "{{% {keyword} %}} without an opening {{% if %}} or {{% for %}}"
+        ))),
+    }
+}
+
+type Terminator = (String, Vec<Token>, Position);
+
+/// Reads nodes up to the end of the script or a statement that ends a block: `elif`, `else` or
+/// `end`.
+fn block(
+    pieces: &mut impl Iterator<Item = Piece>,
+) -> Result<(Vec<Node>, Option<Terminator>), ParseError> {
+    let mut nodes = Vec::new();
+    while let Some(piece) = pieces.next() {
+        match piece {
+            Piece::Text(text) if text.is_empty() => (),
+            Piece::Text(text) => nodes.push(Node::Text(text)),
+            Piece::Output(source, position) => {
+                let tokens = tokenize(&source).map_err(|e| position.error(e))?;
+                nodes.push(Node::Output(
+                    expression(tokens).map_err(|e| position.error(e))?,
+                    position,
+                ));
+            }
+            Piece::Statement(source, position) => {
+                let mut tokens = tokenize(&source).map_err(|e| position.error(e))?;
+                if tokens.is_empty() {
+                    return Err(position.error("Empty {% %} statement".to_string()));
+                }
+                let keyword = match tokens.remove(0) {
+                    Token::Ident(x) => x,
+                    other => {
+                        return Err(position.error(format!("Expected a statement, found {other:?}")))
+                    }
+                };
+                match keyword.as_str() {
+                    "elif" | "else" | "end" => {
+                        return Ok((nodes, Some((keyword, tokens, position))))
+                    }
+                    "let" => {
+                        let (name, value) =
+                            binding(tokens, Token::Op("=")).map_err(|e| position.error(e))?;
+                        nodes.push(Node::Let(name, value, position));
+                    }
+                    "for" => {
+                        let (name, list) = binding(tokens, Token::Ident("in".to_string()))
+                            .map_err(|e| position.error(e))?;
+                        let (body, terminator) = block(pieces)?;
+                        expect_end(terminator, "for", position)?;
+                        nodes.push(Node::For(name, list, position, body));
+                    }
+                    "if" => nodes.push(if_chain(tokens, position, pieces)?),
+                    other => {
+                        return Err(position.error(format!("Unknown statement {{% {other} %}}")))
+                    }
+                }
+            }
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn if_chain(
+    tokens: Vec<Token>,
+    position: Position,
+    pieces: &mut impl Iterator<Item = Piece>,
+) -> Result<Node, ParseError> {
+    let mut branches = Vec::new();
+    let mut condition = (expression(tokens).map_err(|e| position.error(e))?, position);
+    loop {
+        let (body, terminator) = block(pieces)?;
+        branches.push((condition.0, condition.1, body));
+        match terminator {
+            Some((keyword, tokens, at)) if keyword == "elif" => {
+                condition = (expression(tokens).map_err(|e| at.error(e))?, at);
+            }
+            Some((keyword, tokens, at)) if keyword == "else" => {
+                if !tokens.is_empty() {
+                    return Err(
+                        at.error("{% else %} takes no condition, use {% elif %}".to_string())
+                    );
+                }
+                let (otherwise, terminator) = block(pieces)?;
+                expect_end(terminator, "if", position)?;
+                return Ok(Node::If(branches, otherwise));
+            }
+            terminator => {
+                expect_end(terminator, "if", position)?;
+                return Ok(Node::If(branches, Vec::new()));
+            }
+        }
+    }
+}
+
+fn expect_end(
+    terminator: Option<Terminator>,
+    opening: &str,
+    position: Position,
+) -> Result<(), ParseError> {
+    match terminator {
+        Some((keyword, tokens, at)) if keyword == "end" => match tokens.is_empty() {
+            true => Ok(()),
+            false => Err(at.error("{% end %} takes nothing else".to_string())),
+        },
+        Some((keyword, _, at)) => Err(at.error(format!("Unexpected {{% {keyword} %}}"))),
+        None => Err(position.error(format!("{{% {opening} %}} is missing its {{% end %}}"))),
+    }
+}
+
+/// Reads `name <separator> expression`, as in `let` and `for`.
+fn binding(mut tokens: Vec<Token>, separator: Token) -> Result<(String, Expr), String> {
+    if tokens.len() < 3 {
+        return Err(format!("Expected a name, {separator:?} and an expression"));
+    }
+    let name = match tokens.remove(0) {
+        Token::Ident(x) if !is_keyword(&x) => x,
+        other => return Err(format!("Expected a name, found {other:?}")),
+    };
+    if tokens.remove(0) != separator {
+        return Err(format!("Expected {separator:?} after {name}"));
+    }
+    Ok((name, expression(tokens)?))
+}
+
+fn is_keyword(word: &str) -> bool {
+    matches!(
+        word,
+        "let"
+            | "if"
+            | "elif"
+            | "else"
+            | "end"
+            | "for"
+            | "in"
+            | "and"
+            | "or"
+            | "not"
+            | "true"
+            | "false"
+            | "nil"
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 17] = [
+    "==", "!=", "<=", ">=", "<", ">", "=", "+", "-", "*", "/", "%", "(", ")", "[", "]", ",",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut out = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&character) = chars.peek() {
+        if character.is_whitespace() {
+            chars.next();
+        } else if character.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(&x) = chars.peek() {
+                if !(x.is_ascii_digit() || x == '.') {
+                    break;
+                }
+                number.push(x);
+                chars.next();
+            }
+            match number.parse() {
+                Ok(x) => out.push(Token::Num(x)),
+                Err(_) => return Err(format!("{number} is not a number")),
+            }
+        } else if character.is_alphabetic() || character == '_' {
+            let mut word = String::new();
+            while let Some(&x) = chars.peek() {
+                if !(x.is_alphanumeric() || x == '_') {
+                    break;
+                }
+                word.push(x);
+                chars.next();
+            }
+            out.push(Token::Ident(word));
+        } else if character == '"' {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => string.push('\n'),
+                        Some('t') => string.push('\t'),
+                        Some(x) => string.push(x),
+                        None => return Err("Unclosed string".to_string()),
+                    },
+                    Some(x) => string.push(x),
+                    None => return Err("Unclosed string".to_string()),
+                }
+            }
+            out.push(Token::Str(string));
+        } else {
+            let rest: String = chars.clone().take(2).collect();
+            let operator = OPERATORS
+                .iter()
+                .find(|x| rest.starts_with(**x))
+                .ok_or(format!("Unexpected {character}"))?;
+            for _ in 0..operator.len() {
+                chars.next();
+            }
+            out.push(Token::Op(operator));
+        }
+    }
+    Ok(out)
+}
+
+fn expression(tokens: Vec<Token>) -> Result<Expr, String> {
+    if tokens.is_empty() {
+        return Err("Expected an expression".to_string());
+    }
+    let mut parser = Parser { tokens, index: 0 };
+    let out = parser.or()?;
+    match parser.tokens.get(parser.index) {
+        Some(token) => Err(format!("Unexpected {token:?}")),
+        None => Ok(out),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek_is(&self, token: &Token) -> bool {
+        self.tokens.get(self.index) == Some(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek_is(token);
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.eat(&token) {
+            true => Ok(()),
+            false => Err(format!("Expected {token:?}")),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat(&Token::Ident("or".to_string())) {
+            left = Expr::Binary("or", Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.not()?;
+        while self.eat(&Token::Ident("and".to_string())) {
+            left = Expr::Binary("and", Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.eat(&Token::Ident("not".to_string())) {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        for operator in ["==", "!=", "<=", ">=", "<", ">"] {
+            if self.eat(&Token::Op(operator)) {
+                return Ok(Expr::Binary(
+                    operator,
+                    Box::new(left),
+                    Box::new(self.sum()?),
+                ));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.product()?;
+        'outer: loop {
+            for operator in ["+", "-"] {
+                if self.eat(&Token::Op(operator)) {
+                    left = Expr::Binary(operator, Box::new(left), Box::new(self.product()?));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        'outer: loop {
+            for operator in ["*", "/", "%"] {
+                if self.eat(&Token::Op(operator)) {
+                    left = Expr::Binary(operator, Box::new(left), Box::new(self.unary()?));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat(&Token::Op("-")) {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        let mut value = self.primary()?;
+        while self.eat(&Token::Op("[")) {
+            let index = self.or()?;
+            self.expect(Token::Op("]"))?;
+            value = Expr::Index(Box::new(value), Box::new(index));
+        }
+        Ok(value)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = match self.tokens.get(self.index) {
+            Some(x) => x.clone(),
+            None => return Err("Expression ends too early".to_string()),
+        };
+        self.index += 1;
+        match token {
+            Token::Num(x) => Ok(Expr::Literal(Value::Num(x))),
+            Token::Str(x) => Ok(Expr::Literal(Value::Str(x))),
+            Token::Ident(x) => match x.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "nil" => Ok(Expr::Literal(Value::Nil)),
+                word if is_keyword(word) => Err(format!("Unexpected {word}")),
+                _ if self.eat(&Token::Op("(")) => Ok(Expr::Call(x, self.list(")")?)),
+                _ => Ok(Expr::Variable(x)),
+            },
+            Token::Op("(") => {
+                let inner = self.or()?;
+                self.expect(Token::Op(")"))?;
+                Ok(inner)
+            }
+            Token::Op("[") => Ok(Expr::List(self.list("]")?)),
+            other => Err(format!("Unexpected {other:?}")),
+        }
+    }
+
+    /// Reads comma-separated expressions up to and including `close`.
+    fn list(&mut self, close: &'static str) -> Result<Vec<Expr>, String> {
+        let mut items = Vec::new();
+        if self.eat(&Token::Op(close)) {
+            return Ok(items);
+        }
+        loop {
+            items.push(self.or()?);
+            if self.eat(&Token::Op(close)) {
+                return Ok(items);
+            }
+            self.expect(Token::Op(","))?;
+        }
+    }
+}
+
+struct Interpreter<'a, 'b> {
+    args: &'a TemplateArgs,
+    context: &'a TemplateContext<'b>,
+    scopes: Vec<HashMap<String, Value>>,
+    steps: usize,
+    max_steps: usize,
+    max_bytes: usize,
+    deadline: Option<Instant>,
+    dependencies: Dependencies,
+}
+
+impl<'a, 'b> Interpreter<'a, 'b> {
+    fn new(args: &'a TemplateArgs, context: &'a TemplateContext<'b>) -> Self {
+        let limits = context.templates.limits();
+        Self {
+            args,
+            context,
+            scopes: vec![HashMap::new()],
+            steps: 0,
+            max_steps: limits.max_script_steps,
+            max_bytes: limits.max_output_bytes,
+            deadline: context
+                .templates
+                .usage()
+                .started
+                .checked_add(limits.max_duration),
+            dependencies: Dependencies::new(),
+        }
+    }
+
+    fn step(&mut self, count: usize) -> Result<(), String> {
+        let before = self.steps;
+        self.steps = self.steps.saturating_add(count);
+        if self.steps > self.max_steps {
+            return Err(format!("Script took more than {} steps", self.max_steps));
+        }
+        // Looking at the clock is slow, so only do it every thousand steps or so.
+        if let Some(deadline) = self.deadline {
+            if before >> 10 != self.steps >> 10 && Instant::now() > deadline {
+                return Err("Script ran out of time".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, nodes: &[Node], out: &mut String) -> Result<(), ParseError> {
+        self.scopes.push(HashMap::new());
+        let result = self.nodes(nodes, out);
+        self.scopes.pop();
+        result
+    }
+
+    fn nodes(&mut self, nodes: &[Node], out: &mut String) -> Result<(), ParseError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Output(expression, position) => {
+                    let value = self.evaluate(expression).map_err(|e| position.error(e))?;
+                    out.push_str(&value.to_string());
+                }
+                Node::Let(name, expression, position) => {
+                    let value = self.evaluate(expression).map_err(|e| position.error(e))?;
+                    let scope = match self.scopes.iter_mut().rev().find(|x| x.contains_key(name)) {
+                        Some(scope) => scope,
+                        None => self.scopes.last_mut().unwrap(),
+                    };
+                    scope.insert(name.clone(), value);
+                }
+                Node::If(branches, otherwise) => {
+                    let mut chosen = otherwise;
+                    for (condition, position, body) in branches {
+                        if self
+                            .evaluate(condition)
+                            .map_err(|e| position.error(e))?
+                            .truthy()
+                        {
+                            chosen = body;
+                            break;
+                        }
+                    }
+                    self.block(chosen, out)?;
+                }
+                Node::For(name, list, position, body) => {
+                    let items = match self.evaluate(list).map_err(|e| position.error(e))? {
+                        Value::List(items) => items,
+                        Value::Nil => Vec::new(),
+                        other => {
+                            return Err(
+                                position.error(format!("Cannot loop over a {}", other.type_name()))
+                            )
+                        }
+                    };
+                    for item in items {
+                        self.step(1).map_err(|e| position.error(e))?;
+                        self.scopes.push(HashMap::from([(name.clone(), item)]));
+                        let result = self.block(body, out);
+                        self.scopes.pop();
+                        result?;
+                    }
+                }
+            }
+            if out.len() > self.max_bytes {
+                return Err(ParseError::empty(
+                    format!("Script produced more than {} bytes", self.max_bytes).as_str(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&mut self, expression: &Expr) -> Result<Value, String> {
+        self.step(1)?;
+        let value = match expression {
+            Expr::Literal(x) => x.clone(),
+            Expr::Variable(name) => match self.scopes.iter().rev().find_map(|x| x.get(name)) {
+                Some(x) => x.clone(),
+                None => return Err(format!("Unknown variable {name}")),
+            },
+            Expr::List(items) => Value::List(
+                items
+                    .iter()
+                    .map(|x| self.evaluate(x))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::Index(value, index) => {
+                let value = self.evaluate(value)?;
+                let index = self.evaluate(index)?;
+                index_value(value, index)?
+            }
+            Expr::Call(name, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|x| self.evaluate(x))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, arguments)?
+            }
+            Expr::Not(x) => Value::Bool(!self.evaluate(x)?.truthy()),
+            Expr::Negate(x) => Value::Num(-number(&self.evaluate(x)?)?),
+            Expr::Binary("and", left, right) => match self.evaluate(left)? {
+                left if !left.truthy() => left,
+                _ => self.evaluate(right)?,
+            },
+            Expr::Binary("or", left, right) => match self.evaluate(left)? {
+                left if left.truthy() => left,
+                _ => self.evaluate(right)?,
+            },
+            Expr::Binary(operator, left, right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                binary(operator, left, right)?
+            }
+        };
+        if value.size() > self.max_bytes {
+            return Err(format!("Value larger than {} bytes", self.max_bytes));
+        }
+        Ok(value)
+    }
+
+    fn call(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, String> {
+        let arguments = arguments.as_slice();
+        let out = match (name, arguments) {
+            ("len", [Value::Str(x)]) => Value::Num(x.chars().count() as f64),
+            ("len", [Value::List(x)]) => Value::Num(x.len() as f64),
+            ("len", [Value::Nil]) => Value::Num(0.0),
+            ("upper", [x]) => Value::Str(x.to_string().to_uppercase()),
+            ("lower", [x]) => Value::Str(x.to_string().to_lowercase()),
+            ("trim", [x]) => Value::Str(x.to_string().trim().to_string()),
+            ("escape", [x]) => Value::Str(crate::escape_html(&x.to_string())),
+            ("str", [x]) => Value::Str(x.to_string()),
+            ("num", [x]) => match x.to_string().trim().parse() {
+                Ok(x) => Value::Num(x),
+                Err(_) => Value::Nil,
+            },
+            ("replace", [x, from, to]) => {
+                let from = from.to_string();
+                if from.is_empty() {
+                    return Err("replace() needs something to replace".to_string());
+                }
+                let (text, to) = (x.to_string(), to.to_string());
+                let count = text.matches(&from).count();
+                self.step(count + text.len() / 64)?;
+                self.fits(text.len() - count * from.len() + count.saturating_mul(to.len()))?;
+                Value::Str(text.replace(&from, &to))
+            }
+            ("contains", [Value::List(items), x]) => Value::Bool(items.contains(x)),
+            ("contains", [x, part]) => Value::Bool(x.to_string().contains(&part.to_string())),
+            ("starts_with", [x, part]) => Value::Bool(x.to_string().starts_with(&part.to_string())),
+            ("ends_with", [x, part]) => Value::Bool(x.to_string().ends_with(&part.to_string())),
+            ("split", [x, separator]) => {
+                let separator = separator.to_string();
+                if separator.is_empty() {
+                    return Err("split() needs a separator".to_string());
+                }
+                let text = x.to_string();
+                // Each part takes a byte more than its text, like in `Value::size`.
+                let count = text.matches(&separator).count() + 1;
+                self.step(count)?;
+                self.fits(text.len() - (count - 1) * separator.len() + count)?;
+                Value::List(
+                    text.split(&separator)
+                        .map(|x| Value::Str(x.to_string()))
+                        .collect(),
+                )
+            }
+            ("join", [Value::List(items)]) => self.join(items, "")?,
+            ("join", [Value::List(items), separator]) => {
+                self.join(items, &separator.to_string())?
+            }
+            ("slice", [x, start]) => slice(x, number(start)?, None)?,
+            ("slice", [x, start, end]) => slice(x, number(start)?, Some(number(end)?))?,
+            ("sort", [Value::List(items)]) => {
+                let mut items = items.clone();
+                self.step(items.len())?;
+                items.sort_by(|a, b| compare(a, b).unwrap_or(std::cmp::Ordering::Equal));
+                Value::List(items)
+            }
+            ("reverse", [Value::List(items)]) => Value::List(items.iter().rev().cloned().collect()),
+            ("range", [end]) => self.range(0.0, number(end)?)?,
+            ("range", [start, end]) => self.range(number(start)?, number(end)?)?,
+            ("arg", [key]) => self.argument(key).unwrap_or(Value::Nil),
+            ("arg", [key, default]) => self.argument(key).unwrap_or(default.clone()),
+            ("args", []) => Value::List(
+                self.args
+                    .positional()
+                    .iter()
+                    .map(|x| Value::Str(x.raw().to_string()))
+                    .collect(),
+            ),
+            ("note", []) => match self.context.note_name() {
+                Some(x) => Value::Str(x),
+                None => Value::Nil,
+            },
+            ("meta", [key]) => match self.context.front_matter() {
+                Some(front_matter) => front_matter_value(front_matter, &key.to_string()),
+                None => Value::Nil,
+            },
+            ("meta", [note, key]) => match self.note(note) {
+                Some(path) => {
+                    self.dependencies.titles.insert(path.clone());
+                    let front_matter = self.context.vault().front_matter(&path);
+                    front_matter_value(&front_matter, &key.to_string())
+                }
+                None => Value::Nil,
+            },
+            ("exists", [note]) => Value::Bool(self.note(note).is_some()),
+            ("title", [note]) => match self.note(note) {
+                Some(path) => {
+                    self.dependencies.titles.insert(path.clone());
+                    match self.context.vault().title(&path) {
+                        Some(x) => Value::Str(x),
+                        None => Value::Nil,
+                    }
+                }
+                None => Value::Nil,
+            },
+            ("tags", [note]) => match self.note(note) {
+                Some(path) => {
+                    self.dependencies.titles.insert(path.clone());
+                    strings(self.context.vault().tags(&path))
+                }
+                None => Value::List(Vec::new()),
+            },
+            ("notes", []) => {
+                self.dependencies.vault = true;
+                self.names(self.context.vault().notes())?
+            }
+            ("tagged", [tag]) => {
+                self.dependencies.vault = true;
+                let tag = tag.to_string();
+                self.names(
+                    self.context
+                        .vault()
+                        .tagged(tag.trim().trim_start_matches('#')),
+                )?
+            }
+            ("backlinks", [note]) => {
+                self.dependencies.vault = true;
+                match self.note(note) {
+                    Some(path) => self.names(self.context.vault().backlinks(&path))?,
+                    None => Value::List(Vec::new()),
+                }
+            }
+            ("links", [note]) => match self.note(note) {
+                Some(path) => {
+                    self.dependencies.transclusions.insert(path.clone());
+                    self.names(self.context.vault().links_from(&path))?
+                }
+                None => Value::List(Vec::new()),
+            },
+            _ if FUNCTIONS.contains(&name) => {
+                let types: Vec<&str> = arguments.iter().map(Value::type_name).collect();
+                return Err(format!("{name}() does not take ({})", types.join(", ")));
+            }
+            _ => return Err(format!("Unknown function {name}()")),
+        };
+        Ok(out)
+    }
+
+    fn argument(&self, key: &Value) -> Option<Value> {
+        let raw = match key {
+            Value::Num(index) if *index >= 1.0 => self.args.get(*index as usize - 1),
+            key => self.args.value(&key.to_string()),
+        };
+        raw.map(|x| Value::Str(x.to_string()))
+    }
+
+    /// Fails if a value of `size` bytes would be too large, before it is built.
+    fn fits(&self, size: usize) -> Result<(), String> {
+        match size > self.max_bytes {
+            true => Err(format!("Value larger than {} bytes", self.max_bytes)),
+            false => Ok(()),
+        }
+    }
+
+    fn join(&mut self, items: &[Value], separator: &str) -> Result<Value, String> {
+        self.step(items.len())?;
+        let separators = items.len().saturating_sub(1);
+        self.fits(separators.saturating_mul(separator.len()))?;
+        let mut out = String::new();
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 {
+                out.push_str(separator);
+            }
+            out.push_str(&item.to_string());
+            self.fits(out.len())?;
+        }
+        Ok(Value::Str(out))
+    }
+
+    fn range(&mut self, start: f64, end: f64) -> Result<Value, String> {
+        let count = (end - start).max(0.0) as usize;
+        self.step(count)?;
+        Ok(Value::List(
+            (0..count).map(|x| Value::Num(start + x as f64)).collect(),
+        ))
+    }
+
+    /// The note a script names, if it exists.
+    fn note(&self, name: &Value) -> Option<PathBuf> {
+        let resolution = self.context.resolve(&name.to_string());
+        match resolution.exists() {
+            true => Some(resolution.path().to_path_buf()),
+            false => None,
+        }
+    }
+
+    fn names(&mut self, notes: Vec<PathBuf>) -> Result<Value, String> {
+        self.step(notes.len())?;
+        Ok(Value::List(
+            notes.iter().map(|x| Value::Str(note_name(x))).collect(),
+        ))
+    }
+}
+
+const FUNCTIONS: &[&str] = &[
+    "len",
+    "upper",
+    "lower",
+    "trim",
+    "escape",
+    "str",
+    "num",
+    "replace",
+    "contains",
+    "starts_with",
+    "ends_with",
+    "split",
+    "join",
+    "slice",
+    "sort",
+    "reverse",
+    "range",
+    "arg",
+    "args",
+    "note",
+    "meta",
+    "exists",
+    "title",
+    "tags",
+    "notes",
+    "tagged",
+    "backlinks",
+    "links",
+];
+
+fn note_name(note: &Path) -> String {
+    match note.file_stem() {
+        Some(x) => x.to_string_lossy().to_string(),
+        None => String::new(),
+    }
+}
+
+fn strings(items: Vec<String>) -> Value {
+    Value::List(items.into_iter().map(Value::Str).collect())
+}
+
+fn front_matter_value(front_matter: &FrontMatter, key: &str) -> Value {
+    match front_matter.get(key) {
+        Some(FrontMatterValue::Text(x)) => Value::Str(x.clone()),
+        Some(FrontMatterValue::List(x)) => strings(x.clone()),
+        None => Value::Nil,
+    }
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Num(x) => Ok(*x),
+        other => Err(format!("Expected a number, found a {}", other.type_name())),
+    }
+}
+
+/// Turns a possibly negative index, counting from the end, into a position.
+fn position(index: f64, length: usize) -> usize {
+    let index = index as i64;
+    if index < 0 {
+        (length as i64 + index).max(0) as usize
+    } else {
+        (index as usize).min(length)
+    }
+}
+
+fn index_value(value: Value, index: Value) -> Result<Value, String> {
+    let index = number(&index)?;
+    let out = match value {
+        Value::List(items) => {
+            let at = position(index, items.len());
+            items.into_iter().nth(at)
+        }
+        Value::Str(text) => {
+            let length = text.chars().count();
+            let at = position(index, length);
+            text.chars().nth(at).map(|x| Value::Str(x.to_string()))
+        }
+        Value::Nil => None,
+        other => return Err(format!("Cannot index a {}", other.type_name())),
+    };
+    Ok(out.unwrap_or(Value::Nil))
+}
+
+fn slice(value: &Value, start: f64, end: Option<f64>) -> Result<Value, String> {
+    match value {
+        Value::List(items) => {
+            let start = position(start, items.len());
+            let end = end
+                .map_or(items.len(), |x| position(x, items.len()))
+                .max(start);
+            Ok(Value::List(items[start..end].to_vec()))
+        }
+        Value::Str(text) => {
+            let length = text.chars().count();
+            let start = position(start, length);
+            let end = end.map_or(length, |x| position(x, length)).max(start);
+            Ok(Value::Str(
+                text.chars().skip(start).take(end - start).collect(),
+            ))
+        }
+        other => Err(format!("Cannot slice a {}", other.type_name())),
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Result<std::cmp::Ordering, String> {
+    match (left, right) {
+        (Value::Num(a), Value::Num(b)) => a.partial_cmp(b).ok_or("Cannot compare NaN".to_string()),
+        (Value::Str(a), Value::Str(b)) => Ok(a.cmp(b)),
+        (a, b) => Err(format!(
+            "Cannot compare a {} with a {}",
+            a.type_name(),
+            b.type_name()
+        )),
+    }
+}
+
+fn binary(operator: &str, left: Value, right: Value) -> Result<Value, String> {
+    use std::cmp::Ordering::*;
+    let out = match (operator, left, right) {
+        ("==", a, b) => Value::Bool(a == b),
+        ("!=", a, b) => Value::Bool(a != b),
+        ("<", a, b) => Value::Bool(compare(&a, &b)? == Less),
+        ("<=", a, b) => Value::Bool(compare(&a, &b)? != Greater),
+        (">", a, b) => Value::Bool(compare(&a, &b)? == Greater),
+        (">=", a, b) => Value::Bool(compare(&a, &b)? != Less),
+        ("+", Value::Num(a), Value::Num(b)) => Value::Num(a + b),
+        ("+", Value::List(mut a), Value::List(mut b)) => {
+            a.append(&mut b);
+            Value::List(a)
+        }
+        ("+", a @ Value::Str(_), b) | ("+", a, b @ Value::Str(_)) => Value::Str(format!("{a}{b}")),
+        ("-", Value::Num(a), Value::Num(b)) => Value::Num(a - b),
+        ("*", Value::Num(a), Value::Num(b)) => Value::Num(a * b),
+        ("/" | "%", Value::Num(_), Value::Num(0.0)) => return Err("Division by zero".to_string()),
+        ("/", Value::Num(a), Value::Num(b)) => Value::Num(a / b),
+        ("%", Value::Num(a), Value::Num(b)) => Value::Num(a % b),
+        (operator, a, b) => {
+            return Err(format!(
+                "Cannot apply {operator} to a {} and a {}",
+                a.type_name(),
+                b.type_name()
+            ))
+        }
+    };
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{
+        context::{Note, Span},
+        limits::Limits,
+        template::TemplateMap,
+    };
+
+    /// Runs `source` for the note `caller` of `directory`, called as `{{t|one|name=ann}}`.
+    fn run_in(directory: &Path, source: &str, limits: Limits) -> Result<String, ParseError> {
+        let mut templates = TemplateMap::new();
+        templates.set_limits(limits);
+        let note = Note {
+            path: directory.join("caller.md"),
+            front_matter: FrontMatter::split("---\nstatus: draft\n---\n").0,
+        };
+        let context = TemplateContext::new(
+            &templates,
+            directory.to_path_buf(),
+            Some(Rc::new(note)),
+            Span::default(),
+        );
+        let mut args = TemplateArgs::new();
+        args.push("one".to_string());
+        args.insert("name".to_string(), "ann".to_string());
+        run(source, &args, &context).map(|(out, _)| out)
+    }
+
+    fn limited(source: &str, limits: Limits) -> Result<String, ParseError> {
+        run_in(&std::env::temp_dir(), source, limits)
+    }
+
+    fn output(source: &str) -> String {
+        limited(source, Limits::default()).unwrap()
+    }
+
+    fn output_of(source: &str) -> Result<String, ParseError> {
+        limited(source, Limits::default())
+    }
+
+    fn fails(result: Result<String, ParseError>, comment: &str) -> ParseError {
+        match result {
+            Ok(out) => panic!("expected an error, got {out}"),
+            Err(e) => {
+                assert!(e.comment.contains(comment), "{}", e.comment);
+                e
+            }
+        }
+    }
+
+    #[test]
+    fn statements() {
+        let source = "{% let x = 1 %}\nA\n{% if x == 2 %}\ntwo\n{% elif x == 1 %}\none\n\
+                      {% else %}\nother\n{% end %}\nB\n";
+        assert_eq!(output(source), "A\none\nB\n");
+        assert_eq!(
+            output("{% if nil %}\nyes\n{% else %}\nno\n{% end %}\n"),
+            "no\n"
+        );
+        assert_eq!(output("{% if 0 %}\nyes\n{% end %}\n"), "");
+        assert_eq!(
+            output("{% for n in [1, 2, 3] %}{%= n * 2 %} {% end %}"),
+            "2 4 6 "
+        );
+        assert_eq!(output("{% for n in nil %}x{% end %}"), "");
+        fails(
+            output_of("{% for n in 3 %}x{% end %}"),
+            "Cannot loop over a number",
+        );
+    }
+
+    #[test]
+    fn let_changes_a_variable_or_makes_one_for_the_block() {
+        let source = "{% let a = 1 %}{% if true %}{% let a = 2 %}{% let b = 3 %}{% end %}{%= a %}";
+        assert_eq!(output(source), "2");
+        let source = "{% if true %}{% let b = 3 %}{% end %}{%= b %}";
+        fails(output_of(source), "Unknown variable b");
+        let source = "{% for x in [1, 2] %}{% let total = x %}{% end %}{%= total %}";
+        fails(output_of(source), "Unknown variable total");
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(output("{%= 1 + 2 * 3 %}"), "7");
+        assert_eq!(output("{%= (1 + 2) * 3 %}"), "9");
+        assert_eq!(
+            output("{%= 7 % 3 %} {%= 7 / 2 %} {%= -2 - -3 %}"),
+            "1 3.5 1"
+        );
+        assert_eq!(output("{%= \"a\" + 1 %} {%= [1] + [2, 3] %}"), "a1 1, 2, 3");
+        assert_eq!(
+            output("{%= [1, 2][-1] %}{%= \"abc\"[1] %}{%= [1][5] %}"),
+            "2b"
+        );
+        assert_eq!(output("{%= 1 < 2 and \"b\" >= \"a\" %}"), "true");
+        assert_eq!(
+            output("{%= nil or \"x\" %} {%= not 0 %} {%= 1 != 1.0 %}"),
+            "x true false"
+        );
+        assert_eq!(output("{%= \"a\\\"b\\n\" %}"), "a\"b\n");
+        fails(output_of("{%= 1 / 0 %}"), "Division by zero");
+        fails(
+            output_of("{%= 1 < \"a\" %}"),
+            "Cannot compare a number with a string",
+        );
+        fails(
+            output_of("{%= true + 1 %}"),
+            "Cannot apply + to a bool and a number",
+        );
+        fails(output_of("{%= 1[0] %}"), "Cannot index a number");
+        fails(output_of("{%= 1 $ 2 %}"), "Unexpected $");
+        fails(output_of("{%= (1 %}"), "Expected Op(\")\")");
+    }
+
+    #[test]
+    fn text_functions() {
+        assert_eq!(
+            output("{%= len(\"héllo\") %} {%= len([1, 2]) %} {%= len(nil) %}"),
+            "5 2 0"
+        );
+        assert_eq!(
+            output("{%= upper(\"a\") %}{%= lower(\"B\") %}{%= trim(\"  c \") %}"),
+            "Abc"
+        );
+        assert_eq!(output("{%= escape(\"<a & b>\") %}"), "&lt;a &amp; b&gt;");
+        assert_eq!(
+            output("{%= str(1.5) + \"!\" %} {%= num(\" 42 \") + 1 %}"),
+            "1.5! 43"
+        );
+        assert_eq!(output("{%= num(\"x\") == nil %}"), "true");
+        assert_eq!(output("{%= replace(\"a-b-c\", \"-\", \"+\") %}"), "a+b+c");
+        assert_eq!(
+            output("{%= contains([1, 2], 2) %} {%= contains(\"abc\", \"bc\") %}"),
+            "true true"
+        );
+        assert_eq!(
+            output("{%= starts_with(\"abc\", \"ab\") %} {%= ends_with(\"abc\", \"ab\") %}"),
+            "true false"
+        );
+        fails(
+            output_of("{%= replace(\"a\", \"\", \"b\") %}"),
+            "needs something to replace",
+        );
+        fails(output_of("{%= len(1) %}"), "len() does not take (number)");
+        fails(output_of("{%= shout(1) %}"), "Unknown function shout()");
+    }
+
+    #[test]
+    fn list_functions() {
+        assert_eq!(output("{%= len(split(\"a,,b\", \",\")) %}"), "3");
+        assert_eq!(output("{%= split(\"a,b\", \",\")[1] %}"), "b");
+        assert_eq!(
+            output("{%= join([\"a\", \"b\"]) %} {%= join([1, 2], \"-\") %}"),
+            "ab 1-2"
+        );
+        assert_eq!(
+            output("{%= slice(\"hello\", 1, 3) %} {%= slice([1, 2, 3], -2) %}"),
+            "el 2, 3"
+        );
+        assert_eq!(
+            output("{%= sort([3, 1, 2]) %}; {%= sort([\"b\", \"a\"]) %}"),
+            "1, 2, 3; a, b"
+        );
+        assert_eq!(
+            output("{%= reverse([1, 2]) %}; {%= range(3) %}; {%= range(2, 4) %}"),
+            "2, 1; 0, 1, 2; 2, 3"
+        );
+        fails(output_of("{%= split(\"a\", \"\") %}"), "needs a separator");
+        fails(output_of("{%= slice(1, 0) %}"), "Cannot slice a number");
+        fails(
+            output_of("{%= range(\"a\") %}"),
+            "Expected a number, found a string",
+        );
+    }
+
+    #[test]
+    fn arguments_and_the_calling_note() {
+        assert_eq!(output("{%= arg(1) %} {%= arg(\"name\") %}"), "one ann");
+        assert_eq!(output("{%= arg(\"missing\", \"d\") %}{%= arg(2) %}"), "d");
+        assert_eq!(
+            output("{%= args() %} {%= note() %} {%= meta(\"status\") %}"),
+            "one caller draft"
+        );
+    }
+
+    #[test]
+    fn vault_functions() {
+        let directory =
+            std::env::temp_dir().join(format!("confoosion-script-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("a.md"),
+            "---\nstatus: done\ntags: [x]\n---\n# Alpha\n\nSee [[b]]. #todo\n",
+        )
+        .unwrap();
+        std::fs::write(directory.join("b.md"), "# Bravo\n").unwrap();
+        let output = |source: &str| run_in(&directory, source, Limits::default()).unwrap();
+
+        assert_eq!(
+            output("{%= exists(\"a\") %} {%= exists(\"z\") %}"),
+            "true false"
+        );
+        assert_eq!(
+            output("{%= meta(\"a\", \"status\") %} {%= title(\"b\") %}"),
+            "done Bravo"
+        );
+        assert_eq!(
+            output("{%= tags(\"a\") %}; {%= tags(\"z\") %}"),
+            "todo, x; "
+        );
+        assert_eq!(
+            output("{%= notes() %}; {%= tagged(\"#todo\") %}"),
+            "a, b; a"
+        );
+        assert_eq!(
+            output("{%= backlinks(\"b\") %}; {%= links(\"a\") %}"),
+            "a; b"
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn each_limit_stops_a_script() {
+        let steps = Limits {
+            max_script_steps: 100,
+            ..Limits::default()
+        };
+        assert_eq!(limited("{%= len(range(50)) %}", steps).unwrap(), "50");
+        fails(
+            limited("{% for x in range(1000) %}{% end %}", steps),
+            "more than 100 steps",
+        );
+        let split = Limits {
+            max_script_steps: 10,
+            ..Limits::default()
+        };
+        assert_eq!(
+            limited("{%= len(split(\"a,b\", \",\")) %}", split).unwrap(),
+            "2"
+        );
+        fails(
+            limited("{%= split(\"a,b,c,d,e,f,g,h,i,j,k,l\", \",\") %}", split),
+            "more than 10 steps",
+        );
+
+        let bytes = Limits {
+            max_output_bytes: 100,
+            ..Limits::default()
+        };
+        let e = fails(
+            limited("{% for x in range(30) %}abcd{% end %}", bytes),
+            "Script produced more than 100 bytes",
+        );
+        assert_eq!(e.line(), 0);
+        fails(
+            limited("{%= str(range(60)) %}", bytes),
+            "Value larger than 100 bytes",
+        );
+        fails(
+            limited(
+                "{%= replace(\"aaaaaaaaaa\", \"a\", \"xxxxxxxxxxxxxxxxxxxx\") %}",
+                bytes,
+            ),
+            "Value larger than 100 bytes",
+        );
+        fails(
+            limited("{%= join(range(10), \"----------------\") %}", bytes),
+            "Value larger than 100 bytes",
+        );
+        assert_eq!(
+            limited("{%= join(range(3), \"-\") %}", bytes).unwrap(),
+            "0-1-2"
+        );
+
+        let time = Limits {
+            max_duration: Duration::ZERO,
+            ..Limits::default()
+        };
+        fails(
+            limited("{% for x in range(5000) %}{% end %}", time),
+            "ran out of time",
+        );
+    }
+
+    #[test]
+    fn errors_say_where_they_are() {
+        let e = fails(output_of("text\n  {%= nope %}"), "Unknown variable nope");
+        assert_eq!((e.line(), e.column()), (2, 3));
+        let e = fails(
+            output_of("{% if true %}\nx\n{% else 1 %}\n{% end %}\n"),
+            "takes no condition",
+        );
+        assert_eq!((e.line(), e.column()), (3, 1));
+        let e = fails(output_of("a {% if"), "Unclosed {% in script");
+        assert_eq!((e.line(), e.column()), (1, 3));
+        let e = fails(output_of("\n{% for x in [] %}\n"), "missing its {% end %}");
+        assert_eq!((e.line(), e.column()), (2, 1));
+        fails(output_of("{% end %}"), "without an opening");
+        fails(output_of("{% elif true %}"), "without an opening");
+        fails(output_of("{% if true %}{% end x %}"), "takes nothing else");
+        fails(output_of("{% %}"), "Empty {% %} statement");
+        fails(output_of("{% print 1 %}"), "Unknown statement {% print %}");
+        fails(
+            output_of("{% let if = 1 %}"),
+            "Expected a name, found Ident(\"if\")",
+        );
+        fails(output_of("{%= \"open %}"), "Unclosed {% in script");
+    }
+}
@@ -1,47 +1,387 @@
-use std::{cell::RefCell, collections::HashMap, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
 
-use crate::{error::ParseError, putback::PutBackChars, ExitMode, ParsedHTML};
+use crate::{
+    arguments::TemplateArgs,
+    context::{Note, Span, TemplateContext},
+    dependencies::Dependencies,
+    error::ParseError,
+    limits::{Limits, Usage},
+    links::{LinkGenerator, RelativeHtmlLinks},
+    notetemplate::{substitute, NoteTemplate},
+    putback::PutBackChars,
+    resolver::{DirectoryResolver, LinkResolver},
+    script,
+    title::TitleCache,
+    vault::VaultQuery,
+    ExitMode, ParsedHTML,
+};
 
 pub type Template =
-    dyn Fn(Vec<String>, &TemplateMap, PathBuf) -> Result<(ParsedHTML, ExitMode), ParseError>;
+    dyn Fn(TemplateArgs, &TemplateContext) -> Result<(ParsedHTML, ExitMode), ParseError>;
+
+/// A template call as far as a pure template can tell: where it is called from and with what.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ExpansionKey {
+    name: String,
+    directory: PathBuf,
+    positional: Vec<String>,
+    named: Vec<(String, String)>,
+}
+
+impl ExpansionKey {
+    fn new(name: &str, directory: &Path, args: &TemplateArgs) -> Self {
+        Self {
+            name: name.to_string(),
+            directory: directory.to_path_buf(),
+            positional: args
+                .positional()
+                .iter()
+                .map(|x| x.raw().to_string())
+                .collect(),
+            named: args
+                .named()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+}
+
+struct Expansion {
+    /// The files the output was built from, such as template notes and the notes its links
+    /// point at, with when they were modified, or `None` for those that did not exist.
+    sources: Vec<(PathBuf, Option<SystemTime>)>,
+    output: (ParsedHTML, ExitMode),
+}
+
 pub struct TemplateMap {
     pub map: HashMap<String, Box<Template>>,
-    recursion_depth: RefCell<u16>,
+    pure: HashSet<String>,
+    expansions: RefCell<HashMap<ExpansionKey, Expansion>>,
+    limits: Limits,
+    usage: RefCell<Usage>,
+    call_path: RefCell<Vec<String>>,
+    resolver: Box<dyn LinkResolver>,
+    link_generator: Box<dyn LinkGenerator>,
+    title_cache: Rc<TitleCache>,
+    template_prefix: String,
+    note_templates: RefCell<HashMap<PathBuf, (SystemTime, Rc<NoteTemplate>)>>,
+    notes: RefCell<Vec<Rc<Note>>>,
+    vault: Option<Rc<dyn VaultQuery>>,
 }
 
 impl TemplateMap {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
-            recursion_depth: RefCell::new(0),
+            pure: HashSet::new(),
+            expansions: RefCell::new(HashMap::new()),
+            limits: Limits::default(),
+            usage: RefCell::new(Usage::new()),
+            call_path: RefCell::new(Vec::new()),
+            resolver: Box::new(DirectoryResolver),
+            link_generator: Box::new(RelativeHtmlLinks),
+            title_cache: Rc::new(TitleCache::new()),
+            template_prefix: "Template:".to_string(),
+            note_templates: RefCell::new(HashMap::new()),
+            notes: RefCell::new(Vec::new()),
+            vault: None,
         }
     }
+    /// A map with the standard templates of [`crate::builtins`] already registered.
+    pub fn with_builtins() -> Self {
+        let mut out = Self::new();
+        crate::builtins::register(&mut out);
+        out
+    }
     pub fn insert(&mut self, name: String, function: Box<Template>) -> bool {
+        self.pure.remove(&name);
+        self.forget_expansions(&name);
         self.map.insert(name, function).is_none()
     }
+    /// Registers a template whose output depends only on its arguments and the directory it is
+    /// called from. Its expansions are cached and reused across renders, until one of the files
+    /// in their [`Dependencies`] changes.
+    pub fn insert_pure(&mut self, name: String, function: Box<Template>) -> bool {
+        let new = self.insert(name.clone(), function);
+        self.pure.insert(name);
+        new
+    }
+    pub fn is_pure(&self, name: &str) -> bool {
+        self.pure.contains(name)
+    }
+    /// Drops every cached expansion, for when something a pure template reads has changed.
+    pub fn clear_expansions(&self) {
+        self.expansions.borrow_mut().clear();
+    }
+    fn forget_expansions(&self, name: &str) {
+        self.expansions
+            .borrow_mut()
+            .retain(|key, _| key.name != name);
+    }
+    pub fn resolver(&self) -> &dyn LinkResolver {
+        self.resolver.as_ref()
+    }
+    pub fn set_resolver(&mut self, resolver: Box<dyn LinkResolver>) {
+        self.resolver = resolver;
+    }
+    pub fn link_generator(&self) -> &dyn LinkGenerator {
+        self.link_generator.as_ref()
+    }
+    pub fn set_link_generator(&mut self, link_generator: Box<dyn LinkGenerator>) {
+        self.link_generator = link_generator;
+    }
+    pub fn title_cache(&self) -> &TitleCache {
+        &self.title_cache
+    }
+    pub fn shared_title_cache(&self) -> Rc<TitleCache> {
+        self.title_cache.clone()
+    }
+    pub fn set_title_cache(&mut self, title_cache: Rc<TitleCache>) {
+        self.title_cache = title_cache;
+    }
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+    /// What the current document has used of its [`Limits`].
+    pub fn usage(&self) -> Usage {
+        *self.usage.borrow()
+    }
+    /// Starts a new budget, as happens at the start of every document.
+    pub fn reset_usage(&self) {
+        *self.usage.borrow_mut() = Usage::new();
+    }
+    /// The names of the templates being expanded, outermost first.
+    pub fn call_path(&self) -> Vec<String> {
+        self.call_path.borrow().clone()
+    }
+    pub fn vault(&self) -> Option<&dyn VaultQuery> {
+        self.vault.as_deref()
+    }
+    /// Answers the vault queries of templates from an index. Without one, templates only see
+    /// the notes in the current directory.
+    pub fn set_vault(&mut self, vault: Rc<dyn VaultQuery>) {
+        self.vault = Some(vault);
+    }
+    /// Where template notes live, relative to the calling note's directory. With the default
+    /// prefix `Template:`, `{{infobox}}` is looked up in `Template:infobox.md`.
+    pub fn template_prefix(&self) -> &str {
+        &self.template_prefix
+    }
+    pub fn set_template_prefix(&mut self, prefix: String) {
+        self.template_prefix = prefix;
+    }
+    pub fn note_template_path<P: AsRef<Path>>(&self, name: &str, dir: P) -> PathBuf {
+        dir.as_ref()
+            .join(format!("{}{}.md", self.template_prefix, name.trim()))
+    }
+    /// Loads the template note for `name`, reusing the previous load if the note is unchanged.
+    pub fn note_template<P: AsRef<Path>>(
+        &self,
+        name: &str,
+        dir: P,
+    ) -> Option<Result<Rc<NoteTemplate>, ParseError>> {
+        let path = self.note_template_path(name, dir);
+        let modified = match path.metadata().and_then(|x| x.modified()) {
+            Ok(x) => x,
+            Err(_) => {
+                self.note_templates.borrow_mut().remove(&path);
+                return None;
+            }
+        };
+        if let Some((loaded_at, template)) = self.note_templates.borrow().get(&path) {
+            if *loaded_at == modified {
+                return Some(Ok(template.clone()));
+            }
+        }
+        let template = match NoteTemplate::read(&path) {
+            Ok(x) => Rc::new(x),
+            Err(e) => return Some(Err(e)),
+        };
+        self.note_templates
+            .borrow_mut()
+            .insert(path, (modified, template.clone()));
+        Some(Ok(template))
+    }
     pub fn call(
         &self,
         name: String,
-        args: Vec<String>,
+        args: TemplateArgs,
         dir: PathBuf,
+        span: Span,
     ) -> Result<(ParsedHTML, ExitMode), ParseError> {
-        if *self.recursion_depth.borrow() >= Self::max_recursion_depth() {
-            return Err(ParseError::empty(
-                "Maximum template recursion depth exceeded.",
-            ));
+        if self.call_path.borrow().is_empty() && self.notes.borrow().is_empty() {
+            self.reset_usage();
         }
-        *self.recursion_depth.borrow_mut() += 1;
-        let out = match self.map.get(&name) {
-            Some(callback) => callback(args, self, dir),
-            None => Err(ParseError::empty(
-                format!("Template {{{{{}}}}} not found", name).as_str(),
-            )),
-        };
-        *self.recursion_depth.borrow_mut() -= 1;
+        self.call_path.borrow_mut().push(name.trim().to_string());
+        self.usage.borrow_mut().invocations += 1;
+        let out = self
+            .check_limits()
+            .and_then(|_| self.expand(name.clone(), args, dir, span));
+        let out = out.and_then(|(mut parsed, exit)| {
+            parsed
+                .dependencies
+                .templates
+                .insert(name.trim().to_string());
+            self.usage.borrow_mut().output_bytes += parsed.html.len();
+            self.check_limits()?;
+            Ok((parsed, exit))
+        });
+        self.call_path.borrow_mut().pop();
         out
     }
-    pub fn max_recursion_depth() -> u16 {
-        128
+    fn check_limits(&self) -> Result<(), ParseError> {
+        let depth = self.call_path.borrow().len();
+        match self.usage.borrow().exceeded(&self.limits, depth) {
+            Some(reason) => Err(ParseError::empty(
+                format!(
+                    "Template limit exceeded, {reason}, in {}",
+                    self.call_path.borrow().join(" → ")
+                )
+                .as_str(),
+            )),
+            None => Ok(()),
+        }
+    }
+    fn expand(
+        &self,
+        name: String,
+        args: TemplateArgs,
+        dir: PathBuf,
+        span: Span,
+    ) -> Result<(ParsedHTML, ExitMode), ParseError> {
+        let context = TemplateContext::new(self, dir, self.current_note(), span);
+        match self.map.get(&name) {
+            Some(callback) if self.pure.contains(&name) => {
+                let key = ExpansionKey::new(&name, &context.directory, &args);
+                self.memoized(key, || callback(args, &context))
+            }
+            Some(callback) => callback(args, &context),
+            None => match self.note_template(&name, &context.directory) {
+                Some(Ok(template)) if template.is_pure() => {
+                    let key = ExpansionKey::new(&name, &context.directory, &args);
+                    self.memoized(key, || {
+                        self.expand_note_template(&template, &args, &context)
+                    })
+                }
+                Some(Ok(template)) => self.expand_note_template(&template, &args, &context),
+                Some(Err(e)) => Err(e),
+                None => Err(ParseError::empty(
+                    format!("Template {{{{{}}}}} not found", name).as_str(),
+                )),
+            },
+        }
+    }
+    /// Reuses the output cached under `key`, unless a file it was built from changed since.
+    /// Errors are not cached.
+    fn memoized(
+        &self,
+        key: ExpansionKey,
+        expand: impl FnOnce() -> Result<(ParsedHTML, ExitMode), ParseError>,
+    ) -> Result<(ParsedHTML, ExitMode), ParseError> {
+        if let Some(expansion) = self.expansions.borrow().get(&key) {
+            let current = expansion
+                .sources
+                .iter()
+                .all(|(file, modified)| last_modified(file) == *modified);
+            if current {
+                return Ok(expansion.output.clone());
+            }
+        }
+        let output = expand()?;
+        let (parsed, _) = &output;
+        // Neither output about the whole vault nor links that may resolve to any note created
+        // later can be checked file by file, so those are expanded every time.
+        if !parsed.dependencies.vault && parsed.broken_links.is_empty() {
+            let sources = parsed
+                .dependencies
+                .files()
+                .into_iter()
+                .map(|file| (file.to_path_buf(), last_modified(file)))
+                .collect();
+            self.expansions.borrow_mut().insert(
+                key,
+                Expansion {
+                    sources,
+                    output: output.clone(),
+                },
+            );
+        }
+        Ok(output)
+    }
+    /// Forgets the templates and notes that were being rendered when a render was abandoned
+    /// halfway, such as by a panic, so that the next render starts afresh.
+    pub fn abandon_render(&self) {
+        self.call_path.borrow_mut().clear();
+        self.notes.borrow_mut().clear();
+        self.reset_usage();
+    }
+    /// The note currently being rendered, if the render started from a file.
+    pub fn current_note(&self) -> Option<Rc<Note>> {
+        self.notes.borrow().last().cloned()
+    }
+    /// Whether `note` is already being rendered further up, which would make including it loop.
+    pub fn is_rendering<P: AsRef<Path>>(&self, note: P) -> bool {
+        self.notes.borrow().iter().any(|x| x.path == note.as_ref())
+    }
+    pub(crate) fn enter_note(&self, note: Note) {
+        if self.notes.borrow().is_empty() {
+            self.reset_usage();
+        }
+        self.notes.borrow_mut().push(Rc::new(note));
+    }
+    pub(crate) fn leave_note(&self) {
+        self.notes.borrow_mut().pop();
+    }
+    fn expand_note_template(
+        &self,
+        template: &NoteTemplate,
+        args: &TemplateArgs,
+        context: &TemplateContext,
+    ) -> Result<(ParsedHTML, ExitMode), ParseError> {
+        // Positions inside the template note mean nothing in the calling note, so report the
+        // call site and say where in the template things went wrong.
+        let in_template = |e: ParseError| {
+            ParseError::at(
+                context.span.line,
+                context.span.column,
+                format!(
+                    "{}:{}:{}: {}",
+                    template.path.display(),
+                    e.line(),
+                    e.column(),
+                    e.comment
+                ),
+            )
+        };
+        let mut dependencies = Dependencies::new();
+        let source = if template.is_script() {
+            let (source, script_dependencies) = script::run(&template.source, args, context)
+                .map_err(|e| match e.line() {
+                    0 => e,
+                    line => ParseError::at(line + template.body_line - 1, e.column(), e.comment),
+                })
+                .map_err(in_template)?;
+            dependencies = script_dependencies;
+            substitute(&source, args)?
+        } else {
+            template.substitute(args)?
+        };
+        let (mut parsed, exit) = context.render(&source).map_err(in_template)?;
+        parsed.dependencies.merge(dependencies);
+        parsed
+            .dependencies
+            .template_notes
+            .insert(template.path.clone());
+        Ok((parsed, exit))
     }
 }
 
@@ -51,7 +391,13 @@ impl Default for TemplateMap {
     }
 }
 
-pub fn read_template_argument(chars: &mut PutBackChars) -> (String, ExitMode) {
+fn last_modified(file: &Path) -> Option<SystemTime> {
+    file.metadata().and_then(|x| x.modified()).ok()
+}
+
+/// Reads one argument of a template or wiki-link, up to the `|`, `}}` or `]]` that ends it. A
+/// lone `}` or `]`, or an escape at the end of the file, is an error.
+pub fn read_template_argument(chars: &mut PutBackChars) -> Result<(String, ExitMode), ParseError> {
     enum NestKind {
         WikiLink,
         Template,
@@ -60,16 +406,31 @@ pub fn read_template_argument(chars: &mut PutBackChars) -> (String, ExitMode) {
     let mut stack: Vec<NestKind> = Vec::new();
 
     while let Some(character) = chars.next() {
+        if character == '<' && chars.skip_if("nowiki>") {
+            arg.push_str("<nowiki>");
+            while !chars.skip_if("</nowiki>") {
+                match chars.next() {
+                    Some(character) => arg.push(character),
+                    None => return Ok((arg, ExitMode::EndOfFile)),
+                }
+            }
+            arg.push_str("</nowiki>");
+            continue;
+        }
         if character == '\\' {
             arg.push(character);
-            arg.push(
-                chars
-                    .next()
-                    .expect("Stray escape character and EndOfFile inside template or wiki-link."),
-            );
+            match chars.next() {
+                Some(character) => arg.push(character),
+                None => {
+                    return Err(ParseError::from_str(
+                        chars,
+                        "Stray escape character and end of file inside template or wiki-link",
+                    ))
+                }
+            }
             continue;
         }
-        let _ = match stack.last() {
+        match stack.last() {
             Some(NestKind::Template) => {
                 if character == '}' {
                     if let Some(character) = chars.next() {
@@ -79,12 +440,8 @@ pub fn read_template_argument(chars: &mut PutBackChars) -> (String, ExitMode) {
                             continue;
                         } else {
                             chars.putback(character);
-                            chars.putback('}');
                         }
-                    } else {
-                        chars.putback('}');
                     }
-                } else {
                 }
             }
             Some(NestKind::WikiLink) => {
@@ -96,35 +453,29 @@ pub fn read_template_argument(chars: &mut PutBackChars) -> (String, ExitMode) {
                             continue;
                         } else {
                             chars.putback(character);
-                            chars.putback(']');
                         }
-                    } else {
-                        chars.putback(']');
                     }
-                } else {
                 }
             }
             None => match character {
-                '|' => return (arg, ExitMode::EndOfArgument),
+                '|' => return Ok((arg, ExitMode::EndOfArgument)),
                 ']' => {
-                    assert_eq!(
-                        chars.next(),
-                        Some(']'),
-                        "Lone “]” inside link or template at {}:{}",
-                        chars.line_number,
-                        chars.column_number
-                    );
-                    return (arg, ExitMode::EndOfLink);
+                    if chars.next() != Some(']') {
+                        return Err(ParseError::from_str(
+                            chars,
+                            "Lone “]” inside link or template",
+                        ));
+                    }
+                    return Ok((arg, ExitMode::EndOfLink));
                 }
                 '}' => {
-                    assert_eq!(
-                        chars.next(),
-                        Some('}'),
-                        "Lone “}}” inside link or template at {}:{}",
-                        chars.line_number,
-                        chars.column_number
-                    );
-                    return (arg, ExitMode::EndOfTemplate);
+                    if chars.next() != Some('}') {
+                        return Err(ParseError::from_str(
+                            chars,
+                            "Lone “}” inside link or template",
+                        ));
+                    }
+                    return Ok((arg, ExitMode::EndOfTemplate));
                 }
                 _ => (),
             },
@@ -156,5 +507,179 @@ pub fn read_template_argument(chars: &mut PutBackChars) -> (String, ExitMode) {
             other => arg.push(other),
         }
     }
-    (arg, ExitMode::EndOfFile)
+    Ok((arg, ExitMode::EndOfFile))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::*;
+    use crate::markdown_to_html;
+
+    fn vault(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("confoosion-template-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Writes `file` with a modification time that is sure to differ from the one before.
+    fn write(file: &Path, contents: &str, age: u64) {
+        fs::write(file, contents).unwrap();
+        let time = SystemTime::now() - Duration::from_secs(age);
+        fs::File::options()
+            .write(true)
+            .open(file)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    fn render(directory: &Path, source: &str, templates: &TemplateMap) -> String {
+        markdown_to_html(source, directory.join("note.md"), templates)
+            .unwrap()
+            .html
+    }
+
+    #[test]
+    fn pure_expansions_are_reused() {
+        let directory = vault("reused");
+        write(
+            &directory.join("Template:stamp.md"),
+            "---\npure: true\n---\nstamped {{{1}}}",
+            10,
+        );
+        let templates = TemplateMap::new();
+        assert!(render(&directory, "{{stamp|a}}", &templates).contains("stamped a"));
+        assert_eq!(templates.expansions.borrow().len(), 1);
+        assert!(render(&directory, "{{stamp|a}}", &templates).contains("stamped a"));
+        assert!(render(&directory, "{{stamp|b}}", &templates).contains("stamped b"));
+        assert_eq!(templates.expansions.borrow().len(), 2);
+    }
+
+    #[test]
+    fn pure_expansions_notice_nested_templates_changing() {
+        let directory = vault("nested");
+        write(
+            &directory.join("Template:outer.md"),
+            "---\npure: true\n---\n<{{inner}}>",
+            10,
+        );
+        write(&directory.join("Template:inner.md"), "one", 10);
+        let templates = TemplateMap::new();
+        assert!(render(&directory, "{{outer}}", &templates).contains("one"));
+        write(&directory.join("Template:inner.md"), "two", 5);
+        assert!(render(&directory, "{{outer}}", &templates).contains("two"));
+    }
+
+    #[test]
+    fn pure_expansions_notice_link_targets_appearing() {
+        let directory = vault("links");
+        write(
+            &directory.join("Template:see.md"),
+            "---\npure: true\n---\nSee [[{{{1}}}]]",
+            10,
+        );
+        write(&directory.join("old.md"), "# Old\n", 10);
+        let templates = TemplateMap::new();
+        let before = render(&directory, "{{see|new}}", &templates);
+        write(&directory.join("new.md"), "# New\n", 5);
+        let after = render(&directory, "{{see|new}}", &templates);
+        assert_ne!(before, after);
+        assert!(after.contains("New"), "{after}");
+
+        assert!(render(&directory, "{{see|old}}", &templates).contains("Old"));
+        write(&directory.join("old.md"), "# Older\n", 5);
+        assert!(render(&directory, "{{see|old}}", &templates).contains("Older"));
+    }
+
+    /// Renders `source` with `limits`, which it is expected to exceed, and returns the last line
+    /// of the error, below those naming the templates it passed through.
+    fn exceeding(directory: &Path, source: &str, limits: Limits) -> String {
+        let mut templates = TemplateMap::new();
+        templates.set_limits(limits);
+        templates.insert(
+            "slow".to_string(),
+            Box::new(|_, _| {
+                std::thread::sleep(Duration::from_millis(20));
+                Ok((ParsedHTML::new(), ExitMode::EndOfFile))
+            }),
+        );
+        let error = markdown_to_html(source, directory.join("note.md"), &templates).unwrap_err();
+        error.comment.lines().last().unwrap().to_string()
+    }
+
+    #[test]
+    fn templates_may_only_nest_so_deep() {
+        let directory = vault("depth");
+        write(&directory.join("Template:ping.md"), "{{pong}}", 10);
+        write(&directory.join("Template:pong.md"), "{{ping}}", 10);
+        let limits = Limits {
+            max_recursion_depth: 3,
+            ..Limits::default()
+        };
+        let error = exceeding(&directory, "{{ping}}", limits);
+        assert_eq!(
+            error,
+            "Template limit exceeded, templates nested more than 3 deep, in ping → pong → ping → pong"
+        );
+    }
+
+    #[test]
+    fn templates_may_only_produce_so_much() {
+        let directory = vault("output");
+        write(&directory.join("Template:big.md"), &"x".repeat(100), 10);
+        let limits = Limits {
+            max_output_bytes: 50,
+            ..Limits::default()
+        };
+        let error = exceeding(&directory, "{{big}}", limits);
+        assert_eq!(
+            error,
+            "Template limit exceeded, templates produced more than 50 bytes, in big"
+        );
+        let limits = Limits {
+            max_output_bytes: 200,
+            ..Limits::default()
+        };
+        let mut templates = TemplateMap::new();
+        templates.set_limits(limits);
+        assert!(render(&directory, "{{big}}", &templates).contains(&"x".repeat(100)));
+    }
+
+    #[test]
+    fn templates_may_only_be_called_so_often() {
+        let directory = vault("invocations");
+        write(
+            &directory.join("Template:many.md"),
+            "{{one}}{{one}}{{one}}",
+            10,
+        );
+        write(&directory.join("Template:one.md"), "1", 10);
+        let limits = Limits {
+            max_invocations: 2,
+            ..Limits::default()
+        };
+        let error = exceeding(&directory, "{{many}}", limits);
+        assert_eq!(
+            error,
+            "Template limit exceeded, more than 2 template calls, in many → one"
+        );
+    }
+
+    #[test]
+    fn templates_may_only_take_so_long() {
+        let directory = vault("duration");
+        let limits = Limits {
+            max_duration: Duration::from_millis(5),
+            ..Limits::default()
+        };
+        let error = exceeding(&directory, "{{slow}}", limits);
+        assert_eq!(
+            error,
+            "Template limit exceeded, templates took longer than 5ms, in slow"
+        );
+    }
 }
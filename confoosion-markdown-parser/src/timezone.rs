@@ -0,0 +1,335 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::date::{days_in_month, is_leap_year, Date};
+
+/// Where compiled time zones are installed, for `TZ` values like `Europe/Berlin`.
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+/// How far local time is ahead of UTC, in seconds, at `time` (seconds since 1970-01-01 UTC).
+///
+/// The time zone is found the way the C library finds it: `TZ` names a compiled time zone or
+/// is a rule like `CET-1CEST,M3.5.0,M10.5.0/3`, and `/etc/localtime` is used when it is unset.
+/// A time zone that cannot be read counts as UTC.
+pub fn local_offset(time: i64) -> i64 {
+    let zone = match env::var("TZ") {
+        Ok(x) if !x.is_empty() => x,
+        _ => return compiled_offset(Path::new("/etc/localtime"), time).unwrap_or(0),
+    };
+    let name = zone.strip_prefix(':').unwrap_or(&zone);
+    let path = match name.starts_with('/') {
+        true => PathBuf::from(name),
+        false => Path::new(ZONEINFO).join(name),
+    };
+    compiled_offset(&path, time)
+        .or_else(|| Some(Rule::parse(name)?.offset(time)))
+        .unwrap_or(0)
+}
+
+fn compiled_offset(path: &Path, time: i64) -> Option<i64> {
+    tzif_offset(&fs::read(path).ok()?, time)
+}
+
+/// Reads the offset at `time` from a compiled time zone, a TZif file as described in RFC 8536.
+fn tzif_offset(data: &[u8], time: i64) -> Option<i64> {
+    let mut data = data;
+    let mut counts = tzif_counts(data)?;
+    let mut time_size = 4;
+    if data[4] >= b'2' {
+        // Skip the 32-bit block for the one with 64-bit times that follows it.
+        let [isut, isstd, leap, times, types, chars] = counts;
+        let skip = 44 + times * 5 + types * 6 + chars + leap * 8 + isstd + isut;
+        data = data.get(skip..)?;
+        counts = tzif_counts(data)?;
+        time_size = 8;
+    }
+    let [isut, isstd, leap, times, types, chars] = counts;
+    let transitions = data.get(44..44 + times * time_size)?;
+    let transition_types = data.get(44 + times * time_size..44 + times * (time_size + 1))?;
+    let infos = 44 + times * (time_size + 1);
+    let offset_of = |index: usize| {
+        let info = data.get(infos + index * 6..infos + index * 6 + 4)?;
+        Some(i32::from_be_bytes(info.try_into().ok()?) as i64)
+    };
+    let transition = |index: usize| {
+        let bytes = &transitions[index * time_size..(index + 1) * time_size];
+        match time_size {
+            8 => i64::from_be_bytes(bytes.try_into().unwrap()),
+            _ => i32::from_be_bytes(bytes.try_into().unwrap()) as i64,
+        }
+    };
+    let passed = (0..times).take_while(|x| transition(*x) <= time).count();
+    if passed == times && time_size == 8 {
+        // After the last transition, the rule at the end of the file applies, if there is one.
+        let footer = infos + types * 6 + chars + leap * 12 + isstd + isut;
+        let footer = std::str::from_utf8(data.get(footer..)?).ok()?;
+        if let Some(rule) = footer
+            .trim_matches('\n')
+            .lines()
+            .next()
+            .and_then(Rule::parse)
+        {
+            return Some(rule.offset(time));
+        }
+    }
+    match passed {
+        0 => offset_of(0),
+        x => offset_of(*transition_types.get(x - 1)? as usize),
+    }
+}
+
+/// The counts in a TZif header, in the order they are stored.
+fn tzif_counts(data: &[u8]) -> Option<[usize; 6]> {
+    if data.get(..4)? != b"TZif" {
+        return None;
+    }
+    let mut counts = [0; 6];
+    for (index, count) in counts.iter_mut().enumerate() {
+        let bytes = data.get(20 + index * 4..24 + index * 4)?;
+        *count = u32::from_be_bytes(bytes.try_into().ok()?) as usize;
+    }
+    Some(counts)
+}
+
+/// A POSIX time zone rule: a standard offset and, for time zones with daylight saving time, the
+/// daylight offset and when it starts and ends. Offsets are in seconds ahead of UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    standard: i64,
+    daylight: Option<(i64, Change, Change)>,
+}
+
+/// When daylight saving time starts or ends: a day of the year and the local time on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Change {
+    day: Day,
+    time: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Day {
+    /// `Jn`: day 1 to 365, never counting 29 February.
+    Julian(i64),
+    /// `n`: day 0 to 365, counting 29 February.
+    Ordinal(i64),
+    /// `Mm.w.d`: weekday `d` (0 is Sunday) of week `w` of month `m`, where week 5 is the last.
+    Month(u32, u32, u32),
+}
+
+impl Rule {
+    fn parse(text: &str) -> Option<Self> {
+        let mut rest = text;
+        take_zone_name(&mut rest)?;
+        let standard = -take_offset(&mut rest)?;
+        if rest.is_empty() {
+            return Some(Self {
+                standard,
+                daylight: None,
+            });
+        }
+        take_zone_name(&mut rest)?;
+        let daylight = match rest.starts_with(',') || rest.is_empty() {
+            true => standard + 3_600,
+            false => -take_offset(&mut rest)?,
+        };
+        // Without a rule, daylight saving time follows the United States.
+        let rest = match rest {
+            "" => ",M3.2.0,M11.1.0",
+            x => x,
+        };
+        let mut changes = rest.strip_prefix(',')?.split(',');
+        let start = Change::parse(changes.next()?)?;
+        let end = Change::parse(changes.next()?)?;
+        match changes.next() {
+            None => Some(Self {
+                standard,
+                daylight: Some((daylight, start, end)),
+            }),
+            Some(_) => None,
+        }
+    }
+
+    fn offset(self, time: i64) -> i64 {
+        let (daylight, start, end) = match self.daylight {
+            Some(x) => x,
+            None => return self.standard,
+        };
+        let year = Date::from_days((time + self.standard).div_euclid(86_400)).year;
+        // Daylight saving time starts by standard time and ends by daylight saving time.
+        let start = start.at(year) - self.standard;
+        let end = end.at(year) - daylight;
+        let in_daylight = match start <= end {
+            true => start <= time && time < end,
+            // South of the equator, daylight saving time spans the turn of the year.
+            false => !(end <= time && time < start),
+        };
+        match in_daylight {
+            true => daylight,
+            false => self.standard,
+        }
+    }
+}
+
+impl Change {
+    fn parse(text: &str) -> Option<Self> {
+        let (day, time) = match text.split_once('/') {
+            Some((day, time)) => (day, Some(time)),
+            None => (text, None),
+        };
+        let day = if let Some(month) = day.strip_prefix('M') {
+            let mut parts = month.split('.').map(|x| x.parse::<u32>().ok());
+            let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+            let valid = (1..=12).contains(&month) && (1..=5).contains(&week) && weekday <= 6;
+            match valid && parts.next().is_none() {
+                true => Day::Month(month, week, weekday),
+                false => return None,
+            }
+        } else if let Some(day) = day.strip_prefix('J') {
+            Day::Julian(day.parse().ok().filter(|x| (1..=365).contains(x))?)
+        } else {
+            Day::Ordinal(day.parse().ok().filter(|x| (0..=365).contains(x))?)
+        };
+        let time = match time {
+            Some(mut time) => {
+                let seconds = take_offset(&mut time)?;
+                match time.is_empty() {
+                    true => seconds,
+                    false => return None,
+                }
+            }
+            None => 7_200,
+        };
+        Some(Self { day, time })
+    }
+
+    /// The change in `year`, in seconds since 1970-01-01 as if the local time were UTC.
+    fn at(self, year: i32) -> i64 {
+        let new_year = Date::new(year, 1, 1).unwrap().days();
+        let day = match self.day {
+            Day::Julian(day) => new_year + day - 1 + (is_leap_year(year) && day >= 60) as i64,
+            Day::Ordinal(day) => new_year + day,
+            Day::Month(month, week, weekday) => {
+                let first = Date::new(year, month, 1).unwrap();
+                // `Date::weekday` starts on Monday, POSIX rules on Sunday.
+                let first_weekday = (first.weekday() + 1) % 7;
+                let mut day = 1 + (weekday + 7 - first_weekday) % 7 + (week - 1) * 7;
+                while day > days_in_month(year, month) {
+                    day -= 7;
+                }
+                first.days() + day as i64 - 1
+            }
+        };
+        day * 86_400 + self.time
+    }
+}
+
+/// Takes a zone abbreviation, like `CET` or `<+03>`, from the start of `text`.
+fn take_zone_name(text: &mut &str) -> Option<()> {
+    let length = match text.strip_prefix('<') {
+        Some(quoted) => quoted.find('>')? + 2,
+        None => text
+            .find(|x: char| !x.is_ascii_alphabetic())
+            .unwrap_or(text.len()),
+    };
+    if length < 3 {
+        return None;
+    }
+    *text = &text[length..];
+    Some(())
+}
+
+/// Takes `[+-]hh[:mm[:ss]]` from the start of `text`, in seconds. POSIX offsets count west of
+/// UTC, so `CET-1` is an hour ahead of UTC.
+fn take_offset(text: &mut &str) -> Option<i64> {
+    let (sign, rest) = match text.as_bytes().first()? {
+        b'-' => (-1, &text[1..]),
+        b'+' => (1, &text[1..]),
+        _ => (1, *text),
+    };
+    let length = rest
+        .find(|x: char| !(x.is_ascii_digit() || x == ':'))
+        .unwrap_or(rest.len());
+    let mut seconds = 0;
+    let mut parts = 0;
+    for (part, scale) in rest[..length].split(':').zip([3_600, 60, 1]) {
+        if part.is_empty() || part.len() > 3 {
+            return None;
+        }
+        seconds += part.parse::<i64>().ok()? * scale;
+        parts += 1;
+    }
+    if parts != rest[..length].split(':').count() {
+        return None;
+    }
+    *text = &rest[length..];
+    Some(sign * seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str, hour: i64) -> i64 {
+        Date::parse(date).unwrap().days() * 86_400 + hour * 3_600
+    }
+
+    #[test]
+    fn rules_switch_to_daylight_saving_time() {
+        let berlin = Rule::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        assert_eq!(berlin.offset(at("2024-01-15", 12)), 3_600);
+        assert_eq!(berlin.offset(at("2024-07-15", 12)), 7_200);
+        // 31 March 2024, 01:00 UTC is 03:00 in Berlin, after the change.
+        assert_eq!(berlin.offset(at("2024-03-31", 1) - 1), 3_600);
+        assert_eq!(berlin.offset(at("2024-03-31", 1)), 7_200);
+        assert_eq!(berlin.offset(at("2024-10-27", 1) - 1), 7_200);
+        assert_eq!(berlin.offset(at("2024-10-27", 1)), 3_600);
+
+        let sydney = Rule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.offset(at("2024-01-15", 12)), 39_600);
+        assert_eq!(sydney.offset(at("2024-07-15", 12)), 36_000);
+
+        let new_york = Rule::parse("EST5EDT").unwrap();
+        assert_eq!(new_york.offset(at("2024-01-15", 12)), -18_000);
+        assert_eq!(new_york.offset(at("2024-07-15", 12)), -14_400);
+    }
+
+    #[test]
+    fn rules_without_daylight_saving_time() {
+        assert_eq!(Rule::parse("UTC0").unwrap().offset(0), 0);
+        assert_eq!(Rule::parse("<+0530>-5:30").unwrap().offset(0), 19_800);
+        assert_eq!(Rule::parse("<-03>3").unwrap().offset(0), -10_800);
+        assert_eq!(Rule::parse("Europe/Nowhere"), None);
+        assert_eq!(Rule::parse("CET-1CEST,M3.5.0"), None);
+    }
+
+    #[test]
+    fn compiled_time_zones_are_read() {
+        // A version 2 file with one transition to a type three hours ahead and a rule after it.
+        let block = |data: &mut Vec<u8>, time: &[u8]| {
+            data.extend(b"TZif2");
+            data.extend([0; 15]);
+            for count in [0u32, 0, 0, 1, 2, 4] {
+                data.extend(count.to_be_bytes());
+            }
+            data.extend(time);
+            data.push(1);
+            data.extend(0i32.to_be_bytes());
+            data.extend([0, 0]);
+            data.extend(10_800i32.to_be_bytes());
+            data.extend([0, 0]);
+            data.extend(b"UTC\0");
+        };
+        let mut data = Vec::new();
+        block(&mut data, &1_000i32.to_be_bytes());
+        block(&mut data, &1_000i64.to_be_bytes());
+        data.extend(b"\n<+04>-4\n");
+        assert_eq!(tzif_offset(&data, 999), Some(0));
+        assert_eq!(tzif_offset(&data, 1_000), Some(14_400));
+
+        let without_rule = data.len() - b"<+04>-4\n".len();
+        assert_eq!(tzif_offset(&data[..without_rule], 1_000), Some(10_800));
+        assert_eq!(tzif_offset(b"not a time zone", 0), None);
+    }
+}
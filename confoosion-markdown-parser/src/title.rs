@@ -0,0 +1,165 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+use crate::{frontmatter::FrontMatter, markdown_inline_to_html, raw_title, template::TemplateMap};
+
+/// The front matter and title of a note, which is all that is needed to link to it.
+#[derive(Debug, Clone, Default)]
+pub struct NoteHeader {
+    pub front_matter: FrontMatter,
+    /// The `#` title line as written, without the leading `#`.
+    pub raw_title: Option<String>,
+    /// The title rendered to HTML.
+    pub title: Option<String>,
+}
+
+impl NoteHeader {
+    /// Reads a note up to and including its title line, and no further.
+    pub fn read<P: AsRef<Path>>(note: P) -> io::Result<Self> {
+        let note = note.as_ref();
+        let mut reader = BufReader::new(File::open(note)?);
+        let mut head = String::new();
+        reader.read_line(&mut head)?;
+        if head.trim_end_matches(['\n', '\r']) == "---" {
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    break;
+                }
+                head.push_str(&line);
+                if matches!(line.trim_end_matches(['\n', '\r']), "---" | "...") {
+                    reader.read_line(&mut head)?;
+                    break;
+                }
+            }
+        }
+        Ok(Self::parse(&head, note.parent()))
+    }
+
+    /// Reads the header from the start of a note's contents. The title is only rendered when
+    /// the note's directory is known.
+    pub fn parse(contents: &str, directory: Option<&Path>) -> Self {
+        let (front_matter, body, _) = FrontMatter::split(contents);
+        let raw_title = raw_title(body);
+        let title = match (&raw_title, directory) {
+            (Some(raw), Some(directory)) => render_title(raw, directory),
+            _ => None,
+        };
+        Self {
+            front_matter,
+            raw_title,
+            title,
+        }
+    }
+
+    /// Every name the note can be linked to by, besides its file name.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.front_matter.aliases();
+        if let Some(title) = &self.raw_title {
+            names.push(title.trim().to_string());
+        }
+        names
+    }
+}
+
+/// Note headers keyed by path, re-read only when a note's modification time changes.
+///
+/// Shared between the parser, which needs titles for link labels and resolution, and anything
+/// else indexing the vault.
+#[derive(Debug, Default)]
+pub struct TitleCache {
+    entries: RefCell<HashMap<PathBuf, (SystemTime, Rc<NoteHeader>)>>,
+}
+
+impl TitleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn header<P: AsRef<Path>>(&self, note: P) -> Option<Rc<NoteHeader>> {
+        let note = note.as_ref();
+        let modified = match note.metadata().and_then(|x| x.modified()) {
+            Ok(x) => x,
+            Err(_) => {
+                self.invalidate(note);
+                return None;
+            }
+        };
+        if let Some((cached_at, header)) = self.entries.borrow().get(note) {
+            if *cached_at == modified {
+                return Some(header.clone());
+            }
+        }
+        let header = Rc::new(NoteHeader::read(note).ok()?);
+        self.entries
+            .borrow_mut()
+            .insert(note.to_path_buf(), (modified, header.clone()));
+        Some(header)
+    }
+
+    pub fn title<P: AsRef<Path>>(&self, note: P) -> Option<String> {
+        self.header(note)?.title.clone()
+    }
+
+    pub fn invalidate<P: AsRef<Path>>(&self, note: P) {
+        self.entries.borrow_mut().remove(note.as_ref());
+    }
+
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+}
+
+fn render_title(raw: &str, directory: &Path) -> Option<String> {
+    match markdown_inline_to_html(raw, &TemplateMap::new(), directory) {
+        Ok(parsed) => Some(parsed.html),
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn headers_are_read_again_after_the_note_changes() {
+        let directory =
+            std::env::temp_dir().join(format!("confoosion-title-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let note = directory.join("note.md");
+        let then = SystemTime::now() - Duration::from_secs(100);
+        let write = |source: &str, modified: SystemTime| {
+            fs::write(&note, source).unwrap();
+            let file = File::options().write(true).open(&note).unwrap();
+            file.set_modified(modified).unwrap();
+        };
+        let cache = TitleCache::new();
+
+        write("---\nalias: First\n---\n# One\n", then);
+        assert_eq!(cache.title(&note).as_deref(), Some("One"));
+        assert_eq!(cache.header(&note).unwrap().names(), ["First", "One"]);
+
+        // The same time means the same note, until the cache is told otherwise.
+        write("# Two\n", then);
+        assert_eq!(cache.title(&note).as_deref(), Some("One"));
+        cache.invalidate(&note);
+        assert_eq!(cache.title(&note).as_deref(), Some("Two"));
+
+        write("# Three\n", then + Duration::from_secs(1));
+        assert_eq!(cache.title(&note).as_deref(), Some("Three"));
+
+        fs::remove_file(&note).unwrap();
+        assert!(cache.header(&note).is_none());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
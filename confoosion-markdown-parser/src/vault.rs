@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+use crate::{frontmatter::FrontMatter, resolver::notes_in, scan, template::TemplateMap};
+
+/// Read-only questions about the notes in a vault, for templates that list or count notes.
+///
+/// Every note is identified by its path. Results are sorted by path.
+pub trait VaultQuery {
+    fn notes(&self) -> Vec<PathBuf>;
+    fn front_matter(&self, note: &Path) -> FrontMatter;
+    /// The rendered `#` title of a note.
+    fn title(&self, note: &Path) -> Option<String>;
+    /// The existing notes that `note` links to.
+    fn links_from(&self, note: &Path) -> Vec<PathBuf>;
+    /// The notes that link to `note`.
+    fn backlinks(&self, note: &Path) -> Vec<PathBuf>;
+    /// The tags in a note's front matter and text, sorted.
+    fn tags(&self, note: &Path) -> Vec<String>;
+    /// The notes with `tag`, or a tag nested under it. See [`tag_matches`].
+    fn tagged(&self, tag: &str) -> Vec<PathBuf> {
+        self.notes()
+            .into_iter()
+            .filter(|note| self.tags(note).iter().any(|x| tag_matches(x, tag)))
+            .collect()
+    }
+    /// Every tag in use, sorted.
+    fn all_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .notes()
+            .iter()
+            .flat_map(|note| self.tags(note))
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+}
+
+/// Whether `tag` is `query` or nested under it, ignoring case, so that a query for `project`
+/// finds `#project/alpha` as well.
+pub fn tag_matches(tag: &str, query: &str) -> bool {
+    let tag = tag.to_lowercase();
+    let query = query.to_lowercase();
+    tag == query || tag.strip_prefix(&query).is_some_and(|x| x.starts_with('/'))
+}
+
+/// The tags of a note: those in its front matter and the `#tags` in its text, sorted and without
+/// duplicates.
+pub fn note_tags(source: &str) -> Vec<String> {
+    let (front_matter, body, _) = FrontMatter::split(source);
+    let mut tags = front_matter.tags();
+    tags.extend(scan::tags(body).into_iter().map(|x| x.tag));
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Answers vault queries by looking at the notes in one directory. Every query reads the
+/// directory again; keep an index instead when rendering many notes.
+pub struct DirectoryVault<'a> {
+    pub directory: PathBuf,
+    pub templates: &'a TemplateMap,
+}
+
+impl<'a> VaultQuery for DirectoryVault<'a> {
+    fn notes(&self) -> Vec<PathBuf> {
+        notes_in(&self.directory)
+    }
+
+    fn front_matter(&self, note: &Path) -> FrontMatter {
+        match self.templates.title_cache().header(note) {
+            Some(header) => header.front_matter.clone(),
+            None => FrontMatter::default(),
+        }
+    }
+
+    fn title(&self, note: &Path) -> Option<String> {
+        self.templates.title_cache().title(note)
+    }
+
+    fn links_from(&self, note: &Path) -> Vec<PathBuf> {
+        let source = match std::fs::read_to_string(note) {
+            Ok(x) => x,
+            Err(_) => return Vec::new(),
+        };
+        let (_, body, _) = FrontMatter::split(&source);
+        let mut out: Vec<PathBuf> = scan::wiki_links(body)
+            .into_iter()
+            .map(|link| {
+                self.templates
+                    .resolver()
+                    .resolve(&link.target, &self.directory, self.templates.title_cache())
+            })
+            .filter(|resolution| resolution.exists())
+            .map(|resolution| resolution.path().to_path_buf())
+            .collect();
+        out.sort();
+        out.dedup();
+        out
+    }
+
+    fn backlinks(&self, note: &Path) -> Vec<PathBuf> {
+        self.notes()
+            .into_iter()
+            .filter(|other| other != note && self.links_from(other).iter().any(|x| x == note))
+            .collect()
+    }
+
+    fn tags(&self, note: &Path) -> Vec<String> {
+        match std::fs::read_to_string(note) {
+            Ok(source) => note_tags(&source),
+            Err(_) => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{self, Command, Output, Stdio},
+};
+
+/// A directory of notes of its own for each test.
+fn vault(name: &str, notes: &[(&str, &str)]) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("confoosion-cli-{name}-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    for (note, source) in notes {
+        fs::write(directory.join(format!("{note}.md")), source).unwrap();
+    }
+    directory
+}
+
+/// Runs the parser in `directory` with `stdin` as its standard input.
+fn run(directory: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_confoosion-markdown-parser"))
+        .args(args)
+        .current_dir(directory)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn a_dash_reads_the_note_from_standard_input() {
+    let directory = vault("stdin", &[("here", "# Here\n")]);
+    let output = run(&directory, &["render", "-"], "[[here]] and **bold**\n");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let html = stdout(&output);
+    assert!(html.contains("<a href=\"here.html\">Here</a>"), "{html}");
+    assert!(html.contains("<b>bold</b>"), "{html}");
+
+    let output = run(&directory, &["links", "-"], "[[here]] [[gone]]");
+    let lines: Vec<String> = stdout(&output).lines().map(str::to_string).collect();
+    assert_eq!(
+        lines,
+        [
+            format!("missing\t{}", directory.join("gone.md").display()),
+            format!("found\t{}", directory.join("here.md").display()),
+        ]
+    );
+    let output = run(&directory, &["title", "-"], "# A *title*\n");
+    assert_eq!(stdout(&output), "A *title*\n");
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn output_goes_to_the_file_given_with_o() {
+    let directory = vault("output", &[("note", "# Note\n\n**text**\n")]);
+    let output = run(&directory, &["render", "note.md", "-o", "note.html"], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+    let html = fs::read_to_string(directory.join("note.html")).unwrap();
+    assert!(html.contains("<b>text</b>"), "{html}");
+
+    let output = run(&directory, &["render", "note.md", "-o"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("-o needs a file name\n"));
+    let output = run(
+        &directory,
+        &["render", "note.md", "-o", "missing/note.html"],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).starts_with("Could not write missing/note.html: "));
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn exit_codes_tell_problems_usage_and_files_apart() {
+    let directory = vault("codes", &[("a", "# A\n\nSee [[b]].\n"), ("b", "# B\n")]);
+    let code = |args: &[&str]| run(&directory, args, "").status.code();
+    assert_eq!(code(&["check", "."]), Some(0));
+    assert_eq!(code(&["--help"]), Some(0));
+    assert_eq!(code(&[]), Some(2));
+    assert_eq!(code(&["unknown"]), Some(2));
+    assert_eq!(code(&["check"]), Some(2));
+    assert_eq!(code(&["render", "a.md", "b.md"]), Some(2));
+    assert_eq!(code(&["render", "missing.md"]), Some(3));
+    assert_eq!(code(&["check", "missing"]), Some(3));
+
+    fs::write(directory.join("c.md"), "[[nowhere]]\n").unwrap();
+    let output = run(&directory, &["check", "."], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "./c.md: warning: broken link [[nowhere]]\n"
+    );
+    assert_eq!(code(&["title", "c.md"]), Some(1));
+    assert_eq!(code(&["render", "-"]), Some(0));
+
+    // Not UTF-8, so it cannot be read as a note.
+    fs::write(directory.join("d.md"), [0xff, 0xfe]).unwrap();
+    let output = run(&directory, &["check", "."], "");
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("./d.md: error: could not read: "),
+        "{stderr}"
+    );
+    assert!(
+        stderr.ends_with("Could not read 1 note(s) in .\n"),
+        "{stderr}"
+    );
+    fs::remove_dir_all(&directory).unwrap();
+}